@@ -0,0 +1,78 @@
+use std::cmp;
+use std::fmt;
+use std::time::Duration;
+
+/// A trait which determines how long the pool waits before retrying a failed
+/// connection attempt.
+pub trait Backoff: fmt::Debug + Send + Sync + 'static {
+    /// Returns the delay before the specified retry.
+    ///
+    /// `attempt` is 1 for the first retry after a failed connection attempt,
+    /// 2 for the second, and so on. It is reset once a connection is
+    /// successfully established.
+    fn delay(&self, attempt: u32) -> Duration;
+}
+
+/// A `Backoff` implementation which always waits the same amount of time.
+#[derive(Copy, Clone, Debug)]
+pub struct FixedBackoff(pub Duration);
+
+impl Backoff for FixedBackoff {
+    fn delay(&self, _: u32) -> Duration {
+        self.0
+    }
+}
+
+/// A `Backoff` implementation which doubles the delay after each failed
+/// attempt, up to a maximum.
+#[derive(Copy, Clone, Debug)]
+pub struct ExponentialBackoff {
+    initial: Duration,
+    max: Duration,
+    jitter: bool,
+}
+
+impl ExponentialBackoff {
+    /// Creates a new `ExponentialBackoff` which initially waits `initial`,
+    /// never waiting more than `max`.
+    pub fn new(initial: Duration, max: Duration) -> ExponentialBackoff {
+        ExponentialBackoff {
+            initial,
+            max,
+            jitter: false,
+        }
+    }
+
+    /// If true, each delay will be randomly reduced by up to half.
+    ///
+    /// This prevents many pools which lost their connections at the same time
+    /// from retrying in lockstep.
+    ///
+    /// Defaults to false.
+    pub fn jitter(mut self, jitter: bool) -> ExponentialBackoff {
+        self.jitter = jitter;
+        self
+    }
+}
+
+impl Backoff for ExponentialBackoff {
+    fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        let delay = self
+            .initial
+            .checked_mul(factor)
+            .map_or(self.max, |delay| cmp::min(delay, self.max));
+
+        if self.jitter {
+            let half = delay / 2;
+            let nanos = half.as_nanos() as u64;
+            if nanos > 0 {
+                return half + Duration::from_nanos(crate::random() % nanos);
+            }
+        }
+
+        delay
+    }
+}
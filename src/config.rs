@@ -1,30 +1,149 @@
 use scheduled_thread_pool::ScheduledThreadPool;
+use std::env;
 use std::fmt;
 use std::marker::PhantomData;
 use std::sync::Arc;
 use std::time::Duration;
 
 use crate::{
-    CustomizeConnection, Error, HandleError, HandleEvent, LoggingErrorHandler, ManageConnection,
-    NopConnectionCustomizer, NopEventHandler, Pool,
+    Backoff, CustomizeConnection, Error, ExponentialBackoff, HandleError, HandleEvent,
+    LoggingErrorHandler, ManageConnection, NopConnectionCustomizer, NopEventHandler, Pool,
+    StatsSink,
 };
 
+const DEFAULT_THREAD_NAME_PREFIX: &str = "r2d2-worker-";
+
+/// The order in which idle connections are handed out.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IdleStrategy {
+    /// Hand out the most recently returned connection.
+    ///
+    /// This keeps the set of connections in active use as small as possible,
+    /// which lets `idle_timeout` shrink the pool when load drops.
+    Lifo,
+    /// Hand out the connection which has been idle the longest.
+    ///
+    /// This spreads use evenly across all connections in the pool.
+    Fifo,
+    /// Hand out a randomly selected idle connection.
+    Random,
+}
+
+// checks settings which `Builder` and `Config` share, so that `update_config`
+// can't leave a running pool in a state `build` would reject
+macro_rules! validate {
+    ($config:expr) => {{
+        let config = &$config;
+        if config.max_size == 0 {
+            return Err(Error::config("max_size must be positive"));
+        }
+        if let Some(min_idle) = config.min_idle {
+            if min_idle > config.max_size {
+                return Err(Error::config("min_idle must be no larger than max_size"));
+            }
+        }
+        if let Some(warmup_count) = config.warmup_count {
+            if warmup_count > config.min_idle.unwrap_or(config.max_size) {
+                return Err(Error::config(
+                    "warmup_count must be no larger than min_idle",
+                ));
+            }
+        }
+        if config.max_lifetime == Some(Duration::from_secs(0)) {
+            return Err(Error::config("max_lifetime must be positive"));
+        }
+        if let (Some(max_lifetime), Some(jitter)) = (config.max_lifetime, config.lifetime_jitter) {
+            if jitter >= max_lifetime {
+                return Err(Error::config(
+                    "lifetime_jitter must be smaller than max_lifetime",
+                ));
+            }
+        }
+        if config.max_uses == Some(0) {
+            return Err(Error::config("max_uses must be positive"));
+        }
+        if config.max_concurrent_connects == Some(0) {
+            return Err(Error::config("max_concurrent_connects must be positive"));
+        }
+        if config.idle_timeout == Some(Duration::from_secs(0)) {
+            return Err(Error::config("idle_timeout must be positive"));
+        }
+        if config.test_while_idle == Some(Duration::from_secs(0)) {
+            return Err(Error::config("test_while_idle must be positive"));
+        }
+        if config.connection_timeout == Duration::from_secs(0) {
+            return Err(Error::config("connection_timeout must be positive"));
+        }
+        if config.wait_timeout == Some(Duration::from_secs(0)) {
+            return Err(Error::config("wait_timeout must be positive"));
+        }
+        if config.connect_timeout == Some(Duration::from_secs(0)) {
+            return Err(Error::config("connect_timeout must be positive"));
+        }
+        if config.hedge_delay == Some(Duration::from_secs(0)) {
+            return Err(Error::config("hedge_delay must be positive"));
+        }
+        if config.leak_detection_threshold == Some(Duration::from_secs(0)) {
+            return Err(Error::config("leak_detection_threshold must be positive"));
+        }
+        if config.slow_checkout_threshold == Some(Duration::from_secs(0)) {
+            return Err(Error::config("slow_checkout_threshold must be positive"));
+        }
+        if config.initialization_timeout == Some(Duration::from_secs(0)) {
+            return Err(Error::config("initialization_timeout must be positive"));
+        }
+        if config.maintenance_rate == Duration::from_secs(0) {
+            return Err(Error::config("maintenance_rate must be positive"));
+        }
+        if config.stats_interval == Duration::from_secs(0) {
+            return Err(Error::config("stats_interval must be positive"));
+        }
+        Ok(())
+    }};
+}
+
 /// A builder for a connection pool.
+///
+/// A `Builder` can be cloned to create several pools from a common base
+/// configuration. Handlers are shared between the clones.
 pub struct Builder<M>
 where
     M: ManageConnection,
 {
     max_size: u32,
     min_idle: Option<u32>,
+    warmup_count: Option<u32>,
     test_on_check_out: bool,
+    test_on_check_in: bool,
+    test_while_idle: Option<Duration>,
     max_lifetime: Option<Duration>,
+    lifetime_jitter: Option<Duration>,
+    max_uses: Option<u32>,
     idle_timeout: Option<Duration>,
     connection_timeout: Duration,
-    error_handler: Box<dyn HandleError<M::Error>>,
-    connection_customizer: Box<dyn CustomizeConnection<M::Connection, M::Error>>,
-    event_handler: Box<dyn HandleEvent>,
+    wait_timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    hedge_delay: Option<Duration>,
+    leak_detection_threshold: Option<Duration>,
+    slow_checkout_threshold: Option<Duration>,
+    initialization_timeout: Option<Duration>,
+    max_waiters: Option<u32>,
+    max_concurrent_connects: Option<u32>,
+    fair_queueing: bool,
+    background_disconnect: bool,
+    capture_backtraces: bool,
+    idle_strategy: IdleStrategy,
+    synchronous: bool,
+    backoff: Option<Arc<dyn Backoff>>,
+    error_handler: Arc<dyn HandleError<M::Error>>,
+    connection_customizer: Arc<dyn CustomizeConnection<M::Connection, M::Error>>,
+    event_handler: Arc<dyn HandleEvent>,
+    stats_sink: Option<Arc<dyn StatsSink>>,
     thread_pool: Option<Arc<ScheduledThreadPool>>,
-    reaper_rate: Duration,
+    thread_name_prefix: String,
+    pool_name: Option<Arc<str>>,
+    maintenance_rate: Duration,
+    stats_interval: Duration,
     _p: PhantomData<M>,
 }
 
@@ -36,17 +155,87 @@ where
         fmt.debug_struct("Builder")
             .field("max_size", &self.max_size)
             .field("min_idle", &self.min_idle)
+            .field("warmup_count", &self.warmup_count)
             .field("test_on_check_out", &self.test_on_check_out)
+            .field("test_on_check_in", &self.test_on_check_in)
+            .field("test_while_idle", &self.test_while_idle)
             .field("max_lifetime", &self.max_lifetime)
+            .field("lifetime_jitter", &self.lifetime_jitter)
+            .field("max_uses", &self.max_uses)
             .field("idle_timeout", &self.idle_timeout)
             .field("connection_timeout", &self.connection_timeout)
+            .field("wait_timeout", &self.wait_timeout)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("hedge_delay", &self.hedge_delay)
+            .field("leak_detection_threshold", &self.leak_detection_threshold)
+            .field("slow_checkout_threshold", &self.slow_checkout_threshold)
+            .field("initialization_timeout", &self.initialization_timeout)
+            .field("max_waiters", &self.max_waiters)
+            .field("max_concurrent_connects", &self.max_concurrent_connects)
+            .field("fair_queueing", &self.fair_queueing)
+            .field("background_disconnect", &self.background_disconnect)
+            .field("capture_backtraces", &self.capture_backtraces)
+            .field("idle_strategy", &self.idle_strategy)
+            .field("synchronous", &self.synchronous)
+            .field("backoff", &self.backoff)
             .field("error_handler", &self.error_handler)
             .field("event_handler", &self.event_handler)
+            .field("stats_sink", &self.stats_sink)
             .field("connection_customizer", &self.connection_customizer)
+            .field("thread_name_prefix", &self.thread_name_prefix)
+            .field("pool_name", &self.pool_name)
+            .field("maintenance_rate", &self.maintenance_rate)
+            .field("stats_interval", &self.stats_interval)
             .finish()
     }
 }
 
+// manual to avoid a bound on M
+impl<M> Clone for Builder<M>
+where
+    M: ManageConnection,
+{
+    fn clone(&self) -> Builder<M> {
+        Builder {
+            max_size: self.max_size,
+            min_idle: self.min_idle,
+            warmup_count: self.warmup_count,
+            test_on_check_out: self.test_on_check_out,
+            test_on_check_in: self.test_on_check_in,
+            test_while_idle: self.test_while_idle,
+            max_lifetime: self.max_lifetime,
+            lifetime_jitter: self.lifetime_jitter,
+            max_uses: self.max_uses,
+            idle_timeout: self.idle_timeout,
+            connection_timeout: self.connection_timeout,
+            wait_timeout: self.wait_timeout,
+            connect_timeout: self.connect_timeout,
+            hedge_delay: self.hedge_delay,
+            leak_detection_threshold: self.leak_detection_threshold,
+            slow_checkout_threshold: self.slow_checkout_threshold,
+            initialization_timeout: self.initialization_timeout,
+            max_waiters: self.max_waiters,
+            max_concurrent_connects: self.max_concurrent_connects,
+            fair_queueing: self.fair_queueing,
+            background_disconnect: self.background_disconnect,
+            capture_backtraces: self.capture_backtraces,
+            idle_strategy: self.idle_strategy,
+            synchronous: self.synchronous,
+            backoff: self.backoff.clone(),
+            error_handler: self.error_handler.clone(),
+            connection_customizer: self.connection_customizer.clone(),
+            event_handler: self.event_handler.clone(),
+            stats_sink: self.stats_sink.clone(),
+            thread_pool: self.thread_pool.clone(),
+            thread_name_prefix: self.thread_name_prefix.clone(),
+            pool_name: self.pool_name.clone(),
+            maintenance_rate: self.maintenance_rate,
+            stats_interval: self.stats_interval,
+            _p: PhantomData,
+        }
+    }
+}
+
 impl<M> Default for Builder<M>
 where
     M: ManageConnection,
@@ -55,15 +244,38 @@ where
         Builder {
             max_size: 10,
             min_idle: None,
+            warmup_count: None,
             test_on_check_out: true,
+            test_on_check_in: false,
+            test_while_idle: None,
             idle_timeout: Some(Duration::from_secs(10 * 60)),
             max_lifetime: Some(Duration::from_secs(30 * 60)),
+            lifetime_jitter: None,
+            max_uses: None,
             connection_timeout: Duration::from_secs(30),
-            error_handler: Box::new(LoggingErrorHandler),
-            event_handler: Box::new(NopEventHandler),
-            connection_customizer: Box::new(NopConnectionCustomizer),
+            wait_timeout: None,
+            connect_timeout: None,
+            hedge_delay: None,
+            leak_detection_threshold: None,
+            slow_checkout_threshold: None,
+            initialization_timeout: None,
+            max_waiters: None,
+            max_concurrent_connects: None,
+            fair_queueing: false,
+            background_disconnect: false,
+            capture_backtraces: false,
+            idle_strategy: IdleStrategy::Lifo,
+            synchronous: false,
+            backoff: None,
+            error_handler: Arc::new(LoggingErrorHandler),
+            event_handler: Arc::new(NopEventHandler),
+            stats_sink: None,
+            connection_customizer: Arc::new(NopConnectionCustomizer),
             thread_pool: None,
-            reaper_rate: Duration::from_secs(30),
+            thread_name_prefix: DEFAULT_THREAD_NAME_PREFIX.to_string(),
+            pool_name: None,
+            maintenance_rate: Duration::from_secs(30),
+            stats_interval: Duration::from_secs(10),
             _p: PhantomData,
         }
     }
@@ -80,15 +292,134 @@ where
         Builder::default()
     }
 
-    /// Sets the maximum number of connections managed by the pool.
+    /// Constructs a new `Builder` configured from environment variables.
     ///
-    /// Defaults to 10.
+    /// Each setting is read from a variable named by `prefix`, an underscore,
+    /// and the name of the setting in upper case, for example
+    /// `R2D2_MAX_SIZE` or `R2D2_CONNECTION_TIMEOUT` for a prefix of `R2D2`.
+    /// The supported settings are `MAX_SIZE`, `MIN_IDLE`, `WARMUP_COUNT`,
+    /// `TEST_ON_CHECK_OUT`, `TEST_ON_CHECK_IN`, `TEST_WHILE_IDLE`,
+    /// `MAX_LIFETIME`, `LIFETIME_JITTER`, `MAX_USES`, `IDLE_TIMEOUT`,
+    /// `CONNECTION_TIMEOUT`, `WAIT_TIMEOUT`, `CONNECT_TIMEOUT`, `HEDGE_DELAY`,
+    /// `LEAK_DETECTION_THRESHOLD`, `SLOW_CHECKOUT_THRESHOLD`,
+    /// `INITIALIZATION_TIMEOUT`, `MAX_WAITERS`, `MAX_CONCURRENT_CONNECTS`,
+    /// `FAIR_QUEUEING`, `BACKGROUND_DISCONNECT`, `CAPTURE_BACKTRACES`,
+    /// `IDLE_STRATEGY`, `SYNCHRONOUS`, `MAINTENANCE_RATE`, `STATS_INTERVAL`,
+    /// `THREAD_NAME_PREFIX`, and `POOL_NAME`. Settings whose variable is not
+    /// set keep their default values.
     ///
-    /// # Panics
+    /// Durations are written as an integer followed by an optional unit of
+    /// `ms`, `s`, `m`, or `h`, defaulting to seconds. Optional settings can be
+    /// cleared with the value `none`. Booleans are `true` or `false`, and the
+    /// idle strategy is one of `lifo`, `fifo`, or `random`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a variable is set to a value which cannot be
+    /// parsed.
+    pub fn from_env(prefix: &str) -> Result<Builder<M>, Error> {
+        let mut builder = Builder::new();
+
+        if let Some(v) = env_var(prefix, "MAX_SIZE", |v| v.parse().ok())? {
+            builder.max_size = v;
+        }
+        if let Some(v) = env_var(prefix, "MIN_IDLE", |v| optional(v, |v| v.parse().ok()))? {
+            builder.min_idle = v;
+        }
+        if let Some(v) = env_var(prefix, "WARMUP_COUNT", |v| optional(v, |v| v.parse().ok()))? {
+            builder.warmup_count = v;
+        }
+        if let Some(v) = env_var(prefix, "TEST_ON_CHECK_OUT", |v| v.parse().ok())? {
+            builder.test_on_check_out = v;
+        }
+        if let Some(v) = env_var(prefix, "TEST_ON_CHECK_IN", |v| v.parse().ok())? {
+            builder.test_on_check_in = v;
+        }
+        if let Some(v) = env_var(prefix, "TEST_WHILE_IDLE", |v| optional(v, parse_duration))? {
+            builder.test_while_idle = v;
+        }
+        if let Some(v) = env_var(prefix, "MAX_LIFETIME", |v| optional(v, parse_duration))? {
+            builder.max_lifetime = v;
+        }
+        if let Some(v) = env_var(prefix, "LIFETIME_JITTER", |v| optional(v, parse_duration))? {
+            builder.lifetime_jitter = v;
+        }
+        if let Some(v) = env_var(prefix, "MAX_USES", |v| optional(v, |v| v.parse().ok()))? {
+            builder.max_uses = v;
+        }
+        if let Some(v) = env_var(prefix, "IDLE_TIMEOUT", |v| optional(v, parse_duration))? {
+            builder.idle_timeout = v;
+        }
+        if let Some(v) = env_var(prefix, "CONNECTION_TIMEOUT", parse_duration)? {
+            builder.connection_timeout = v;
+        }
+        if let Some(v) = env_var(prefix, "WAIT_TIMEOUT", |v| optional(v, parse_duration))? {
+            builder.wait_timeout = v;
+        }
+        if let Some(v) = env_var(prefix, "CONNECT_TIMEOUT", |v| optional(v, parse_duration))? {
+            builder.connect_timeout = v;
+        }
+        if let Some(v) = env_var(prefix, "HEDGE_DELAY", |v| optional(v, parse_duration))? {
+            builder.hedge_delay = v;
+        }
+        if let Some(v) = env_var(prefix, "LEAK_DETECTION_THRESHOLD", |v| {
+            optional(v, parse_duration)
+        })? {
+            builder.leak_detection_threshold = v;
+        }
+        if let Some(v) = env_var(prefix, "SLOW_CHECKOUT_THRESHOLD", |v| {
+            optional(v, parse_duration)
+        })? {
+            builder.slow_checkout_threshold = v;
+        }
+        if let Some(v) = env_var(prefix, "INITIALIZATION_TIMEOUT", |v| {
+            optional(v, parse_duration)
+        })? {
+            builder.initialization_timeout = v;
+        }
+        if let Some(v) = env_var(prefix, "MAX_WAITERS", |v| optional(v, |v| v.parse().ok()))? {
+            builder.max_waiters = v;
+        }
+        if let Some(v) = env_var(prefix, "MAX_CONCURRENT_CONNECTS", |v| {
+            optional(v, |v| v.parse().ok())
+        })? {
+            builder.max_concurrent_connects = v;
+        }
+        if let Some(v) = env_var(prefix, "FAIR_QUEUEING", |v| v.parse().ok())? {
+            builder.fair_queueing = v;
+        }
+        if let Some(v) = env_var(prefix, "BACKGROUND_DISCONNECT", |v| v.parse().ok())? {
+            builder.background_disconnect = v;
+        }
+        if let Some(v) = env_var(prefix, "CAPTURE_BACKTRACES", |v| v.parse().ok())? {
+            builder.capture_backtraces = v;
+        }
+        if let Some(v) = env_var(prefix, "IDLE_STRATEGY", parse_idle_strategy)? {
+            builder.idle_strategy = v;
+        }
+        if let Some(v) = env_var(prefix, "SYNCHRONOUS", |v| v.parse().ok())? {
+            builder.synchronous = v;
+        }
+        if let Some(v) = env_var(prefix, "MAINTENANCE_RATE", parse_duration)? {
+            builder.maintenance_rate = v;
+        }
+        if let Some(v) = env_var(prefix, "STATS_INTERVAL", parse_duration)? {
+            builder.stats_interval = v;
+        }
+        if let Some(v) = env_var(prefix, "THREAD_NAME_PREFIX", |v| Some(v.to_string()))? {
+            builder.thread_name_prefix = v;
+        }
+        if let Some(v) = env_var(prefix, "POOL_NAME", |v| Some(Arc::from(v)))? {
+            builder.pool_name = Some(v);
+        }
+
+        Ok(builder)
+    }
+
+    /// Sets the maximum number of connections managed by the pool.
     ///
-    /// Panics if `max_size` is 0.
+    /// Defaults to 10.
     pub fn max_size(mut self, max_size: u32) -> Builder<M> {
-        assert!(max_size > 0, "max_size must be positive");
         self.max_size = max_size;
         self
     }
@@ -104,15 +435,54 @@ where
         self
     }
 
+    /// Sets the number of connections `build` waits for before returning.
+    ///
+    /// If set, `build` will only block until this many connections have been
+    /// established, possibly zero, and the pool will open the rest of its
+    /// `min_idle` connections in the background.
+    ///
+    /// Defaults to `None` (equivalent to the value of `min_idle`).
+    pub fn warmup_count(mut self, warmup_count: Option<u32>) -> Builder<M> {
+        self.warmup_count = warmup_count;
+        self
+    }
+
     /// Sets the thread pool used for asynchronous operations such as connection
     /// creation.
     ///
+    /// This can't be combined with `synchronous`.
+    ///
     /// Defaults to a new pool with 3 threads.
     pub fn thread_pool(mut self, thread_pool: Arc<ScheduledThreadPool>) -> Builder<M> {
         self.thread_pool = Some(thread_pool);
         self
     }
 
+    /// Sets the prefix used to name the threads of the pool's default thread
+    /// pool.
+    ///
+    /// Each thread's name is the prefix followed by its index. This has no
+    /// effect if a thread pool is provided via `thread_pool`.
+    ///
+    /// Defaults to `"r2d2-worker-"`, or `"r2d2-{pool_name}-worker-"` if the
+    /// pool is named.
+    pub fn thread_name_prefix(mut self, thread_name_prefix: String) -> Builder<M> {
+        self.thread_name_prefix = thread_name_prefix;
+        self
+    }
+
+    /// Sets the name of the pool.
+    ///
+    /// The name is included in the pool's events, log messages, statistics
+    /// snapshots, and the names of its threads, which tells pools apart in a
+    /// process with more than one.
+    ///
+    /// Defaults to `None`.
+    pub fn pool_name(mut self, pool_name: String) -> Builder<M> {
+        self.pool_name = Some(Arc::from(pool_name));
+        self
+    }
+
     /// If true, the health of a connection will be verified via a call to
     /// `ConnectionManager::is_valid` before it is checked out of the pool.
     ///
@@ -122,37 +492,83 @@ where
         self
     }
 
+    /// If true, the health of a connection will be verified via a call to
+    /// `ManageConnection::is_valid` when it is returned to the pool.
+    ///
+    /// Connections which fail the check are closed rather than being handed
+    /// out to the next caller.
+    ///
+    /// Defaults to false.
+    pub fn test_on_check_in(mut self, test_on_check_in: bool) -> Builder<M> {
+        self.test_on_check_in = test_on_check_in;
+        self
+    }
+
+    /// Sets the interval at which idle connections are tested.
+    ///
+    /// If set, the pool will periodically verify the health of its idle
+    /// connections via a call to `ManageConnection::deep_check` from its helper
+    /// threads, closing those that fail. This detects dead connections during
+    /// quiet periods rather than when they are next checked out.
+    ///
+    /// Defaults to `None`.
+    pub fn test_while_idle(mut self, test_while_idle: Option<Duration>) -> Builder<M> {
+        self.test_while_idle = test_while_idle;
+        self
+    }
+
     /// Sets the maximum lifetime of connections in the pool.
     ///
-    /// If set, connections will be closed after existing for at most 30 seconds
-    /// beyond this duration.
+    /// If set, connections will be closed after existing for at most one
+    /// maintenance interval beyond this duration.
     ///
     /// If a connection reaches its maximum lifetime while checked out it will
     /// be closed when it is returned to the pool.
     ///
     /// Defaults to 30 minutes.
-    ///
-    /// # Panics
-    ///
-    /// Panics if `max_lifetime` is the zero `Duration`.
     pub fn max_lifetime(mut self, max_lifetime: Option<Duration>) -> Builder<M> {
-        assert_ne!(max_lifetime, Some(Duration::from_secs(0)), "max_lifetime must be positive");
         self.max_lifetime = max_lifetime;
         self
     }
 
-    /// Sets the idle timeout used by the pool.
+    /// Sets the maximum amount by which each connection's lifetime is randomly
+    /// shortened.
     ///
-    /// If set, connections will be closed after sitting idle for at most 30
-    /// seconds beyond this duration.
+    /// If set, each connection is closed after a lifetime chosen at random
+    /// between `max_lifetime` minus this jitter and `max_lifetime`. This
+    /// spreads out the replacement of connections which were all opened at
+    /// the same time, such as when the pool starts up.
     ///
-    /// Defaults to 10 minutes.
+    /// Defaults to `None`.
+    pub fn lifetime_jitter(mut self, lifetime_jitter: Option<Duration>) -> Builder<M> {
+        self.lifetime_jitter = lifetime_jitter;
+        self
+    }
+
+    /// Sets the maximum number of times a connection will be checked out.
     ///
-    /// # Panics
+    /// If set, a connection will be closed when it is returned to the pool
+    /// after being checked out this many times. This can be used to recycle
+    /// connections which accumulate per-session state over their lifetime.
+    ///
+    /// Defaults to `None`.
+    pub fn max_uses(mut self, max_uses: Option<u32>) -> Builder<M> {
+        self.max_uses = max_uses;
+        self
+    }
+
+    /// Sets the idle timeout used by the pool.
     ///
-    /// Panics if `idle_timeout` is the zero `Duration`.
+    /// If set, connections will be closed after sitting idle for at most one
+    /// maintenance interval beyond this duration.
+    ///
+    /// Closed connections are only replaced as needed to maintain `min_idle`,
+    /// so the pool will shrink back down to that size when traffic drops. If
+    /// `min_idle` is not set, the pool will instead replace every reaped
+    /// connection.
+    ///
+    /// Defaults to 10 minutes.
     pub fn idle_timeout(mut self, idle_timeout: Option<Duration>) -> Builder<M> {
-        assert_ne!(idle_timeout, Some(Duration::from_secs(0)), "idle_timeout must be positive");
         self.idle_timeout = idle_timeout;
         self
     }
@@ -160,27 +576,215 @@ where
     /// Sets the connection timeout used by the pool.
     ///
     /// Calls to `Pool::get` will wait this long for a connection to become
-    /// available before returning an error.
+    /// available before returning an error, unless `wait_timeout` is set. It
+    /// is also the default for `initialization_timeout`.
     ///
     /// Defaults to 30 seconds.
-    ///
-    /// # Panics
-    ///
-    /// Panics if `connection_timeout` is the zero duration
     pub fn connection_timeout(mut self, connection_timeout: Duration) -> Builder<M> {
-        assert!(
-            connection_timeout > Duration::from_secs(0),
-            "connection_timeout must be positive"
-        );
         self.connection_timeout = connection_timeout;
         self
     }
 
+    /// Sets the wait timeout used by the pool.
+    ///
+    /// Calls to `Pool::get` will wait this long for a connection to become
+    /// available before returning an error. This bounds the time spent
+    /// waiting in the pool rather than the time taken to open an individual
+    /// connection, which is controlled by `connect_timeout`.
+    ///
+    /// Defaults to `None` (equivalent to the value of `connection_timeout`).
+    pub fn wait_timeout(mut self, wait_timeout: Option<Duration>) -> Builder<M> {
+        self.wait_timeout = wait_timeout;
+        self
+    }
+
+    /// Sets the connect timeout used by the pool.
+    ///
+    /// If set, a new connection which took longer than this to establish is
+    /// discarded and the attempt is treated as a failure, to be retried
+    /// according to the pool's backoff policy, or reported to the caller of
+    /// a synchronous pool. This allows slow connection handshakes to be
+    /// rejected independently of `wait_timeout`.
+    ///
+    /// The pool can't interrupt a connection attempt, so the timeout is only
+    /// checked once `ManageConnection::connect` returns. Managers which can
+    /// bound their own handshakes should use `ConnectContext::deadline`.
+    ///
+    /// Defaults to `None`.
+    pub fn connect_timeout(mut self, connect_timeout: Option<Duration>) -> Builder<M> {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// Sets the hedge delay used by the pool.
+    ///
+    /// If set, a connection attempt which hasn't finished after this long is
+    /// raced against a second attempt. Whichever connection is established
+    /// first is kept, and the other is closed. This reduces the tail latency
+    /// of opening connections when some attempts stall. Hedging only applies
+    /// to connections opened by the pool's helper threads.
+    ///
+    /// Defaults to `None`.
+    pub fn hedge_delay(mut self, hedge_delay: Option<Duration>) -> Builder<M> {
+        self.hedge_delay = hedge_delay;
+        self
+    }
+
+    /// Sets the leak detection threshold used by the pool.
+    ///
+    /// If set, a connection which has been checked out for longer than this
+    /// is reported as a possible leak through the pool's event handler and
+    /// event subscribers, once per checkout. If backtraces are enabled through
+    /// the `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` environment variables, the
+    /// report includes the backtrace of the checkout. Leaks are detected by
+    /// the pool's helper threads every `maintenance_rate`.
+    ///
+    /// Defaults to `None`.
+    pub fn leak_detection_threshold(
+        mut self,
+        leak_detection_threshold: Option<Duration>,
+    ) -> Builder<M> {
+        self.leak_detection_threshold = leak_detection_threshold;
+        self
+    }
+
+    /// Sets the slow checkout threshold used by the pool.
+    ///
+    /// If set, a successful checkout which waited longer than this for a
+    /// connection is reported through the pool's event handler and event
+    /// subscribers along with the state of the pool at the time.
+    ///
+    /// Defaults to `None`.
+    pub fn slow_checkout_threshold(
+        mut self,
+        slow_checkout_threshold: Option<Duration>,
+    ) -> Builder<M> {
+        self.slow_checkout_threshold = slow_checkout_threshold;
+        self
+    }
+
+    /// Sets the initialization timeout used by the pool.
+    ///
+    /// `build` will wait this long for the pool to establish its warm-up
+    /// connections before returning an error.
+    ///
+    /// Defaults to `None` (equivalent to the value of `connection_timeout`).
+    pub fn initialization_timeout(
+        mut self,
+        initialization_timeout: Option<Duration>,
+    ) -> Builder<M> {
+        self.initialization_timeout = initialization_timeout;
+        self
+    }
+
+    /// Sets the maximum number of callers which may wait for a connection at
+    /// once.
+    ///
+    /// If set, calls to `Pool::get` which cannot immediately be satisfied
+    /// while this many other callers are already waiting will fail
+    /// immediately rather than waiting for the wait timeout.
+    ///
+    /// Defaults to `None`.
+    pub fn max_waiters(mut self, max_waiters: Option<u32>) -> Builder<M> {
+        self.max_waiters = max_waiters;
+        self
+    }
+
+    /// Sets the maximum number of connections which may be in the process of
+    /// being opened at once.
+    ///
+    /// This avoids overwhelming a database which limits the rate of new
+    /// connections when a large pool starts up. Other background connection
+    /// attempts are started as the in-progress ones finish rather than holding
+    /// up the pool's threads, and a caller of a synchronous pool waits for one
+    /// of the in-progress attempts to finish no longer than its own timeout.
+    ///
+    /// Defaults to `None`.
+    pub fn max_concurrent_connects(mut self, max_concurrent_connects: Option<u32>) -> Builder<M> {
+        self.max_concurrent_connects = max_concurrent_connects;
+        self
+    }
+
+    /// If true, the pool will not use any background threads.
+    ///
+    /// Connections are instead opened on the thread calling `Pool::get` when
+    /// no idle connection is available, and a failure to connect is reported
+    /// immediately, along with the manager's error, rather than retried.
+    /// Connections are only checked against `max_lifetime` when they are
+    /// returned, and `idle_timeout`, `test_while_idle` and `min_idle` have no
+    /// effect after the pool is built. This mode is intended for deterministic
+    /// tests, and can't be combined with `thread_pool`.
+    ///
+    /// Defaults to false.
+    pub fn synchronous(mut self, synchronous: bool) -> Builder<M> {
+        self.synchronous = synchronous;
+        self
+    }
+
+    /// If true, connections will be handed out to waiting callers in the order
+    /// in which they started waiting.
+    ///
+    /// By default, a returned connection goes to whichever waiting caller
+    /// happens to wake up first, which under heavy contention can leave some
+    /// callers waiting much longer than others. In fair mode, a caller will
+    /// also not take an idle connection while others are still waiting.
+    ///
+    /// Defaults to false.
+    pub fn fair_queueing(mut self, fair_queueing: bool) -> Builder<M> {
+        self.fair_queueing = fair_queueing;
+        self
+    }
+
+    /// If true, connections will be disconnected on the pool's helper threads.
+    ///
+    /// By default, `ManageConnection::disconnect` is called and the connection
+    /// dropped on whichever thread caused it to be closed, which may be a
+    /// caller returning a connection to the pool. In background mode, callers
+    /// do not wait for a slow close handshake. This has no effect if the pool
+    /// is synchronous.
+    ///
+    /// Defaults to false.
+    pub fn background_disconnect(mut self, background_disconnect: bool) -> Builder<M> {
+        self.background_disconnect = background_disconnect;
+        self
+    }
+
+    /// If true, the backtrace of every checkout is captured.
+    ///
+    /// The backtraces of checked out connections are included in leak events,
+    /// timeout events, and `Pool::checked_out`, which makes it possible to
+    /// find the code holding on to connections when the pool is exhausted.
+    /// Capturing a backtrace is expensive, so this is intended for debugging.
+    ///
+    /// Defaults to false.
+    pub fn capture_backtraces(mut self, capture_backtraces: bool) -> Builder<M> {
+        self.capture_backtraces = capture_backtraces;
+        self
+    }
+
+    /// Sets the strategy used to pick which idle connection is handed out.
+    ///
+    /// Defaults to `IdleStrategy::Lifo`.
+    pub fn idle_strategy(mut self, idle_strategy: IdleStrategy) -> Builder<M> {
+        self.idle_strategy = idle_strategy;
+        self
+    }
+
+    /// Sets the policy used to space out retries when the pool fails to open a
+    /// new connection.
+    ///
+    /// Defaults to an `ExponentialBackoff` starting at 400 milliseconds and
+    /// limited to half of the connection timeout.
+    pub fn backoff(mut self, backoff: Box<dyn Backoff>) -> Builder<M> {
+        self.backoff = Some(Arc::from(backoff));
+        self
+    }
+
     /// Sets the handler for errors reported in the pool.
     ///
     /// Defaults to the `LoggingErrorHandler`.
     pub fn error_handler(mut self, error_handler: Box<dyn HandleError<M::Error>>) -> Builder<M> {
-        self.error_handler = error_handler;
+        self.error_handler = Arc::from(error_handler);
         self
     }
 
@@ -188,7 +792,27 @@ where
     ///
     /// Defaults to the `NopEventHandler`.
     pub fn event_handler(mut self, event_handler: Box<dyn HandleEvent>) -> Builder<M> {
-        self.event_handler = event_handler;
+        self.event_handler = Arc::from(event_handler);
+        self
+    }
+
+    /// Sets a sink which is sent a snapshot of the pool's statistics every
+    /// `stats_interval`.
+    ///
+    /// Snapshots are sent from the pool's helper threads, so none are sent by
+    /// a synchronous pool.
+    ///
+    /// Defaults to `None`.
+    pub fn stats_sink(mut self, stats_sink: Box<dyn StatsSink>) -> Builder<M> {
+        self.stats_sink = Some(Arc::from(stats_sink));
+        self
+    }
+
+    /// Sets the interval at which snapshots are sent to the `stats_sink`.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn stats_interval(mut self, stats_interval: Duration) -> Builder<M> {
+        self.stats_interval = stats_interval;
         self
     }
 
@@ -199,32 +823,37 @@ where
         mut self,
         connection_customizer: Box<dyn CustomizeConnection<M::Connection, M::Error>>,
     ) -> Builder<M> {
-        self.connection_customizer = connection_customizer;
+        self.connection_customizer = Arc::from(connection_customizer);
         self
     }
 
-    // used by tests
-    #[allow(dead_code)]
-    pub(crate) fn reaper_rate(mut self, reaper_rate: Duration) -> Builder<M> {
-        self.reaper_rate = reaper_rate;
+    /// Sets the rate at which the pool performs background maintenance.
+    ///
+    /// Each maintenance pass closes connections which have exceeded
+    /// `max_lifetime` or `idle_timeout`, and opens new connections as needed
+    /// to maintain `min_idle`. It runs on the pool's helper threads, so a
+    /// synchronous pool performs none.
+    ///
+    /// Idle connection testing is scheduled separately by `test_while_idle`.
+    ///
+    /// Defaults to 30 seconds.
+    pub fn maintenance_rate(mut self, maintenance_rate: Duration) -> Builder<M> {
+        self.maintenance_rate = maintenance_rate;
         self
     }
 
     /// Consumes the builder, returning a new, initialized pool.
     ///
-    /// It will block until the pool has established its configured minimum
-    /// number of connections, or it times out.
+    /// It will block until the pool has established its configured warm-up
+    /// number of connections (by default `min_idle`), or it times out.
     ///
     /// # Errors
     ///
-    /// Returns an error if the pool is unable to open its minimum number of
-    /// connections.
-    ///
-    /// # Panics
-    ///
-    /// Panics if `min_idle` is greater than `max_size`.
+    /// Returns an error if the configuration is invalid, or if the pool is
+    /// unable to open its minimum number of connections.
     pub fn build(self, manager: M) -> Result<Pool<M>, Error> {
-        let pool = self.build_unchecked(manager);
+        self.validate()?;
+        let pool = self.build_inner(manager);
         pool.wait_for_initialization()?;
         Ok(pool)
     }
@@ -236,48 +865,331 @@ where
     ///
     /// # Panics
     ///
-    /// Panics if `min_idle` is greater than `max_size`.
+    /// Panics if the configuration is invalid.
     pub fn build_unchecked(self, manager: M) -> Pool<M> {
-        if let Some(min_idle) = self.min_idle {
-            assert!(
-                self.max_size >= min_idle,
-                "min_idle must be no larger than max_size"
-            );
+        if let Err(e) = self.validate() {
+            panic!("{}", e);
+        }
+        self.build_inner(manager)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.synchronous && self.thread_pool.is_some() {
+            return Err(Error::config(
+                "thread_pool can't be set for a synchronous pool",
+            ));
         }
+        validate!(self)
+    }
 
+    fn build_inner(self, manager: M) -> Pool<M> {
         let thread_pool = match self.thread_pool {
-            Some(thread_pool) => thread_pool,
-            None => Arc::new(ScheduledThreadPool::with_name("r2d2-worker-{}", 3)),
+            _ if self.synchronous => None,
+            Some(thread_pool) => Some(thread_pool),
+            None => {
+                let pattern = match self.pool_name {
+                    Some(ref name) if self.thread_name_prefix == DEFAULT_THREAD_NAME_PREFIX => {
+                        format!("r2d2-{}-worker-{{}}", name)
+                    }
+                    _ => format!("{}{{}}", self.thread_name_prefix),
+                };
+                Some(Arc::new(
+                    ScheduledThreadPool::builder()
+                        .num_threads(3)
+                        .thread_name_pattern(&pattern)
+                        .build(),
+                ))
+            }
         };
 
+        let default_backoff = self.backoff.is_none();
+        let connection_timeout = self.connection_timeout;
+        let backoff = self
+            .backoff
+            .unwrap_or_else(|| self::default_backoff(connection_timeout));
+
         let config = Config {
             max_size: self.max_size,
             min_idle: self.min_idle,
+            warmup_count: self.warmup_count,
             test_on_check_out: self.test_on_check_out,
+            test_on_check_in: self.test_on_check_in,
+            test_while_idle: self.test_while_idle,
             max_lifetime: self.max_lifetime,
+            lifetime_jitter: self.lifetime_jitter,
+            max_uses: self.max_uses,
             idle_timeout: self.idle_timeout,
             connection_timeout: self.connection_timeout,
+            wait_timeout: self.wait_timeout,
+            connect_timeout: self.connect_timeout,
+            hedge_delay: self.hedge_delay,
+            leak_detection_threshold: self.leak_detection_threshold,
+            slow_checkout_threshold: self.slow_checkout_threshold,
+            initialization_timeout: self.initialization_timeout,
+            max_waiters: self.max_waiters,
+            max_concurrent_connects: self.max_concurrent_connects,
+            fair_queueing: self.fair_queueing,
+            background_disconnect: self.background_disconnect,
+            capture_backtraces: self.capture_backtraces,
+            idle_strategy: self.idle_strategy,
+            synchronous: self.synchronous,
+            backoff,
+            default_backoff,
             error_handler: self.error_handler,
             event_handler: self.event_handler,
+            stats_sink: self.stats_sink,
             connection_customizer: self.connection_customizer,
             thread_pool,
+            maintenance_rate: self.maintenance_rate,
+            stats_interval: self.stats_interval,
+            pool_name: self.pool_name,
         };
 
-        Pool::new_inner(config, manager, self.reaper_rate)
+        Pool::new_inner(config, manager)
+    }
+}
+
+/// A set of changes to apply to a running pool's configuration.
+///
+/// Settings which are not specified keep their current values. Settings
+/// which schedule background work, such as `test_while_idle` and the stats
+/// sink, can only be configured when the pool is built. See
+/// `Pool::update_config`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ConfigUpdate {
+    max_size: Option<u32>,
+    min_idle: Option<Option<u32>>,
+    test_on_check_out: Option<bool>,
+    max_lifetime: Option<Option<Duration>>,
+    idle_timeout: Option<Option<Duration>>,
+    connection_timeout: Option<Duration>,
+    wait_timeout: Option<Option<Duration>>,
+}
+
+impl ConfigUpdate {
+    /// Constructs a new `ConfigUpdate` which does not change any settings.
+    pub fn new() -> ConfigUpdate {
+        ConfigUpdate::default()
+    }
+
+    /// Changes the maximum number of connections managed by the pool.
+    ///
+    /// If the pool currently has more connections than the new maximum, idle
+    /// connections are closed immediately and checked out connections are
+    /// closed as they are returned.
+    pub fn max_size(mut self, max_size: u32) -> ConfigUpdate {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Changes the minimum idle connection count maintained by the pool.
+    pub fn min_idle(mut self, min_idle: Option<u32>) -> ConfigUpdate {
+        self.min_idle = Some(min_idle);
+        self
+    }
+
+    /// Changes whether connections are tested on check out.
+    pub fn test_on_check_out(mut self, test_on_check_out: bool) -> ConfigUpdate {
+        self.test_on_check_out = Some(test_on_check_out);
+        self
+    }
+
+    /// Changes the maximum lifetime of connections in the pool.
+    pub fn max_lifetime(mut self, max_lifetime: Option<Duration>) -> ConfigUpdate {
+        self.max_lifetime = Some(max_lifetime);
+        self
+    }
+
+    /// Changes the idle timeout used by the pool.
+    pub fn idle_timeout(mut self, idle_timeout: Option<Duration>) -> ConfigUpdate {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// Changes the connection timeout used by `Pool::get`.
+    ///
+    /// If the pool uses the default backoff policy, its maximum delay is
+    /// adjusted to match the new timeout.
+    pub fn connection_timeout(mut self, connection_timeout: Duration) -> ConfigUpdate {
+        self.connection_timeout = Some(connection_timeout);
+        self
+    }
+
+    /// Changes the wait timeout used by `Pool::get`.
+    pub fn wait_timeout(mut self, wait_timeout: Option<Duration>) -> ConfigUpdate {
+        self.wait_timeout = Some(wait_timeout);
+        self
+    }
+
+    pub(crate) fn apply<C, E>(&self, config: &mut Config<C, E>) -> Result<(), Error> {
+        if let Some(max_size) = self.max_size {
+            config.max_size = max_size;
+        }
+        if let Some(min_idle) = self.min_idle {
+            config.min_idle = min_idle;
+        }
+        if let Some(test_on_check_out) = self.test_on_check_out {
+            config.test_on_check_out = test_on_check_out;
+        }
+        if let Some(max_lifetime) = self.max_lifetime {
+            config.max_lifetime = max_lifetime;
+        }
+        if let Some(idle_timeout) = self.idle_timeout {
+            config.idle_timeout = idle_timeout;
+        }
+        if let Some(connection_timeout) = self.connection_timeout {
+            config.connection_timeout = connection_timeout;
+            if config.default_backoff {
+                config.backoff = default_backoff(connection_timeout);
+            }
+        }
+        if let Some(wait_timeout) = self.wait_timeout {
+            config.wait_timeout = wait_timeout;
+        }
+
+        validate!(config)
+    }
+}
+
+fn default_backoff(connection_timeout: Duration) -> Arc<dyn Backoff> {
+    Arc::new(ExponentialBackoff::new(
+        Duration::from_millis(400),
+        connection_timeout / 2,
+    ))
+}
+
+fn env_var<T, F>(prefix: &str, name: &str, parse: F) -> Result<Option<T>, Error>
+where
+    F: FnOnce(&str) -> Option<T>,
+{
+    let var = format!("{}_{}", prefix, name);
+    let value = match env::var(&var) {
+        Ok(value) => value,
+        Err(env::VarError::NotPresent) => return Ok(None),
+        Err(env::VarError::NotUnicode(value)) => {
+            return Err(Error::env(var, value.to_string_lossy().into_owned()))
+        }
+    };
+
+    match parse(value.trim()) {
+        Some(v) => Ok(Some(v)),
+        None => Err(Error::env(var, value)),
+    }
+}
+
+fn optional<T, F>(value: &str, parse: F) -> Option<Option<T>>
+where
+    F: FnOnce(&str) -> Option<T>,
+{
+    if value.eq_ignore_ascii_case("none") {
+        Some(None)
+    } else {
+        parse(value).map(Some)
+    }
+}
+
+fn parse_duration(value: &str) -> Option<Duration> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount = amount.parse().ok()?;
+
+    match unit.trim() {
+        "ms" => Some(Duration::from_millis(amount)),
+        "" | "s" => Some(Duration::from_secs(amount)),
+        "m" => amount.checked_mul(60).map(Duration::from_secs),
+        "h" => amount.checked_mul(60 * 60).map(Duration::from_secs),
+        _ => None,
+    }
+}
+
+fn parse_idle_strategy(value: &str) -> Option<IdleStrategy> {
+    match &*value.to_ascii_lowercase() {
+        "lifo" => Some(IdleStrategy::Lifo),
+        "fifo" => Some(IdleStrategy::Fifo),
+        "random" => Some(IdleStrategy::Random),
+        _ => None,
     }
 }
 
 pub struct Config<C, E> {
     pub max_size: u32,
     pub min_idle: Option<u32>,
+    pub warmup_count: Option<u32>,
     pub test_on_check_out: bool,
+    pub test_on_check_in: bool,
+    pub test_while_idle: Option<Duration>,
     pub max_lifetime: Option<Duration>,
+    pub lifetime_jitter: Option<Duration>,
+    pub max_uses: Option<u32>,
     pub idle_timeout: Option<Duration>,
     pub connection_timeout: Duration,
-    pub error_handler: Box<dyn HandleError<E>>,
-    pub event_handler: Box<dyn HandleEvent>,
-    pub connection_customizer: Box<dyn CustomizeConnection<C, E>>,
-    pub thread_pool: Arc<ScheduledThreadPool>,
+    pub wait_timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    pub hedge_delay: Option<Duration>,
+    pub leak_detection_threshold: Option<Duration>,
+    pub slow_checkout_threshold: Option<Duration>,
+    pub initialization_timeout: Option<Duration>,
+    pub max_waiters: Option<u32>,
+    pub max_concurrent_connects: Option<u32>,
+    pub fair_queueing: bool,
+    pub background_disconnect: bool,
+    pub capture_backtraces: bool,
+    pub idle_strategy: IdleStrategy,
+    pub synchronous: bool,
+    pub backoff: Arc<dyn Backoff>,
+    pub default_backoff: bool,
+    pub error_handler: Arc<dyn HandleError<E>>,
+    pub event_handler: Arc<dyn HandleEvent>,
+    pub stats_sink: Option<Arc<dyn StatsSink>>,
+    pub connection_customizer: Arc<dyn CustomizeConnection<C, E>>,
+    pub thread_pool: Option<Arc<ScheduledThreadPool>>,
+    pub maintenance_rate: Duration,
+    pub stats_interval: Duration,
+    pub pool_name: Option<Arc<str>>,
+}
+
+// manual to avoid bounds on C and E
+impl<C, E> Clone for Config<C, E> {
+    fn clone(&self) -> Config<C, E> {
+        Config {
+            max_size: self.max_size,
+            min_idle: self.min_idle,
+            warmup_count: self.warmup_count,
+            test_on_check_out: self.test_on_check_out,
+            test_on_check_in: self.test_on_check_in,
+            test_while_idle: self.test_while_idle,
+            max_lifetime: self.max_lifetime,
+            lifetime_jitter: self.lifetime_jitter,
+            max_uses: self.max_uses,
+            idle_timeout: self.idle_timeout,
+            connection_timeout: self.connection_timeout,
+            wait_timeout: self.wait_timeout,
+            connect_timeout: self.connect_timeout,
+            hedge_delay: self.hedge_delay,
+            leak_detection_threshold: self.leak_detection_threshold,
+            slow_checkout_threshold: self.slow_checkout_threshold,
+            initialization_timeout: self.initialization_timeout,
+            max_waiters: self.max_waiters,
+            max_concurrent_connects: self.max_concurrent_connects,
+            fair_queueing: self.fair_queueing,
+            background_disconnect: self.background_disconnect,
+            capture_backtraces: self.capture_backtraces,
+            idle_strategy: self.idle_strategy,
+            synchronous: self.synchronous,
+            backoff: self.backoff.clone(),
+            default_backoff: self.default_backoff,
+            error_handler: self.error_handler.clone(),
+            event_handler: self.event_handler.clone(),
+            stats_sink: self.stats_sink.clone(),
+            connection_customizer: self.connection_customizer.clone(),
+            thread_pool: self.thread_pool.clone(),
+            maintenance_rate: self.maintenance_rate,
+            stats_interval: self.stats_interval,
+            pool_name: self.pool_name.clone(),
+        }
+    }
 }
 
 // manual to avoid bounds on C and E
@@ -286,13 +1198,37 @@ impl<C, E> fmt::Debug for Config<C, E> {
         fmt.debug_struct("Config")
             .field("max_size", &self.max_size)
             .field("min_idle", &self.min_idle)
+            .field("warmup_count", &self.warmup_count)
             .field("test_on_check_out", &self.test_on_check_out)
+            .field("test_on_check_in", &self.test_on_check_in)
+            .field("test_while_idle", &self.test_while_idle)
             .field("max_lifetime", &self.max_lifetime)
+            .field("lifetime_jitter", &self.lifetime_jitter)
+            .field("max_uses", &self.max_uses)
             .field("idle_timeout", &self.idle_timeout)
             .field("connection_timeout", &self.connection_timeout)
+            .field("wait_timeout", &self.wait_timeout)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("hedge_delay", &self.hedge_delay)
+            .field("leak_detection_threshold", &self.leak_detection_threshold)
+            .field("slow_checkout_threshold", &self.slow_checkout_threshold)
+            .field("initialization_timeout", &self.initialization_timeout)
+            .field("max_waiters", &self.max_waiters)
+            .field("max_concurrent_connects", &self.max_concurrent_connects)
+            .field("fair_queueing", &self.fair_queueing)
+            .field("background_disconnect", &self.background_disconnect)
+            .field("capture_backtraces", &self.capture_backtraces)
+            .field("idle_strategy", &self.idle_strategy)
+            .field("synchronous", &self.synchronous)
+            .field("backoff", &self.backoff)
+            .field("default_backoff", &self.default_backoff)
             .field("error_handler", &self.error_handler)
             .field("event_handler", &self.event_handler)
+            .field("stats_sink", &self.stats_sink)
             .field("connection_customizer", &self.connection_customizer)
+            .field("maintenance_rate", &self.maintenance_rate)
+            .field("stats_interval", &self.stats_interval)
+            .field("pool_name", &self.pool_name)
             .finish()
     }
 }
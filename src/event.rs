@@ -1,8 +1,12 @@
 //! Event subscriptions.
 
+use std::backtrace::Backtrace;
 use std::fmt;
+use std::sync::Arc;
 use std::time::Duration;
 
+use crate::{Health, State};
+
 /// A trait which is provided with information about events in a connection pool.
 pub trait HandleEvent: fmt::Debug + Sync + Send {
     /// Called when a new connection is acquired.
@@ -32,6 +36,45 @@ pub trait HandleEvent: fmt::Debug + Sync + Send {
     /// Called when a connection is checked back into the pool.
     #[allow(unused_variables)]
     fn handle_checkin(&self, event: CheckinEvent) {}
+
+    /// Called when a connection fails a health check.
+    ///
+    /// The default implementation does nothing.
+    #[allow(unused_variables)]
+    fn handle_validation_failure(&self, event: ValidationFailureEvent) {}
+
+    /// Called when a connection has been checked out for longer than the
+    /// pool's leak detection threshold.
+    ///
+    /// The default implementation does nothing.
+    #[allow(unused_variables)]
+    fn handle_leak(&self, event: LeakEvent) {}
+
+    /// Called when a checkout succeeds after waiting longer than the pool's
+    /// slow checkout threshold.
+    ///
+    /// The default implementation does nothing.
+    #[allow(unused_variables)]
+    fn handle_slow_checkout(&self, event: SlowCheckoutEvent) {}
+
+    /// Called when every connection in the pool is checked out and callers
+    /// start waiting for one.
+    ///
+    /// The default implementation does nothing.
+    #[allow(unused_variables)]
+    fn handle_exhausted(&self, event: ExhaustedEvent) {}
+
+    /// Called when an exhausted pool has a connection to spare again.
+    ///
+    /// The default implementation does nothing.
+    #[allow(unused_variables)]
+    fn handle_recovered(&self, event: RecoveredEvent) {}
+
+    /// Called when the health of the pool changes.
+    ///
+    /// The default implementation does nothing.
+    #[allow(unused_variables)]
+    fn handle_health_change(&self, event: HealthChangeEvent) {}
 }
 
 /// A `HandleEvent` implementation which does nothing.
@@ -40,10 +83,42 @@ pub struct NopEventHandler;
 
 impl HandleEvent for NopEventHandler {}
 
+/// An event in a connection pool, as delivered to subscribers.
+///
+/// See `Pool::subscribe`.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum Event {
+    /// A new connection was acquired.
+    Acquire(AcquireEvent),
+    /// A connection was released.
+    Release(ReleaseEvent),
+    /// A connection was checked out from the pool.
+    Checkout(CheckoutEvent),
+    /// A connection was checked back into the pool.
+    Checkin(CheckinEvent),
+    /// A checkout attempt timed out.
+    Timeout(TimeoutEvent),
+    /// A connection failed a health check.
+    ValidationFailure(ValidationFailureEvent),
+    /// A connection may have been leaked.
+    Leak(LeakEvent),
+    /// A checkout waited longer than the slow checkout threshold.
+    SlowCheckout(SlowCheckoutEvent),
+    /// The pool ran out of connections.
+    Exhausted(ExhaustedEvent),
+    /// The pool recovered from being exhausted.
+    Recovered(RecoveredEvent),
+    /// The health of the pool changed.
+    HealthChange(HealthChangeEvent),
+}
+
 /// Information about an acquire event.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct AcquireEvent {
     pub(crate) id: u64,
+    pub(crate) connect_duration: Duration,
+    pub(crate) pool_name: Option<Arc<str>>,
 }
 
 impl AcquireEvent {
@@ -52,13 +127,27 @@ impl AcquireEvent {
     pub fn connection_id(&self) -> u64 {
         self.id
     }
+
+    /// Returns the time taken by the connection manager to establish the
+    /// connection.
+    #[inline]
+    pub fn connect_duration(&self) -> Duration {
+        self.connect_duration
+    }
+
+    /// Returns the name of the pool, if it has one.
+    #[inline]
+    pub fn pool_name(&self) -> Option<&str> {
+        self.pool_name.as_deref()
+    }
 }
 
 /// Information about a release event.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ReleaseEvent {
     pub(crate) id: u64,
     pub(crate) age: Duration,
+    pub(crate) pool_name: Option<Arc<str>>,
 }
 
 impl ReleaseEvent {
@@ -73,13 +162,22 @@ impl ReleaseEvent {
     pub fn age(&self) -> Duration {
         self.age
     }
+
+    /// Returns the name of the pool, if it has one.
+    #[inline]
+    pub fn pool_name(&self) -> Option<&str> {
+        self.pool_name.as_deref()
+    }
 }
 
 /// Information about a checkout event.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct CheckoutEvent {
     pub(crate) id: u64,
     pub(crate) duration: Duration,
+    pub(crate) validation_duration: Option<Duration>,
+    pub(crate) waiters: u32,
+    pub(crate) pool_name: Option<Arc<str>>,
 }
 
 impl CheckoutEvent {
@@ -94,12 +192,35 @@ impl CheckoutEvent {
     pub fn duration(&self) -> Duration {
         self.duration
     }
+
+    /// Returns the time taken by the health check of the connection, if one
+    /// was run as part of the checkout.
+    #[inline]
+    pub fn validation_duration(&self) -> Option<Duration> {
+        self.validation_duration
+    }
+
+    /// Returns the number of other callers waiting for a connection at the
+    /// time of the checkout.
+    #[inline]
+    pub fn waiters(&self) -> u32 {
+        self.waiters
+    }
+
+    /// Returns the name of the pool, if it has one.
+    #[inline]
+    pub fn pool_name(&self) -> Option<&str> {
+        self.pool_name.as_deref()
+    }
 }
 
 /// Information about a timeout event.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct TimeoutEvent {
     pub(crate) timeout: Duration,
+    pub(crate) waiters: u32,
+    pub(crate) checked_out: Vec<CheckedOutConnection>,
+    pub(crate) pool_name: Option<Arc<str>>,
 }
 
 impl TimeoutEvent {
@@ -108,13 +229,64 @@ impl TimeoutEvent {
     pub fn timeout(&self) -> Duration {
         self.timeout
     }
+
+    /// Returns the number of other callers waiting for a connection when the
+    /// attempt timed out.
+    #[inline]
+    pub fn waiters(&self) -> u32 {
+        self.waiters
+    }
+
+    /// Returns the connections which were checked out when the attempt timed
+    /// out.
+    ///
+    /// See `Pool::checked_out`.
+    #[inline]
+    pub fn checked_out(&self) -> &[CheckedOutConnection] {
+        &self.checked_out
+    }
+
+    /// Returns the name of the pool, if it has one.
+    #[inline]
+    pub fn pool_name(&self) -> Option<&str> {
+        self.pool_name.as_deref()
+    }
+}
+
+/// Information about a connection which is checked out of a pool.
+#[derive(Clone, Debug)]
+pub struct CheckedOutConnection {
+    pub(crate) id: u64,
+    pub(crate) duration: Duration,
+    pub(crate) backtrace: Option<Arc<Backtrace>>,
+}
+
+impl CheckedOutConnection {
+    /// Returns the ID of the connection.
+    #[inline]
+    pub fn connection_id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns how long the connection has been checked out.
+    #[inline]
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns the backtrace of the checkout, if one was captured.
+    #[inline]
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        self.backtrace.as_deref()
+    }
 }
 
 /// Information about a checkin event.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct CheckinEvent {
     pub(crate) id: u64,
     pub(crate) duration: Duration,
+    pub(crate) pool_name: Option<Arc<str>>,
 }
 
 impl CheckinEvent {
@@ -129,4 +301,185 @@ impl CheckinEvent {
     pub fn duration(&self) -> Duration {
         self.duration
     }
+
+    /// Returns the name of the pool, if it has one.
+    #[inline]
+    pub fn pool_name(&self) -> Option<&str> {
+        self.pool_name.as_deref()
+    }
+}
+
+/// Information about a validation failure event.
+#[derive(Clone, Debug)]
+pub struct ValidationFailureEvent {
+    pub(crate) id: u64,
+    pub(crate) duration: Duration,
+    pub(crate) error: String,
+    pub(crate) pool_name: Option<Arc<str>>,
+}
+
+impl ValidationFailureEvent {
+    /// Returns the ID of the connection.
+    #[inline]
+    pub fn connection_id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the time taken by the failed health check.
+    #[inline]
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns the error reported by the health check.
+    #[inline]
+    pub fn error(&self) -> &str {
+        &self.error
+    }
+
+    /// Returns the name of the pool, if it has one.
+    #[inline]
+    pub fn pool_name(&self) -> Option<&str> {
+        self.pool_name.as_deref()
+    }
+}
+
+/// Information about a possibly leaked connection.
+#[derive(Clone, Debug)]
+pub struct LeakEvent {
+    pub(crate) id: u64,
+    pub(crate) duration: Duration,
+    pub(crate) backtrace: Option<Arc<Backtrace>>,
+    pub(crate) pool_name: Option<Arc<str>>,
+}
+
+impl LeakEvent {
+    /// Returns the ID of the connection.
+    #[inline]
+    pub fn connection_id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns how long the connection has been checked out.
+    #[inline]
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns the backtrace of the checkout, if one was captured.
+    #[inline]
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        self.backtrace.as_deref()
+    }
+
+    /// Returns the name of the pool, if it has one.
+    #[inline]
+    pub fn pool_name(&self) -> Option<&str> {
+        self.pool_name.as_deref()
+    }
+}
+
+/// Information about a slow checkout.
+#[derive(Clone, Debug)]
+pub struct SlowCheckoutEvent {
+    pub(crate) id: u64,
+    pub(crate) duration: Duration,
+    pub(crate) state: State,
+    pub(crate) pool_name: Option<Arc<str>>,
+}
+
+impl SlowCheckoutEvent {
+    /// Returns the ID of the connection.
+    #[inline]
+    pub fn connection_id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the time spent waiting for the connection.
+    #[inline]
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns the state of the pool when the checkout completed.
+    #[inline]
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Returns the name of the pool, if it has one.
+    #[inline]
+    pub fn pool_name(&self) -> Option<&str> {
+        self.pool_name.as_deref()
+    }
+}
+
+/// Information about the pool running out of connections.
+#[derive(Clone, Debug)]
+pub struct ExhaustedEvent {
+    pub(crate) state: State,
+    pub(crate) pool_name: Option<Arc<str>>,
+}
+
+impl ExhaustedEvent {
+    /// Returns the state of the pool when it became exhausted.
+    #[inline]
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Returns the name of the pool, if it has one.
+    #[inline]
+    pub fn pool_name(&self) -> Option<&str> {
+        self.pool_name.as_deref()
+    }
+}
+
+/// Information about the pool recovering from being exhausted.
+#[derive(Clone, Debug)]
+pub struct RecoveredEvent {
+    pub(crate) duration: Duration,
+    pub(crate) pool_name: Option<Arc<str>>,
+}
+
+impl RecoveredEvent {
+    /// Returns how long the pool was exhausted.
+    #[inline]
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns the name of the pool, if it has one.
+    #[inline]
+    pub fn pool_name(&self) -> Option<&str> {
+        self.pool_name.as_deref()
+    }
+}
+
+/// Information about a change in the health of the pool.
+#[derive(Clone, Debug)]
+pub struct HealthChangeEvent {
+    pub(crate) from: Health,
+    pub(crate) to: Health,
+    pub(crate) pool_name: Option<Arc<str>>,
+}
+
+impl HealthChangeEvent {
+    /// Returns the previous health of the pool.
+    #[inline]
+    pub fn from(&self) -> Health {
+        self.from
+    }
+
+    /// Returns the new health of the pool.
+    #[inline]
+    pub fn to(&self) -> Health {
+        self.to
+    }
+
+    /// Returns the name of the pool, if it has one.
+    #[inline]
+    pub fn pool_name(&self) -> Option<&str> {
+        self.pool_name.as_deref()
+    }
 }
@@ -39,32 +39,65 @@
 #![warn(missing_docs)]
 #![doc(html_root_url = "https://docs.rs/r2d2/0.8")]
 
-use log::error;
-
-use parking_lot::{Condvar, Mutex, MutexGuard};
+use parking_lot::{Condvar, Mutex, MutexGuard, RwLock};
+use scheduled_thread_pool::{JobHandle, ScheduledThreadPool};
+use std::backtrace::{Backtrace, BacktraceStatus};
 use std::cmp;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
 use std::error;
 use std::fmt;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
 use std::mem;
 use std::ops::{Deref, DerefMut};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Weak};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Weak};
+use std::task::{Context, Poll, Waker};
 use std::time::{Duration, Instant};
 
-pub use crate::config::Builder;
+// logs through the `log` crate if the `log` feature is enabled
+macro_rules! pool_log {
+    ($level:ident, pool: $pool:expr, $fmt:literal $($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        log::$level!(concat!("{}", $fmt), $pool.log_prefix() $($arg)*);
+    };
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "log")]
+        log::$level!($($arg)+);
+    };
+}
+
+pub use crate::backoff::{Backoff, ExponentialBackoff, FixedBackoff};
 use crate::config::Config;
-use crate::event::{AcquireEvent, CheckinEvent, CheckoutEvent, ReleaseEvent, TimeoutEvent};
+pub use crate::config::{Builder, ConfigUpdate, IdleStrategy};
+use crate::event::{
+    AcquireEvent, CheckedOutConnection, CheckinEvent, CheckoutEvent, Event, ExhaustedEvent,
+    HealthChangeEvent, LeakEvent, RecoveredEvent, ReleaseEvent, SlowCheckoutEvent, TimeoutEvent,
+    ValidationFailureEvent,
+};
 pub use crate::event::{HandleEvent, NopEventHandler};
 pub use crate::extensions::Extensions;
+pub use crate::pinned::{PinnedConnection, PinnedPool};
+use crate::stats::Stats;
+pub use crate::stats::{ConnectionStatistics, Histogram, Statistics, StatsSink, StatsSnapshot};
+pub use crate::statsd::StatsdReporter;
 
+mod backoff;
 mod config;
 pub mod event;
 mod extensions;
+pub mod manager;
+mod pinned;
+mod stats;
+mod statsd;
 
 #[cfg(test)]
 mod test;
 
 static CONNECTION_ID: AtomicUsize = AtomicUsize::new(0);
+static POOL_ID: AtomicUsize = AtomicUsize::new(0);
 
 /// A trait which provides connection-specific functionality.
 pub trait ManageConnection: Send + Sync + 'static {
@@ -72,17 +105,61 @@ pub trait ManageConnection: Send + Sync + 'static {
     type Connection: Send + 'static;
 
     /// The error type returned by `Connection`s.
-    type Error: error::Error + 'static;
+    ///
+    /// It does not need to implement `std::error::Error`, so types like
+    /// `Box<dyn Error>` can be used directly. Nor does it need to be `Send`,
+    /// as errors are reported on the thread they occur on.
+    type Error: fmt::Debug + fmt::Display + 'static;
 
     /// Attempts to create a new connection.
     fn connect(&self) -> Result<Self::Connection, Self::Error>;
 
+    /// Attempts to create a new connection, with information about why the
+    /// pool is opening it.
+    ///
+    /// The pool always calls this method rather than `connect`. The default
+    /// implementation ignores the context and calls `connect`.
+    #[allow(unused_variables)]
+    fn connect_with_context(
+        &self,
+        context: &ConnectContext,
+    ) -> Result<Self::Connection, Self::Error> {
+        self.connect()
+    }
+
     /// Determines if the connection is still connected to the database.
     ///
     /// A standard implementation would check if a simple query like `SELECT 1`
     /// succeeds.
     fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error>;
 
+    /// Determines if the connection is still connected to the database,
+    /// finishing by `deadline` if there is one.
+    ///
+    /// The pool always calls this method rather than `is_valid`. When a
+    /// connection is tested on check out, the deadline is that of the caller
+    /// waiting for it. The default implementation ignores the deadline and
+    /// calls `is_valid`.
+    #[allow(unused_variables)]
+    fn is_valid_with_deadline(
+        &self,
+        conn: &mut Self::Connection,
+        deadline: Option<Instant>,
+    ) -> Result<(), Self::Error> {
+        self.is_valid(conn)
+    }
+
+    /// Thoroughly determines if the connection is still usable.
+    ///
+    /// Where `is_valid` might simply ping the server, this can run a more
+    /// expensive check. The pool calls this method instead of `is_valid` when
+    /// validating idle connections in the background, since no caller is
+    /// waiting on the result. The default implementation calls
+    /// `is_valid_with_deadline` with no deadline.
+    fn deep_check(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        self.is_valid_with_deadline(conn, None)
+    }
+
     /// *Quickly* determines if the connection is no longer usable.
     ///
     /// This will be called synchronously every time a connection is returned
@@ -93,6 +170,194 @@ pub trait ManageConnection: Send + Sync + 'static {
     /// has disconnected. Implementations that do not support this kind of
     /// fast health check may simply return `false`.
     fn has_broken(&self, conn: &mut Self::Connection) -> bool;
+
+    /// Closes a connection which the pool is discarding.
+    ///
+    /// This is called after `on_destroy`, and can perform a close handshake
+    /// which may block or fail, rather than doing so in the connection's
+    /// `Drop` implementation. Errors are passed to the pool's error handler.
+    /// See `Builder::background_disconnect` to run it on the pool's helper
+    /// threads. The connection is dropped afterwards.
+    ///
+    /// The default implementation does nothing.
+    #[allow(unused_variables)]
+    fn disconnect(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Returns how long the pool should wait before retrying after a failed
+    /// connection attempt, if the error indicates it.
+    ///
+    /// This allows a server's request to back off, for example because it is
+    /// overloaded or throttling new connections, to take precedence over the
+    /// pool's backoff policy.
+    ///
+    /// The default implementation returns `None`.
+    #[allow(unused_variables)]
+    fn retry_after(&self, error: &Self::Error) -> Option<Duration> {
+        None
+    }
+
+    /// Called with connections before the pool closes them.
+    ///
+    /// This is called for connections which are broken, expired, or reaped,
+    /// and for idle connections when the pool shuts down. It can be used to
+    /// cleanly end the session with the server. It is not called for
+    /// connections removed with `PooledConnection::detach` or
+    /// `Pool::into_inner`.
+    ///
+    /// The default implementation does nothing.
+    #[allow(unused_variables)]
+    fn on_destroy(&self, conn: &mut Self::Connection) {}
+
+    /// Returns the time after which a newly opened connection should no longer
+    /// be used, if there is one.
+    ///
+    /// This can be used to retire connections authenticated with a
+    /// short-lived credential, or which the server will close after a fixed
+    /// time. The pool closes connections shortly before they expire, by a
+    /// tenth of their lifetime up to 5 seconds, so that a connection isn't
+    /// checked out just as it expires. This is in addition to enforcing
+    /// `max_lifetime`.
+    ///
+    /// The default implementation returns `None`.
+    #[allow(unused_variables)]
+    fn expires_at(&self, conn: &Self::Connection) -> Option<Instant> {
+        None
+    }
+
+    /// Returns the tags of a newly opened connection.
+    ///
+    /// Tags are fixed for the lifetime of the connection, and can be used to
+    /// check out a specific class of connection with `Pool::get_tagged`.
+    ///
+    /// The default implementation returns no tags.
+    #[allow(unused_variables)]
+    fn tags(&self, conn: &Self::Connection) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+impl<M> ManageConnection for Box<M>
+where
+    M: ManageConnection + ?Sized,
+{
+    type Connection = M::Connection;
+    type Error = M::Error;
+
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        (**self).connect()
+    }
+
+    fn connect_with_context(
+        &self,
+        context: &ConnectContext,
+    ) -> Result<Self::Connection, Self::Error> {
+        (**self).connect_with_context(context)
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        (**self).is_valid(conn)
+    }
+
+    fn is_valid_with_deadline(
+        &self,
+        conn: &mut Self::Connection,
+        deadline: Option<Instant>,
+    ) -> Result<(), Self::Error> {
+        (**self).is_valid_with_deadline(conn, deadline)
+    }
+
+    fn deep_check(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        (**self).deep_check(conn)
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        (**self).has_broken(conn)
+    }
+
+    fn disconnect(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        (**self).disconnect(conn)
+    }
+
+    fn retry_after(&self, error: &Self::Error) -> Option<Duration> {
+        (**self).retry_after(error)
+    }
+
+    fn on_destroy(&self, conn: &mut Self::Connection) {
+        (**self).on_destroy(conn)
+    }
+
+    fn expires_at(&self, conn: &Self::Connection) -> Option<Instant> {
+        (**self).expires_at(conn)
+    }
+
+    fn tags(&self, conn: &Self::Connection) -> Vec<String> {
+        (**self).tags(conn)
+    }
+}
+
+/// The reason the pool is opening a connection.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConnectReason {
+    /// The pool is opening its initial set of connections.
+    Initialization,
+    /// The pool needs more connections to maintain `min_idle` or to serve
+    /// waiting callers.
+    Refill,
+    /// The pool is replacing a connection which was closed.
+    Replacement,
+    /// The connection was requested through `Pool::dedicated_connection`.
+    Dedicated,
+}
+
+/// Information about a connection attempt, passed to
+/// `ManageConnection::connect_with_context`.
+#[derive(Clone, Debug)]
+pub struct ConnectContext {
+    attempt: u32,
+    deadline: Option<Instant>,
+    reason: ConnectReason,
+    tag: Option<Arc<str>>,
+}
+
+impl ConnectContext {
+    /// Returns the number of previous failed attempts to open this connection.
+    ///
+    /// It is 0 for the first attempt.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Returns the time by which the connection should be established, if
+    /// there is one.
+    ///
+    /// This is derived from the pool's `connect_timeout`, and for connections
+    /// opened by a caller of a synchronous pool it is no later than the time
+    /// that caller stops waiting.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Returns the amount of time left before the deadline, if there is one.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Returns the reason the pool is opening the connection.
+    pub fn reason(&self) -> ConnectReason {
+        self.reason
+    }
+
+    /// Returns the tag requested by the `Pool::get_tagged` caller the
+    /// connection is being opened for, if there is one.
+    ///
+    /// Managers which can open connections with a particular tag should do so.
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
 }
 
 /// A trait which handles errors reported by the `ManageConnection`.
@@ -110,15 +375,18 @@ impl<E> HandleError<E> for NopErrorHandler {
 }
 
 /// A `HandleError` implementation which logs at the error level.
+///
+/// Errors are discarded if the `log` feature is disabled.
 #[derive(Copy, Clone, Debug)]
 pub struct LoggingErrorHandler;
 
 impl<E> HandleError<E> for LoggingErrorHandler
 where
-    E: error::Error,
+    E: fmt::Display,
 {
+    #[allow(unused_variables)]
     fn handle_error(&self, error: E) {
-        error!("{}", error);
+        pool_log!(error, "{}", error);
     }
 }
 
@@ -137,6 +405,36 @@ pub trait CustomizeConnection<C, E>: fmt::Debug + Send + Sync + 'static {
         Ok(())
     }
 
+    /// Determines if a connection being returned to the pool should be closed
+    /// rather than reused.
+    ///
+    /// This is called before `on_check_in`, and can be used to discard
+    /// connections whose session state can't be reset, for example ones the
+    /// driver has flagged as tainted.
+    ///
+    /// The default implementation returns `false`.
+    #[allow(unused_variables)]
+    fn discard_on_return(&self, conn: &mut C) -> bool {
+        false
+    }
+
+    /// Called with connections when they are returned to the pool, before
+    /// they are made available to other callers.
+    ///
+    /// This can be used to reset session state such as open transactions,
+    /// temporary tables, or session variables. It is not called for
+    /// connections which are broken or expired, since they will be discarded.
+    ///
+    /// The default implementation simply returns `Ok(())`.
+    ///
+    /// # Errors
+    ///
+    /// If this method returns an error, the connection will be discarded.
+    #[allow(unused_variables)]
+    fn on_check_in(&self, conn: &mut C) -> Result<(), E> {
+        Ok(())
+    }
+
     /// Called with connections when they are removed from the pool.
     ///
     /// The connections may be broken (as reported by `is_valid` or
@@ -157,7 +455,65 @@ struct Conn<C> {
     conn: C,
     extensions: Extensions,
     birth: Instant,
+    lifetime_jitter: Duration,
+    uses: u32,
+    generation: u64,
     id: u64,
+    tags: Vec<String>,
+    expires_at: Option<Instant>,
+    busy: Duration,
+    last_validation: Option<(Instant, Duration)>,
+}
+
+impl<C> Conn<C> {
+    fn statistics(&self, checked_out: bool) -> ConnectionStatistics {
+        ConnectionStatistics {
+            id: self.id,
+            created_at: self.birth,
+            checked_out,
+            checkouts: self.uses,
+            busy: self.busy,
+            last_validated_at: self.last_validation.map(|(at, _)| at),
+            last_validation_duration: self.last_validation.map(|(_, duration)| duration),
+        }
+    }
+
+    fn validated(&mut self, start: Instant) -> Duration {
+        let duration = start.elapsed();
+        self.last_validation = Some((start, duration));
+        duration
+    }
+
+    // the duration of the last health check, if it was run after `since`
+    fn validated_since(&self, since: Instant) -> Option<Duration> {
+        self.last_validation
+            .filter(|&(at, _)| at >= since)
+            .map(|(_, duration)| duration)
+    }
+
+    fn expired<E>(&self, config: &Config<C, E>, now: Instant) -> bool {
+        if let Some(max_uses) = config.max_uses {
+            if self.uses >= max_uses {
+                return true;
+            }
+        }
+
+        if let Some(expires_at) = self.expires_at {
+            if now >= expires_at {
+                return true;
+            }
+        }
+
+        match config.max_lifetime {
+            Some(lifetime) => {
+                let lifetime = lifetime
+                    .checked_sub(self.lifetime_jitter)
+                    .unwrap_or_else(|| Duration::from_secs(0));
+                now - self.birth >= lifetime
+            }
+            None => false,
+        }
+    }
 }
 
 struct IdleConn<C> {
@@ -165,320 +521,2030 @@ struct IdleConn<C> {
     idle_start: Instant,
 }
 
+// a caller waiting for its turn at a connection, woken through its condvar if
+// it is blocked in `Pool::get` or its waker if it is a `GetFuture`
+struct Waiter {
+    cond: Condvar,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Waiter {
+    fn new() -> Waiter {
+        Waiter {
+            cond: Condvar::new(),
+            waker: Mutex::new(None),
+        }
+    }
+
+    fn notify(&self) {
+        self.cond.notify_one();
+        if let Some(waker) = self.waker.lock().take() {
+            waker.wake();
+        }
+    }
+}
+
+// a caller of `Pool::get_tagged` waiting for a connection with its tag
+struct TaggedWaiter {
+    tag: Arc<str>,
+    cond: Arc<Condvar>,
+    // whether the caller may still evict an idle connection with another tag
+    can_evict: bool,
+}
+
 struct PoolInternals<C> {
     conns: Vec<IdleConn<C>>,
     num_conns: u32,
     pending_conns: u32,
+    waiters: u32,
+    tagged_waiters: Vec<TaggedWaiter>,
+    // when each waiting caller started its checkout
+    wait_starts: Vec<Instant>,
+    waiter_queue: VecDeque<Arc<Waiter>>,
+    // futures waiting for a connection outside of the fair queue
+    async_waiters: VecDeque<Arc<Waiter>>,
+    // cleared by the next new connection, and reported by the next timeout
     last_error: Option<String>,
+    // kept until replaced by a newer error
+    recent_error: Option<LastError>,
+    closed: bool,
+    reclaiming: bool,
+    generation: u64,
+    jobs: Vec<JobHandle>,
+    exhausted_since: Option<Instant>,
+    health: Health,
+    // the outcomes of the most recent connection attempts and health checks,
+    // newest in the lowest bit, with failures set
+    outcomes: u32,
+    // statistics of checked out connections as of their checkout
+    in_use: HashMap<u64, (ConnectionStatistics, Instant)>,
+}
+
+impl<C> PoolInternals<C> {
+    fn set_last_error(&mut self, error: String) {
+        self.recent_error = Some(LastError {
+            message: error.clone(),
+            occurred_at: Instant::now(),
+        });
+        self.last_error = Some(error);
+    }
 }
 
 struct SharedPool<M>
 where
     M: ManageConnection,
 {
-    config: Config<M::Connection, M::Error>,
-    manager: M,
+    // tells apart pools which share a name in statistics reports
+    id: u64,
+    config: RwLock<Arc<Config<M::Connection, M::Error>>>,
+    manager: RwLock<Arc<M>>,
     internals: Mutex<PoolInternals<M::Connection>>,
     cond: Condvar,
+    ready: Condvar,
+    extensions: RwLock<Extensions>,
+    subscribers: Mutex<Vec<mpsc::Sender<Event>>>,
+    // lets publish skip the subscribers lock while there are none
+    has_subscribers: AtomicBool,
+    batch: Mutex<()>,
+    idle_count: AtomicUsize,
+    waiter_count: AtomicUsize,
+    connecting: Mutex<ConnectSlots>,
+    connect_slot: Condvar,
+    // connection attempts currently in progress
+    connects_in_flight: AtomicU32,
+    stats: Stats,
+    leases: Mutex<HashMap<u64, Lease>>,
+    // set while none of the outcomes in `PoolInternals::outcomes` failed
+    no_recent_failures: AtomicBool,
 }
 
-fn drop_conns<M>(
-    shared: &Arc<SharedPool<M>>,
-    mut internals: MutexGuard<PoolInternals<M::Connection>>,
-    conns: Vec<Conn<M::Connection>>,
-) where
-    M: ManageConnection,
-{
-    internals.num_conns -= conns.len() as u32;
-    establish_idle_connections(shared, &mut internals);
-    drop(internals); // make sure we run connection destructors without this locked
-
-    for conn in conns {
-        let event = ReleaseEvent {
-            id: conn.id,
-            age: conn.birth.elapsed(),
-        };
-        shared.config.event_handler.handle_release(event);
-        shared.config.connection_customizer.on_release(conn.conn);
-    }
+// a checked out connection, tracked for leak detection and backtrace capture
+struct Lease {
+    checkout: Instant,
+    backtrace: Option<Arc<Backtrace>>,
+    reported: bool,
 }
 
-fn establish_idle_connections<M>(
-    shared: &Arc<SharedPool<M>>,
-    internals: &mut PoolInternals<M::Connection>,
-) where
-    M: ManageConnection,
-{
-    let min = shared.config.min_idle.unwrap_or(shared.config.max_size);
-    let idle = internals.conns.len() as u32;
-    for _ in idle..min {
-        add_connection(shared, internals);
-    }
+fn tracks_leases<C, E>(config: &Config<C, E>) -> bool {
+    config.leak_detection_threshold.is_some() || config.capture_backtraces
 }
 
-fn add_connection<M>(shared: &Arc<SharedPool<M>>, internals: &mut PoolInternals<M::Connection>)
+impl<M> SharedPool<M>
 where
     M: ManageConnection,
 {
-    if internals.num_conns + internals.pending_conns >= shared.config.max_size {
-        return;
+    fn config(&self) -> Arc<Config<M::Connection, M::Error>> {
+        self.config.read().clone()
     }
 
-    internals.pending_conns += 1;
-    inner(Duration::from_secs(0), shared);
+    fn manager(&self) -> Arc<M> {
+        self.manager.read().clone()
+    }
 
-    fn inner<M>(delay: Duration, shared: &Arc<SharedPool<M>>)
-    where
-        M: ManageConnection,
-    {
-        let new_shared = Arc::downgrade(shared);
-        shared.config.thread_pool.execute_after(delay, move || {
-            let shared = match new_shared.upgrade() {
-                Some(shared) => shared,
-                None => return,
-            };
+    // the manager to open a connection with, along with the generation that
+    // connection belongs to
+    fn connector(&self) -> (Arc<M>, u64) {
+        let internals = self.internals.lock();
+        (self.manager(), internals.generation)
+    }
 
-            let conn = shared.manager.connect().and_then(|mut conn| {
-                shared
-                    .config
-                    .connection_customizer
-                    .on_acquire(&mut conn)
-                    .map(|_| conn)
-            });
-            match conn {
-                Ok(conn) => {
-                    let id = CONNECTION_ID.fetch_add(1, Ordering::Relaxed) as u64;
-
-                    let event = AcquireEvent { id };
-                    shared.config.event_handler.handle_acquire(event);
-
-                    let mut internals = shared.internals.lock();
-                    internals.last_error = None;
-                    let now = Instant::now();
-                    let conn = IdleConn {
-                        conn: Conn {
-                            conn,
-                            extensions: Extensions::new(),
-                            birth: now,
-                            id,
-                        },
-                        idle_start: now,
-                    };
-                    internals.conns.push(conn);
-                    internals.pending_conns -= 1;
-                    internals.num_conns += 1;
-                    shared.cond.notify_one();
-                }
-                Err(err) => {
-                    shared.internals.lock().last_error = Some(err.to_string());
-                    shared.config.error_handler.handle_error(err);
-                    let delay = cmp::max(Duration::from_millis(200), delay);
-                    let delay = cmp::min(shared.config.connection_timeout / 2, delay * 2);
-                    inner(delay, &shared);
+    // takes a slot under `max_concurrent_connects`, waiting for one as long as
+    // `wait` allows, or returns `None` if none became free in that time
+    fn connect_permit(&self, wait: SlotWait) -> Option<ConnectPermit<'_>> {
+        let slot = match self.config().max_concurrent_connects {
+            Some(max) => {
+                let mut connecting = self.connecting.lock();
+                while connecting.count >= max {
+                    match wait {
+                        SlotWait::Never => return None,
+                        SlotWait::Queue(retry) => {
+                            connecting.queued.push_back(retry);
+                            return None;
+                        }
+                        SlotWait::Until(deadline) => {
+                            if self
+                                .connect_slot
+                                .wait_until(&mut connecting, deadline)
+                                .timed_out()
+                                && connecting.count >= max
+                            {
+                                return None;
+                            }
+                        }
+                        SlotWait::Forever => self.connect_slot.wait(&mut connecting),
+                    }
                 }
+                connecting.count += 1;
+                Some((&self.connecting, &self.connect_slot))
             }
-        });
+            None => None,
+        };
+        self.connects_in_flight.fetch_add(1, Ordering::Relaxed);
+        Some(ConnectPermit {
+            in_flight: &self.connects_in_flight,
+            slot,
+        })
     }
-}
 
-fn reap_connections<M>(shared: &Weak<SharedPool<M>>)
-where
-    M: ManageConnection,
-{
-    let shared = match shared.upgrade() {
-        Some(shared) => shared,
-        None => return,
-    };
+    fn validation_failed(&self, id: u64, start: Instant, error: String) {
+        let duration = start.elapsed();
+        self.stats.record_validation(duration);
+        self.stats.record_validation_failure();
+        record_health(self, false);
+        let event = ValidationFailureEvent {
+            pool_name: self.pool_name(),
+            id,
+            duration,
+            error,
+        };
+        pool_log!(
+            warn,
+            pool: self,
+            "connection {} failed validation after {:?}: {}",
+            event.id,
+            event.duration,
+            event.error
+        );
+        self.publish(Event::ValidationFailure(event));
+    }
 
-    let mut old = Vec::with_capacity(shared.config.max_size as usize);
-    let mut to_drop = vec![];
+    fn pool_name(&self) -> Option<Arc<str>> {
+        self.config().pool_name.clone()
+    }
 
-    let mut internals = shared.internals.lock();
-    mem::swap(&mut old, &mut internals.conns);
-    let now = Instant::now();
-    for conn in old {
-        let mut reap = false;
-        if let Some(timeout) = shared.config.idle_timeout {
-            reap |= now - conn.idle_start >= timeout;
-        }
-        if let Some(lifetime) = shared.config.max_lifetime {
-            reap |= now - conn.conn.birth >= lifetime;
+    #[cfg(feature = "log")]
+    fn log_prefix(&self) -> LogPrefix {
+        LogPrefix(self.pool_name())
+    }
+
+    fn publish(&self, event: Event) {
+        if self.has_subscribers.load(Ordering::Relaxed) {
+            let mut subscribers = self.subscribers.lock();
+            subscribers.retain(|s| s.send(event.clone()).is_ok());
+            if subscribers.is_empty() {
+                self.has_subscribers.store(false, Ordering::Relaxed);
+            }
         }
-        if reap {
-            to_drop.push(conn.conn);
-        } else {
-            internals.conns.push(conn);
+
+        let handler = &self.config().event_handler;
+        match event {
+            Event::Acquire(event) => handler.handle_acquire(event),
+            Event::Release(event) => handler.handle_release(event),
+            Event::Checkout(event) => handler.handle_checkout(event),
+            Event::Checkin(event) => handler.handle_checkin(event),
+            Event::Timeout(event) => handler.handle_timeout(event),
+            Event::ValidationFailure(event) => handler.handle_validation_failure(event),
+            Event::Leak(event) => handler.handle_leak(event),
+            Event::SlowCheckout(event) => handler.handle_slow_checkout(event),
+            Event::Exhausted(event) => handler.handle_exhausted(event),
+            Event::Recovered(event) => handler.handle_recovered(event),
+            Event::HealthChange(event) => handler.handle_health_change(event),
         }
     }
-    drop_conns(&shared, internals, to_drop);
 }
 
-/// A generic connection pool.
-pub struct Pool<M>(Arc<SharedPool<M>>)
-where
-    M: ManageConnection;
+// prefixes log messages with the name of the pool
+#[cfg(feature = "log")]
+struct LogPrefix(Option<Arc<str>>);
 
-/// Returns a new `Pool` referencing the same state as `self`.
-impl<M> Clone for Pool<M>
+#[cfg(feature = "log")]
+impl fmt::Display for LogPrefix {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(ref name) => write!(fmt, "pool {}: ", name),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<M> Drop for SharedPool<M>
 where
     M: ManageConnection,
 {
-    fn clone(&self) -> Pool<M> {
-        Pool(self.0.clone())
+    fn drop(&mut self) {
+        let conns = mem::take(&mut self.internals.get_mut().conns);
+        let conns = conns.into_iter().map(|conn| conn.conn).collect();
+        for conn in release_conns(self, conns) {
+            close(self, conn);
+        }
     }
 }
 
-impl<M> fmt::Debug for Pool<M>
-where
-    M: ManageConnection + fmt::Debug,
-{
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt.debug_struct("Pool")
-            .field("state", &self.state())
-            .field("config", &self.0.config)
-            .field("manager", &self.0.manager)
-            .finish()
+// how long to wait for a slot under `max_concurrent_connects`
+enum SlotWait {
+    Never,
+    // run the function once a slot is freed rather than wait
+    Queue(Box<dyn FnOnce() + Send>),
+    Until(Instant),
+    Forever,
+}
+
+struct ConnectSlots {
+    count: u32,
+    // background attempts to restart as slots are freed
+    queued: VecDeque<Box<dyn FnOnce() + Send>>,
+}
+
+struct ConnectPermit<'a> {
+    in_flight: &'a AtomicU32,
+    slot: Option<(&'a Mutex<ConnectSlots>, &'a Condvar)>,
+}
+
+impl Drop for ConnectPermit<'_> {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+        if let Some((connecting, slot)) = self.slot {
+            let next = {
+                let mut connecting = connecting.lock();
+                connecting.count -= 1;
+                slot.notify_one();
+                connecting.queued.pop_front()
+            };
+            if let Some(next) = next {
+                next();
+            }
+        }
     }
 }
 
-impl<M> Pool<M>
-where
+fn drop_conns<M>(
+    shared: &Arc<SharedPool<M>>,
+    mut internals: MutexGuard<PoolInternals<M::Connection>>,
+    conns: Vec<Conn<M::Connection>>,
+) where
     M: ManageConnection,
 {
-    /// Creates a new connection pool with a default configuration.
-    pub fn new(manager: M) -> Result<Pool<M>, Error> {
-        Pool::builder().build(manager)
+    internals.num_conns -= conns.len() as u32;
+    shared.stats.record_closed(conns.len());
+    record_idle(shared, &internals);
+    if internals.closed {
+        shared.cond.notify_all();
+    }
+    drop(internals); // make sure we run connection destructors without this locked
+
+    let conns = release_conns(shared, conns);
+    // replacements are opened only once the closed connections have been reported
+    establish_idle_connections(
+        shared,
+        &mut shared.internals.lock(),
+        ConnectReason::Replacement,
+    );
+    for conn in conns {
+        disconnect(shared, conn);
+    }
+}
+
+// reports the release of connections which have been removed from the pool,
+// returning them to be disconnected
+fn release_conns<M>(shared: &SharedPool<M>, conns: Vec<Conn<M::Connection>>) -> Vec<M::Connection>
+where
+    M: ManageConnection,
+{
+    conns
+        .into_iter()
+        .map(|conn| release_conn(shared, conn.conn, conn.id, conn.birth))
+        .collect()
+}
+
+fn release_conn<M>(
+    shared: &SharedPool<M>,
+    mut conn: M::Connection,
+    id: u64,
+    birth: Instant,
+) -> M::Connection
+where
+    M: ManageConnection,
+{
+    shared.manager().on_destroy(&mut conn);
+    let event = ReleaseEvent {
+        pool_name: shared.pool_name(),
+        id,
+        age: birth.elapsed(),
+    };
+    shared.publish(Event::Release(event));
+    conn
+}
+
+// closes a newly opened connection which the customizer hasn't set up, so
+// unlike `disconnect` it isn't passed to `on_release`
+fn close_unacquired<M>(shared: &SharedPool<M>, manager: &M, mut conn: M::Connection)
+where
+    M: ManageConnection,
+{
+    manager.on_destroy(&mut conn);
+    if let Err(e) = manager.disconnect(&mut conn) {
+        shared.config().error_handler.handle_error(e);
+    }
+}
+
+fn disconnect<M>(shared: &Arc<SharedPool<M>>, conn: M::Connection)
+where
+    M: ManageConnection,
+{
+    let config = shared.config();
+    match config.thread_pool {
+        Some(ref thread_pool) if config.background_disconnect => {
+            let shared = shared.clone();
+            thread_pool.execute(move || close(&shared, conn));
+        }
+        _ => close(shared, conn),
+    }
+}
+
+// closes a released connection on the calling thread
+fn close<M>(shared: &SharedPool<M>, mut conn: M::Connection)
+where
+    M: ManageConnection,
+{
+    let config = shared.config();
+    if let Err(e) = shared.manager().disconnect(&mut conn) {
+        config.error_handler.handle_error(e);
+    }
+    config.connection_customizer.on_release(conn);
+}
+
+// mirrors the idle count outside of the lock for idle_available
+fn record_idle<M>(shared: &SharedPool<M>, internals: &PoolInternals<M::Connection>)
+where
+    M: ManageConnection,
+{
+    shared
+        .idle_count
+        .store(internals.conns.len(), Ordering::Relaxed);
+}
+
+fn record_waiters<M>(shared: &SharedPool<M>, internals: &PoolInternals<M::Connection>)
+where
+    M: ManageConnection,
+{
+    shared
+        .waiter_count
+        .store(internals.waiters as usize, Ordering::Relaxed);
+    shared.stats.record_waiters(internals.waiters);
+}
+
+fn pop_idle<C, E>(
+    config: &Config<C, E>,
+    conns: &mut Vec<IdleConn<C>>,
+    tag: Option<&str>,
+) -> Option<IdleConn<C>> {
+    let tag = match tag {
+        Some(tag) => tag,
+        None => {
+            if conns.is_empty() {
+                return None;
+            }
+
+            return match config.idle_strategy {
+                IdleStrategy::Lifo => conns.pop(),
+                IdleStrategy::Fifo => Some(conns.remove(0)),
+                IdleStrategy::Random => {
+                    let idx = random() as usize % conns.len();
+                    Some(conns.swap_remove(idx))
+                }
+            };
+        }
+    };
+
+    let matches = |conn: &IdleConn<C>| conn.conn.tags.iter().any(|t| t == tag);
+    let idx = match config.idle_strategy {
+        IdleStrategy::Lifo => conns.iter().rposition(matches)?,
+        IdleStrategy::Fifo => conns.iter().position(matches)?,
+        IdleStrategy::Random => {
+            let idxs = (0..conns.len())
+                .filter(|&i| matches(&conns[i]))
+                .collect::<Vec<_>>();
+            if idxs.is_empty() {
+                return None;
+            }
+            return Some(conns.swap_remove(idxs[random() as usize % idxs.len()]));
+        }
+    };
+    Some(conns.remove(idx))
+}
+
+// the number of connections a new pool opens before it is returned
+fn initial_size<C, E>(config: &Config<C, E>) -> u32 {
+    match config.warmup_count {
+        Some(warmup_count) => warmup_count,
+        None => config.min_idle.unwrap_or(config.max_size),
+    }
+}
+
+fn lifetime_jitter<C, E>(config: &Config<C, E>) -> Duration {
+    match config.lifetime_jitter {
+        Some(jitter) if jitter.as_nanos() > 0 => {
+            let nanos = cmp::min(jitter.as_nanos(), u64::MAX as u128) as u64;
+            Duration::from_nanos(random() % nanos)
+        }
+        _ => Duration::from_secs(0),
+    }
+}
+
+fn random() -> u64 {
+    // RandomState is freshly keyed for each instance, which is plenty for load spreading
+    RandomState::new().build_hasher().finish()
+}
+
+fn notify_waiter<M>(shared: &SharedPool<M>, internals: &PoolInternals<M::Connection>)
+where
+    M: ManageConnection,
+{
+    shared.cond.notify_one();
+    if let Some(waiter) = internals.waiter_queue.front() {
+        waiter.notify();
+    }
+    // a future which has already been woken is left to poll before another is
+    if let Some(waker) = internals
+        .async_waiters
+        .iter()
+        .find_map(|waiter| waiter.waker.lock().take())
+    {
+        waker.wake();
+    }
+    // prefer a tagged waiter which can use an idle connection as is over one
+    // which would have to evict it
+    let has_tag = |tag: &str| {
+        internals
+            .conns
+            .iter()
+            .any(|conn| conn.conn.tags.iter().any(|t| t == tag))
+    };
+    let waiter = internals
+        .tagged_waiters
+        .iter()
+        .find(|waiter| has_tag(&waiter.tag))
+        .or_else(|| {
+            internals
+                .tagged_waiters
+                .iter()
+                .find(|waiter| waiter.can_evict && !internals.conns.is_empty())
+        });
+    if let Some(waiter) = waiter {
+        waiter.cond.notify_one();
+    }
+}
+
+fn establish_idle_connections<M>(
+    shared: &Arc<SharedPool<M>>,
+    internals: &mut PoolInternals<M::Connection>,
+    reason: ConnectReason,
+) where
+    M: ManageConnection,
+{
+    let config = shared.config();
+    let min = config.min_idle.unwrap_or(config.max_size);
+    let idle = internals.conns.len() as u32;
+    for _ in idle..min {
+        add_connection(shared, internals, reason);
+    }
+}
+
+fn add_connection<M>(
+    shared: &Arc<SharedPool<M>>,
+    internals: &mut PoolInternals<M::Connection>,
+    reason: ConnectReason,
+) where
+    M: ManageConnection,
+{
+    add_tagged_connection(shared, internals, reason, None);
+}
+
+// starts opening a connection, asking the manager for one with `tag`
+fn add_tagged_connection<M>(
+    shared: &Arc<SharedPool<M>>,
+    internals: &mut PoolInternals<M::Connection>,
+    reason: ConnectReason,
+    tag: Option<Arc<str>>,
+) where
+    M: ManageConnection,
+{
+    let config = shared.config();
+    let thread_pool = match config.thread_pool {
+        Some(ref thread_pool) => thread_pool.clone(),
+        // synchronous pools open connections in Pool::get instead
+        None => return,
+    };
+
+    if internals.closed || internals.num_conns + internals.pending_conns >= config.max_size {
+        return;
+    }
+
+    internals.pending_conns += 1;
+    inner(0, shared, thread_pool, reason, tag, None);
+
+    fn inner<M>(
+        attempt: u32,
+        shared: &Arc<SharedPool<M>>,
+        thread_pool: Arc<ScheduledThreadPool>,
+        reason: ConnectReason,
+        tag: Option<Arc<str>>,
+        retry_after: Option<Duration>,
+    ) where
+        M: ManageConnection,
+    {
+        let delay = match (attempt, retry_after) {
+            (_, Some(retry_after)) => retry_after,
+            (0, None) => Duration::from_secs(0),
+            (attempt, None) => shared.config().backoff.delay(attempt),
+        };
+        let new_shared = Arc::downgrade(shared);
+        thread_pool.clone().execute_after(delay, move || {
+            let shared = match new_shared.upgrade() {
+                Some(shared) => shared,
+                None => return,
+            };
+
+            {
+                let mut internals = shared.internals.lock();
+                if internals.closed {
+                    internals.pending_conns -= 1;
+                    shared.cond.notify_all();
+                    return;
+                }
+            }
+
+            // waiting for a slot would tie up a thread the pool's other jobs
+            // need, so the attempt is restarted once one is freed instead
+            let retry = {
+                let shared = new_shared.clone();
+                let thread_pool = thread_pool.clone();
+                let tag = tag.clone();
+                move || {
+                    if let Some(shared) = shared.upgrade() {
+                        let delay = Some(Duration::from_secs(0));
+                        inner(attempt, &shared, thread_pool, reason, tag, delay);
+                    }
+                }
+            };
+            let permit = match shared.connect_permit(SlotWait::Queue(Box::new(retry))) {
+                Some(permit) => permit,
+                None => return,
+            };
+
+            let hedge = shared.config().hedge_delay.map(|delay| {
+                let hedge = Arc::new(Mutex::new(Hedge {
+                    done: false,
+                    running: 1,
+                    retry_after: None,
+                }));
+                let new_shared = Arc::downgrade(&shared);
+                let new_hedge = hedge.clone();
+                let tag = tag.clone();
+                thread_pool.execute_after(delay, move || {
+                    let shared = match new_shared.upgrade() {
+                        Some(shared) => shared,
+                        None => return,
+                    };
+                    let permit = {
+                        let mut hedge = new_hedge.lock();
+                        if hedge.done || shared.internals.lock().closed {
+                            return;
+                        }
+                        // a hedge which would have to wait for a slot isn't worth starting
+                        let permit = match shared.connect_permit(SlotWait::Never) {
+                            Some(permit) => permit,
+                            None => return,
+                        };
+                        hedge.running += 1;
+                        permit
+                    };
+                    let conn = open(&shared, permit, attempt, reason, &tag);
+                    finish_hedged(&shared, &new_hedge, conn, attempt, reason, tag);
+                });
+                hedge
+            });
+
+            let conn = open(&shared, permit, attempt, reason, &tag);
+            match hedge {
+                Some(hedge) => finish_hedged(&shared, &hedge, conn, attempt, reason, tag),
+                None => match conn {
+                    Ok(new) => finish_connection(&shared, new),
+                    Err(retry_after) => inner(
+                        attempt.saturating_add(1),
+                        &shared,
+                        thread_pool,
+                        reason,
+                        tag,
+                        retry_after,
+                    ),
+                },
+            }
+        });
+    }
+
+    // attempts to open and set up a connection, recording any error and
+    // returning the manager's retry hint for it
+    fn open<M>(
+        shared: &Arc<SharedPool<M>>,
+        permit: ConnectPermit<'_>,
+        attempt: u32,
+        reason: ConnectReason,
+        tag: &Option<Arc<str>>,
+    ) -> Result<NewConn<M::Connection>, Option<Duration>>
+    where
+        M: ManageConnection,
+    {
+        let config = shared.config();
+        let (manager, generation) = shared.connector();
+        let context = ConnectContext {
+            attempt,
+            deadline: connect_deadline(&config, None),
+            reason,
+            tag: tag.clone(),
+        };
+        match connect(shared, &*manager, &context, permit, config.connect_timeout) {
+            Ok((conn, connect_duration)) => Ok(NewConn {
+                conn,
+                connect_duration,
+                generation,
+            }),
+            Err(ConnectError::TooSlow(elapsed)) => {
+                connect_failed(shared, too_slow_message(elapsed));
+                Err(None)
+            }
+            Err(ConnectError::Manager(err)) => {
+                pool_log!(
+                    debug,
+                    pool: shared,
+                    "connection attempt {} failed: {}",
+                    attempt + 1,
+                    err
+                );
+                let retry_after = manager.retry_after(&err);
+                connect_failed(shared, err.to_string());
+                config.error_handler.handle_error(err);
+                Err(retry_after)
+            }
+        }
+    }
+
+    // the first hedged attempt to succeed completes the pending connection,
+    // and the last to fail retries if none did
+    fn finish_hedged<M>(
+        shared: &Arc<SharedPool<M>>,
+        hedge: &Mutex<Hedge>,
+        conn: Result<NewConn<M::Connection>, Option<Duration>>,
+        attempt: u32,
+        reason: ConnectReason,
+        tag: Option<Arc<str>>,
+    ) where
+        M: ManageConnection,
+    {
+        let mut hedge = hedge.lock();
+        hedge.running -= 1;
+        if hedge.done {
+            drop(hedge);
+            if let Ok(mut new) = conn {
+                shared.manager().on_destroy(&mut new.conn);
+                disconnect(shared, new.conn);
+            }
+            return;
+        }
+        match conn {
+            Ok(new) => {
+                hedge.done = true;
+                drop(hedge);
+                finish_connection(shared, new);
+            }
+            Err(retry_after) => {
+                hedge.retry_after = cmp::max(hedge.retry_after, retry_after);
+                if hedge.running > 0 {
+                    return;
+                }
+                hedge.done = true;
+                let retry_after = hedge.retry_after;
+                drop(hedge);
+                if let Some(ref thread_pool) = shared.config().thread_pool {
+                    inner(
+                        attempt.saturating_add(1),
+                        shared,
+                        thread_pool.clone(),
+                        reason,
+                        tag,
+                        retry_after,
+                    );
+                }
+            }
+        }
+    }
+}
+
+struct Hedge {
+    done: bool,
+    running: u32,
+    retry_after: Option<Duration>,
+}
+
+// a newly established connection which has not yet been added to the pool
+struct NewConn<C> {
+    conn: C,
+    connect_duration: Duration,
+    // the generation of the pool when the connection attempt started
+    generation: u64,
+}
+
+// adds a newly established connection to the idle set, completing a pending connection
+fn finish_connection<M>(shared: &Arc<SharedPool<M>>, new: NewConn<M::Connection>)
+where
+    M: ManageConnection,
+{
+    let NewConn {
+        conn,
+        connect_duration,
+        generation,
+    } = new;
+
+    let id = CONNECTION_ID.fetch_add(1, Ordering::Relaxed) as u64;
+
+    pool_log!(debug, pool: shared, "opened connection {}", id);
+    shared.stats.record_created();
+    record_health(shared, true);
+    let event = AcquireEvent {
+        id,
+        connect_duration,
+        pool_name: shared.pool_name(),
+    };
+    shared.publish(Event::Acquire(event));
+    let now = Instant::now();
+
+    {
+        let mut internals = shared.internals.lock();
+        // into_inner collects connections which finish while it waits
+        let closed = internals.closed && !internals.reclaiming;
+        // the manager was swapped or the pool cleared while connecting
+        let stale = generation != internals.generation;
+        if closed || stale {
+            internals.pending_conns -= 1;
+            if closed {
+                shared.cond.notify_all();
+            } else {
+                establish_idle_connections(shared, &mut internals, ConnectReason::Replacement);
+            }
+            drop(internals);
+            shared.stats.record_closed(1);
+            let conn = release_conn(shared, conn, id, now);
+            disconnect(shared, conn);
+            return;
+        }
+    }
+
+    let tags = shared.manager().tags(&conn);
+    let expires_at = shared
+        .manager()
+        .expires_at(&conn)
+        .map(|expires_at| retire_at(now, expires_at));
+    let mut internals = shared.internals.lock();
+    internals.last_error = None;
+    let conn = IdleConn {
+        conn: Conn {
+            conn,
+            extensions: Extensions::new(),
+            birth: now,
+            lifetime_jitter: lifetime_jitter(&shared.config()),
+            uses: 0,
+            generation,
+            id,
+            tags,
+            expires_at,
+            busy: Duration::from_secs(0),
+            last_validation: None,
+        },
+        idle_start: now,
+    };
+    internals.conns.push(conn);
+    internals.pending_conns -= 1;
+    internals.num_conns += 1;
+    record_idle(shared, &internals);
+    notify_waiter(shared, &internals);
+    shared.ready.notify_all();
+    check_recovered(shared, &mut internals);
+}
+
+// ends an exhaustion episode once a connection is left idle with nobody waiting for it
+fn check_recovered<M>(
+    shared: &SharedPool<M>,
+    internals: &mut MutexGuard<PoolInternals<M::Connection>>,
+) where
+    M: ManageConnection,
+{
+    if internals.waiters > 0 || internals.conns.is_empty() {
+        return;
+    }
+    if let Some(since) = internals.exhausted_since.take() {
+        let event = RecoveredEvent {
+            pool_name: shared.pool_name(),
+            duration: since.elapsed(),
+        };
+        pool_log!(info, pool: shared, "pool recovered after {:?}", event.duration);
+        MutexGuard::unlocked(internals, || shared.publish(Event::Recovered(event)));
+    }
+}
+
+// the number of recent connection attempts and health checks the health of
+// the pool is derived from
+const HEALTH_WINDOW: u32 = 8;
+
+// how many of those must have failed for the pool to be unhealthy
+const HEALTH_FAILURE_THRESHOLD: u32 = 3;
+
+// updates the health of the pool after a connection attempt or health check
+fn record_health<M>(shared: &SharedPool<M>, ok: bool)
+where
+    M: ManageConnection,
+{
+    // the common case of a healthy pool doesn't need the lock
+    if ok && shared.no_recent_failures.load(Ordering::Acquire) {
+        return;
+    }
+
+    let mut internals = shared.internals.lock();
+    internals.outcomes = (internals.outcomes << 1 | !ok as u32) & ((1 << HEALTH_WINDOW) - 1);
+    shared
+        .no_recent_failures
+        .store(internals.outcomes == 0, Ordering::Release);
+    let health = if internals.outcomes.count_ones() < HEALTH_FAILURE_THRESHOLD {
+        Health::Healthy
+    } else if internals.num_conns == 0 {
+        Health::Unavailable
+    } else {
+        Health::Degraded
+    };
+    if health == internals.health {
+        return;
+    }
+    let event = HealthChangeEvent {
+        pool_name: shared.pool_name(),
+        from: internals.health,
+        to: health,
+    };
+    internals.health = health;
+    pool_log!(
+        info,
+        pool: shared,
+        "pool health changed from {:?} to {:?}",
+        event.from,
+        event.to
+    );
+    MutexGuard::unlocked(&mut internals, || {
+        shared.publish(Event::HealthChange(event))
+    });
+}
+
+// the most a connection is retired ahead of `ManageConnection::expires_at`
+const EXPIRY_MARGIN: Duration = Duration::from_secs(5);
+
+// retires a connection a tenth of its lifetime before it expires, up to
+// `EXPIRY_MARGIN`, so it isn't checked out just as it expires
+fn retire_at(birth: Instant, expires_at: Instant) -> Instant {
+    let lifetime = expires_at.saturating_duration_since(birth);
+    expires_at - cmp::min(lifetime / 10, EXPIRY_MARGIN)
+}
+
+// sets up a newly opened connection with the customizer, closing it if that fails
+fn acquire<M>(
+    shared: &Arc<SharedPool<M>>,
+    manager: &M,
+    mut conn: M::Connection,
+) -> Result<M::Connection, M::Error>
+where
+    M: ManageConnection,
+{
+    match shared.config().connection_customizer.on_acquire(&mut conn) {
+        Ok(()) => Ok(conn),
+        Err(e) => {
+            close_unacquired(shared, manager, conn);
+            Err(e)
+        }
+    }
+}
+
+// the deadline passed to `ManageConnection::connect_with_context`, which is
+// `connect_timeout` from now or the caller's deadline, whichever is sooner
+fn connect_deadline<C, E>(config: &Config<C, E>, deadline: Option<Instant>) -> Option<Instant> {
+    let timeout = config.connect_timeout.map(|t| Instant::now() + t);
+    match (timeout, deadline) {
+        (Some(timeout), Some(deadline)) => Some(cmp::min(timeout, deadline)),
+        (timeout, deadline) => timeout.or(deadline),
+    }
+}
+
+enum ConnectError<E> {
+    Manager(E),
+    // the connection took longer than `connect_timeout` and was closed
+    TooSlow(Duration),
+}
+
+fn too_slow_message(elapsed: Duration) -> String {
+    format!(
+        "connection took {:?} to establish, exceeding the connect timeout",
+        elapsed
+    )
+}
+
+// opens a connection and sets it up with the customizer, closing it if it
+// took longer than `connect_timeout`, and returns how long connecting took
+fn connect<M>(
+    shared: &Arc<SharedPool<M>>,
+    manager: &M,
+    context: &ConnectContext,
+    permit: ConnectPermit<'_>,
+    connect_timeout: Option<Duration>,
+) -> Result<(M::Connection, Duration), ConnectError<M::Error>>
+where
+    M: ManageConnection,
+{
+    let start = Instant::now();
+    let conn = manager.connect_with_context(context);
+    let elapsed = start.elapsed();
+    drop(permit);
+    shared.stats.record_connect(elapsed);
+
+    let conn = conn.map_err(ConnectError::Manager)?;
+    if let Some(connect_timeout) = connect_timeout {
+        if elapsed > connect_timeout {
+            close_unacquired(shared, manager, conn);
+            return Err(ConnectError::TooSlow(elapsed));
+        }
+    }
+    acquire(shared, manager, conn)
+        .map(|conn| (conn, elapsed))
+        .map_err(ConnectError::Manager)
+}
+
+// records a failed attempt to open a pooled connection
+fn connect_failed<M>(shared: &SharedPool<M>, message: String)
+where
+    M: ManageConnection,
+{
+    shared.stats.record_connect_failure();
+    record_health(shared, false);
+    shared.internals.lock().set_last_error(message);
+}
+
+// opens connections on the calling thread until there are `count` of them
+fn connect_inline<M>(
+    shared: &Arc<SharedPool<M>>,
+    count: u32,
+    reason: ConnectReason,
+    tag: Option<&str>,
+    deadline: Instant,
+    timeout: fn(Option<String>) -> Error,
+) -> Result<(), Error>
+where
+    M: ManageConnection,
+{
+    loop {
+        {
+            let mut internals = shared.internals.lock();
+            if internals.closed || internals.num_conns + internals.pending_conns >= count {
+                return Ok(());
+            }
+            internals.pending_conns += 1;
+        }
+
+        let permit = match shared.connect_permit(SlotWait::Until(deadline)) {
+            Some(permit) => permit,
+            None => {
+                let msg = "timed out waiting for another connection attempt to finish".to_string();
+                let mut internals = shared.internals.lock();
+                internals.pending_conns -= 1;
+                internals.set_last_error(msg.clone());
+                return Err(timeout(Some(msg)));
+            }
+        };
+        let config = shared.config();
+        let (manager, generation) = shared.connector();
+        let context = ConnectContext {
+            attempt: 0,
+            deadline: connect_deadline(&config, Some(deadline)),
+            reason,
+            tag: tag.map(Arc::from),
+        };
+        let msg = match connect(shared, &*manager, &context, permit, config.connect_timeout) {
+            Ok((conn, connect_duration)) => {
+                finish_connection(
+                    shared,
+                    NewConn {
+                        conn,
+                        connect_duration,
+                        generation,
+                    },
+                );
+                continue;
+            }
+            Err(ConnectError::TooSlow(elapsed)) => too_slow_message(elapsed),
+            Err(ConnectError::Manager(err)) => {
+                let msg = err.to_string();
+                config.error_handler.handle_error(err);
+                msg
+            }
+        };
+        connect_failed(shared, msg.clone());
+        shared.internals.lock().pending_conns -= 1;
+        return Err(Error::connect(msg));
+    }
+}
+
+fn reap_connections<M>(shared: &Weak<SharedPool<M>>)
+where
+    M: ManageConnection,
+{
+    let shared = match shared.upgrade() {
+        Some(shared) => shared,
+        None => return,
+    };
+
+    let config = shared.config();
+    let mut old = Vec::with_capacity(config.max_size as usize);
+    let mut to_drop = vec![];
+
+    let mut internals = shared.internals.lock();
+    mem::swap(&mut old, &mut internals.conns);
+    let now = Instant::now();
+    for conn in old {
+        let idle = match config.idle_timeout {
+            Some(timeout) => now - conn.idle_start >= timeout,
+            None => false,
+        };
+        let expired = conn.conn.expired(&config, now);
+        if expired {
+            shared.stats.record_expired();
+        } else if idle {
+            shared.stats.record_idle();
+        }
+        if idle || expired || conn.conn.generation != internals.generation {
+            to_drop.push(conn.conn);
+        } else {
+            internals.conns.push(conn);
+        }
+    }
+    if !to_drop.is_empty() {
+        pool_log!(debug, pool: shared, "reaping {} connections", to_drop.len());
+    }
+    drop_conns(&shared, internals, to_drop);
+
+    if let Some(threshold) = config.leak_detection_threshold {
+        detect_leaks(&shared, threshold);
+    }
+}
+
+fn detect_leaks<M>(shared: &SharedPool<M>, threshold: Duration)
+where
+    M: ManageConnection,
+{
+    let mut leaks = vec![];
+    for (&id, lease) in shared.leases.lock().iter_mut() {
+        let duration = lease.checkout.elapsed();
+        if !lease.reported && duration >= threshold {
+            lease.reported = true;
+            leaks.push(LeakEvent {
+                pool_name: shared.pool_name(),
+                id,
+                duration,
+                backtrace: lease.backtrace.clone(),
+            });
+        }
+    }
+
+    for event in leaks {
+        pool_log!(
+            warn,
+            pool: shared,
+            "connection {} has been checked out for {:?} and may have leaked",
+            event.id,
+            event.duration
+        );
+        shared.publish(Event::Leak(event));
+    }
+}
+
+fn validate_idle_connections<M>(shared: &Weak<SharedPool<M>>)
+where
+    M: ManageConnection,
+{
+    let shared = match shared.upgrade() {
+        Some(shared) => shared,
+        None => return,
+    };
+
+    let mut unchecked = {
+        let internals = shared.internals.lock();
+        internals
+            .conns
+            .iter()
+            .map(|conn| conn.conn.id)
+            .collect::<Vec<_>>()
+    };
+
+    // connections are checked one at a time so that the rest stay available
+    while let Some(id) = unchecked.pop() {
+        let conn = {
+            let mut internals = shared.internals.lock();
+            match internals.conns.iter().position(|conn| conn.conn.id == id) {
+                Some(i) => {
+                    let conn = internals.conns.remove(i);
+                    record_idle(&shared, &internals);
+                    conn
+                }
+                // checked out or closed in the meantime
+                None => continue,
+            }
+        };
+
+        check_idle(&shared, conn, |manager, conn| manager.deep_check(conn));
+    }
+}
+
+// checks a connection taken from the idle list, returning it to the list if it
+// passes and closing it otherwise
+fn check_idle<M, F>(shared: &Arc<SharedPool<M>>, mut conn: IdleConn<M::Connection>, check: F)
+where
+    M: ManageConnection,
+    F: FnOnce(&M, &mut M::Connection) -> Result<(), M::Error>,
+{
+    let start = Instant::now();
+    match check(&shared.manager(), &mut conn.conn.conn) {
+        Ok(()) => {
+            shared.stats.record_validation(conn.conn.validated(start));
+            record_health(shared, true);
+            let mut internals = shared.internals.lock();
+            if internals.closed || conn.conn.generation != internals.generation {
+                drop_conns(shared, internals, vec![conn.conn]);
+                return;
+            }
+            // it has been idle all along, so it goes below anything returned in the meantime
+            internals.conns.insert(0, conn);
+            record_idle(shared, &internals);
+            notify_waiter(shared, &internals);
+        }
+        Err(e) => {
+            shared.validation_failed(conn.conn.id, start, e.to_string());
+            let error = e.to_string();
+            shared.config().error_handler.handle_error(e);
+            shared.stats.record_broken();
+            let mut internals = shared.internals.lock();
+            internals.set_last_error(error);
+            drop_conns(shared, internals, vec![conn.conn]);
+        }
+    }
+}
+
+/// A generic connection pool.
+pub struct Pool<M>(Arc<SharedPool<M>>)
+where
+    M: ManageConnection;
+
+/// Returns a new `Pool` referencing the same state as `self`.
+impl<M> Clone for Pool<M>
+where
+    M: ManageConnection,
+{
+    fn clone(&self) -> Pool<M> {
+        Pool(self.0.clone())
+    }
+}
+
+/// A weak reference to a `Pool`.
+///
+/// A `WeakPool` does not keep the pool alive. It is created with
+/// `Pool::downgrade`.
+pub struct WeakPool<M>(Weak<SharedPool<M>>)
+where
+    M: ManageConnection;
+
+impl<M> Clone for WeakPool<M>
+where
+    M: ManageConnection,
+{
+    fn clone(&self) -> WeakPool<M> {
+        WeakPool(self.0.clone())
+    }
+}
+
+impl<M> fmt::Debug for WeakPool<M>
+where
+    M: ManageConnection,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("(WeakPool)")
+    }
+}
+
+impl<M> WeakPool<M>
+where
+    M: ManageConnection,
+{
+    /// Returns a `Pool` if the pool has not yet been dropped.
+    pub fn upgrade(&self) -> Option<Pool<M>> {
+        self.0.upgrade().map(Pool)
+    }
+}
+
+// manual to avoid bounds on M
+impl<M> fmt::Debug for Pool<M>
+where
+    M: ManageConnection,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let (state, waiters) = {
+            let internals = self.0.internals.lock();
+            (state(&self.0, &internals), internals.waiters)
+        };
+        fmt.debug_struct("Pool")
+            .field("state", &state)
+            .field("waiters", &waiters)
+            .field("config", &self.0.config())
+            .finish()
+    }
+}
+
+impl<M> Pool<M>
+where
+    M: ManageConnection,
+{
+    /// Creates a new connection pool with a default configuration.
+    pub fn new(manager: M) -> Result<Pool<M>, Error> {
+        Pool::builder().build(manager)
+    }
+
+    /// Returns a builder type to configure a new pool.
+    pub fn builder() -> Builder<M> {
+        Builder::new()
+    }
+
+    /// Returns the connection manager currently used to open new connections.
+    ///
+    /// After a call to `swap_manager` this is the new manager, while the
+    /// returned handle keeps the old one alive for as long as it is held.
+    pub fn manager(&self) -> Arc<M> {
+        self.0.manager()
     }
 
-    /// Returns a builder type to configure a new pool.
-    pub fn builder() -> Builder<M> {
-        Builder::new()
+    /// Replaces the connection manager used to open new connections.
+    ///
+    /// Connections opened by the old manager are closed as they are returned
+    /// to the pool, or when they are next selected while idle, and replaced
+    /// with connections from the new manager. This can be used to pick up
+    /// rotated credentials without dropping traffic.
+    pub fn swap_manager(&self, manager: M) -> Arc<M> {
+        // connections opened from here on must see both changes or neither
+        let mut internals = self.0.internals.lock();
+        let old = mem::replace(&mut *self.0.manager.write(), Arc::new(manager));
+        internals.generation += 1;
+        old
+    }
+
+    /// Creates a weak reference to the pool which does not keep it alive.
+    pub fn downgrade(&self) -> WeakPool<M> {
+        WeakPool(Arc::downgrade(&self.0))
     }
 
-    // for testing
-    fn new_inner(
-        config: Config<M::Connection, M::Error>,
-        manager: M,
-        reaper_rate: Duration,
-    ) -> Pool<M> {
+    fn new_inner(config: Config<M::Connection, M::Error>, manager: M) -> Pool<M> {
+        let manager = Arc::new(manager);
         let internals = PoolInternals {
             conns: Vec::with_capacity(config.max_size as usize),
             num_conns: 0,
             pending_conns: 0,
+            waiters: 0,
+            tagged_waiters: vec![],
+            wait_starts: vec![],
+            waiter_queue: VecDeque::new(),
+            async_waiters: VecDeque::new(),
             last_error: None,
+            recent_error: None,
+            closed: false,
+            reclaiming: false,
+            exhausted_since: None,
+            health: Health::Healthy,
+            outcomes: 0,
+            in_use: HashMap::new(),
+            generation: 0,
+            jobs: vec![],
         };
 
         let shared = Arc::new(SharedPool {
-            config,
-            manager,
+            id: POOL_ID.fetch_add(1, Ordering::Relaxed) as u64,
+            config: RwLock::new(Arc::new(config)),
+            manager: RwLock::new(manager),
             internals: Mutex::new(internals),
             cond: Condvar::new(),
+            ready: Condvar::new(),
+            extensions: RwLock::new(Extensions::new()),
+            subscribers: Mutex::new(vec![]),
+            has_subscribers: AtomicBool::new(false),
+            batch: Mutex::new(()),
+            idle_count: AtomicUsize::new(0),
+            waiter_count: AtomicUsize::new(0),
+            connecting: Mutex::new(ConnectSlots {
+                count: 0,
+                queued: VecDeque::new(),
+            }),
+            connect_slot: Condvar::new(),
+            connects_in_flight: AtomicU32::new(0),
+            stats: Stats::default(),
+            leases: Mutex::new(HashMap::new()),
+            no_recent_failures: AtomicBool::new(true),
         });
 
-        establish_idle_connections(&shared, &mut shared.internals.lock());
+        establish_idle_connections(
+            &shared,
+            &mut shared.internals.lock(),
+            ConnectReason::Initialization,
+        );
 
-        if shared.config.max_lifetime.is_some() || shared.config.idle_timeout.is_some() {
+        let config = shared.config();
+        if let Some(ref thread_pool) = config.thread_pool {
+            // the reaper always runs since update_config can enable idle_timeout or max_lifetime
             let s = Arc::downgrade(&shared);
-            shared
-                .config
-                .thread_pool
-                .execute_at_fixed_rate(reaper_rate, reaper_rate, move || reap_connections(&s));
+            let rate = config.maintenance_rate;
+            let job = thread_pool.execute_at_fixed_rate(rate, rate, move || reap_connections(&s));
+            shared.internals.lock().jobs.push(job);
+
+            if let Some(interval) = config.test_while_idle {
+                let s = Arc::downgrade(&shared);
+                let job = thread_pool.execute_at_fixed_rate(interval, interval, move || {
+                    validate_idle_connections(&s)
+                });
+                shared.internals.lock().jobs.push(job);
+            }
+
+            if let Some(ref sink) = config.stats_sink {
+                let s = Arc::downgrade(&shared);
+                let sink = sink.clone();
+                let interval = config.stats_interval;
+                let job = thread_pool.execute_at_fixed_rate(interval, interval, move || {
+                    if let Some(shared) = s.upgrade() {
+                        sink.report(&Pool(shared).stats_snapshot());
+                    }
+                });
+                shared.internals.lock().jobs.push(job);
+            }
         }
 
         Pool(shared)
     }
 
     fn wait_for_initialization(&self) -> Result<(), Error> {
-        let end = Instant::now() + self.0.config.connection_timeout;
+        let config = self.0.config();
+        let timeout = config
+            .initialization_timeout
+            .unwrap_or(config.connection_timeout);
+        let initial_size = initial_size(&config);
+        if config.synchronous {
+            let deadline = Instant::now() + timeout;
+            return connect_inline(
+                &self.0,
+                initial_size,
+                ConnectReason::Initialization,
+                None,
+                deadline,
+                Error::initialization,
+            );
+        }
+        self.wait_until_open(initial_size, timeout)
+    }
+
+    /// Waits for the pool to have at least `count` connections which have
+    /// passed a health check.
+    ///
+    /// This is useful for readiness checks on pools created with
+    /// `Builder::build_unchecked`. Idle connections which have not been
+    /// checked yet are tested with `ManageConnection::is_valid`, and those
+    /// which fail are closed and replaced.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pool does not reach `count` healthy connections
+    /// within `timeout`, or if the pool is closed.
+    pub fn wait_until_ready(&self, count: u32, timeout: Duration) -> Result<(), Error> {
+        let end = Instant::now() + timeout;
         let mut internals = self.0.internals.lock();
 
-        let initial_size = self.0.config.min_idle.unwrap_or(self.0.config.max_size);
+        loop {
+            if internals.closed {
+                return Err(Error::closed());
+            }
+            let idle = internals
+                .conns
+                .iter()
+                .filter(|conn| conn.conn.last_validation.is_some())
+                .count();
+            let in_use = internals
+                .in_use
+                .values()
+                .filter(|(stats, _)| stats.last_validated_at.is_some())
+                .count();
+            if (idle + in_use) as u32 >= count {
+                return Ok(());
+            }
 
-        while internals.num_conns != initial_size {
-            if self.0.cond.wait_until(&mut internals, end).timed_out() {
-                return Err(Error(internals.last_error.take()));
+            let unchecked = internals
+                .conns
+                .iter()
+                .position(|conn| conn.conn.last_validation.is_none());
+            match unchecked {
+                Some(i) => {
+                    let conn = internals.conns.remove(i);
+                    record_idle(&self.0, &internals);
+                    drop(internals);
+                    check_idle(&self.0, conn, |manager, conn| {
+                        manager.is_valid_with_deadline(conn, Some(end))
+                    });
+                    internals = self.0.internals.lock();
+                }
+                None => {
+                    if self.0.ready.wait_until(&mut internals, end).timed_out() {
+                        return Err(Error::initialization(internals.last_error.take()));
+                    }
+                }
+            }
+        }
+    }
+
+    // waits for the pool to have at least `count` open connections
+    fn wait_until_open(&self, count: u32, timeout: Duration) -> Result<(), Error> {
+        let end = Instant::now() + timeout;
+        let mut internals = self.0.internals.lock();
+
+        while internals.num_conns < count {
+            if internals.closed {
+                return Err(Error::closed());
+            }
+            if self.0.ready.wait_until(&mut internals, end).timed_out() {
+                return Err(Error::initialization(internals.last_error.take()));
             }
         }
 
         Ok(())
     }
 
+    /// Opens connections until the pool has at least `count` of them, waiting
+    /// for at most `timeout`.
+    ///
+    /// `count` is capped at the pool's `max_size`. Connections beyond
+    /// `min_idle` are still subject to the pool's `idle_timeout`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connections are not established within
+    /// `timeout`, or if the pool is closed.
+    pub fn prewarm(&self, count: u32, timeout: Duration) -> Result<(), Error> {
+        let config = self.0.config();
+        let count = cmp::min(count, config.max_size);
+        if config.synchronous {
+            let deadline = Instant::now() + timeout;
+            return connect_inline(
+                &self.0,
+                count,
+                ConnectReason::Refill,
+                None,
+                deadline,
+                Error::initialization,
+            );
+        }
+        {
+            let mut internals = self.0.internals.lock();
+            for _ in internals.num_conns + internals.pending_conns..count {
+                add_connection(&self.0, &mut internals, ConnectReason::Refill);
+            }
+        }
+        self.wait_until_open(count, timeout)
+    }
+
+    /// Returns `true` if the pool has opened its initial set of connections.
+    ///
+    /// The initial set is the pool's `warmup_count`, which defaults to
+    /// `min_idle`.
+    pub fn is_ready(&self) -> bool {
+        let initial_size = initial_size(&self.0.config());
+        !self.is_closed() && self.0.internals.lock().num_conns >= initial_size
+    }
+
     /// Retrieves a connection from the pool.
     ///
-    /// Waits for at most the configured connection timeout before returning an
-    /// error.
+    /// Waits for at most the configured wait timeout, or the connection
+    /// timeout if there is none, before returning an error.
     pub fn get(&self) -> Result<PooledConnection<M>, Error> {
-        self.get_timeout(self.0.config.connection_timeout)
+        let config = self.0.config();
+        self.get_timeout(config.wait_timeout.unwrap_or(config.connection_timeout))
     }
 
     /// Retrieves a connection from the pool, waiting for at most `timeout`
     ///
-    /// The given timeout will be used instead of the configured connection
-    /// timeout.
+    /// The given timeout will be used instead of the configured wait timeout.
     pub fn get_timeout(&self, timeout: Duration) -> Result<PooledConnection<M>, Error> {
+        self.get_inner(timeout, None)
+    }
+
+    /// Retrieves a connection with the specified tag from the pool.
+    ///
+    /// Tags are assigned by `ManageConnection::tags`. New connections are
+    /// opened as needed just as for `get`, but only idle connections with a
+    /// matching tag are returned. If the pool is full and no idle connection
+    /// has the tag, the one which has been idle longest is closed to make room
+    /// for a new connection, which the manager is asked to open with the tag
+    /// through `ConnectContext::tag`. Waits for as long as `get` before
+    /// returning an error.
+    pub fn get_tagged(&self, tag: &str) -> Result<PooledConnection<M>, Error> {
+        let config = self.0.config();
+        self.get_inner(
+            config.wait_timeout.unwrap_or(config.connection_timeout),
+            Some(tag),
+        )
+    }
+
+    fn get_inner(
+        &self,
+        timeout: Duration,
+        tag: Option<&str>,
+    ) -> Result<PooledConnection<M>, Error> {
+        let config = self.0.config();
         let start = Instant::now();
         let end = start + timeout;
         let mut internals = self.0.internals.lock();
+        let mut first = true;
+
+        // in fair mode, callers queue up and only the one at the front may take a connection
+        // tagged callers can't take just any connection, so they don't queue
+        let waiter = if config.fair_queueing && tag.is_none() {
+            Some(Arc::new(Waiter::new()))
+        } else {
+            None
+        };
+        // tagged callers wait separately so that they're only woken for connections they can use
+        let tagged_waiter = tag.map(|_| Arc::new(Condvar::new()));
+        let mut queued = false;
+        // a tagged caller evicts at most one idle connection
+        let mut can_evict = true;
 
         loop {
-            match self.try_get_inner(internals) {
-                Ok(conn) => {
-                    let event = CheckoutEvent {
-                        id: conn.conn.as_ref().unwrap().id,
-                        duration: start.elapsed(),
-                    };
-                    self.0.config.event_handler.handle_checkout(event);
-                    return Ok(conn);
+            if internals.closed {
+                if let Some(ref waiter) = waiter {
+                    internals.waiter_queue.retain(|w| !Arc::ptr_eq(w, waiter));
                 }
-                Err(i) => internals = i,
+                return Err(Error::closed());
             }
 
-            add_connection(&self.0, &mut internals);
+            let turn = match (internals.waiter_queue.front(), &waiter) {
+                (None, _) => true,
+                (Some(front), Some(waiter)) => Arc::ptr_eq(front, waiter),
+                (Some(_), None) => false,
+            };
+
+            if turn {
+                if queued {
+                    internals.waiter_queue.pop_front();
+                    queued = false;
+                    if internals.conns.len() > 1 {
+                        notify_waiter(&self.0, &internals);
+                    }
+                }
 
-            if self.0.cond.wait_until(&mut internals, end).timed_out() {
-                let event = TimeoutEvent { timeout };
-                self.0.config.event_handler.handle_timeout(event);
+                match self.try_get_inner(&config, internals, Some(end), tag) {
+                    Ok(conn) => {
+                        let inner = conn.conn.as_ref().unwrap();
+                        let id = inner.id;
+                        let validation_duration = inner.validated_since(start);
+                        let duration = start.elapsed();
+                        self.0.stats.record_checkout_wait(duration);
+                        let event = CheckoutEvent {
+                            pool_name: self.0.pool_name(),
+                            id,
+                            duration,
+                            validation_duration,
+                            waiters: self.0.waiter_count.load(Ordering::Relaxed) as u32,
+                        };
+                        self.0.publish(Event::Checkout(event));
+                        if let Some(threshold) = config.slow_checkout_threshold {
+                            if duration > threshold {
+                                self.slow_checkout(id, duration);
+                            }
+                        }
+                        return Ok(conn);
+                    }
+                    Err(i) => internals = i,
+                }
+
+                let count = internals.num_conns + internals.pending_conns;
+                if config.synchronous && count < config.max_size {
+                    drop(internals);
+                    connect_inline(
+                        &self.0,
+                        count + 1,
+                        ConnectReason::Refill,
+                        tag,
+                        end,
+                        Error::timeout,
+                    )?;
+                    internals = self.0.internals.lock();
+                    continue;
+                }
+
+                // every idle connection has another tag and there's no room for
+                // a new one, so replace the one which has been idle longest
+                if tag.is_some()
+                    && can_evict
+                    && !internals.conns.is_empty()
+                    && count >= config.max_size
+                {
+                    can_evict = false;
+                    let conn = internals.conns.remove(0).conn;
+                    internals.num_conns -= 1;
+                    self.0.stats.record_closed(1);
+                    record_idle(&self.0, &internals);
+                    let conns =
+                        MutexGuard::unlocked(&mut internals, || release_conns(&self.0, vec![conn]));
+                    add_tagged_connection(
+                        &self.0,
+                        &mut internals,
+                        ConnectReason::Replacement,
+                        tag.map(Arc::from),
+                    );
+                    MutexGuard::unlocked(&mut internals, || {
+                        for conn in conns {
+                            disconnect(&self.0, conn);
+                        }
+                    });
+                    continue;
+                }
+            }
+
+            add_tagged_connection(
+                &self.0,
+                &mut internals,
+                ConnectReason::Refill,
+                tag.map(Arc::from),
+            );
+
+            if first {
+                if let Some(max_waiters) = config.max_waiters {
+                    if internals.waiters >= max_waiters {
+                        return Err(Error::saturated());
+                    }
+                }
+                first = false;
+            }
+
+            let cond = match (&waiter, &tagged_waiter) {
+                (Some(waiter), _) => {
+                    if !queued {
+                        // a caller which was already at the front keeps its place
+                        if turn {
+                            internals.waiter_queue.push_front(waiter.clone());
+                        } else {
+                            internals.waiter_queue.push_back(waiter.clone());
+                        }
+                        queued = true;
+                    }
+                    &waiter.cond
+                }
+                (None, Some(tagged_waiter)) => &**tagged_waiter,
+                (None, None) => &self.0.cond,
+            };
+
+            if internals.exhausted_since.is_none()
+                && internals.conns.is_empty()
+                && internals.num_conns >= config.max_size
+            {
+                internals.exhausted_since = Some(Instant::now());
+                let event = ExhaustedEvent {
+                    pool_name: self.0.pool_name(),
+                    state: state(&self.0, &internals),
+                };
+                pool_log!(warn, pool: self.0, "pool exhausted: {:?}", event.state);
+                MutexGuard::unlocked(&mut internals, || self.0.publish(Event::Exhausted(event)));
+                continue;
+            }
+
+            internals.waiters += 1;
+            if let (Some(tag), Some(tagged_waiter)) = (tag, &tagged_waiter) {
+                internals.tagged_waiters.push(TaggedWaiter {
+                    tag: Arc::from(tag),
+                    cond: tagged_waiter.clone(),
+                    can_evict,
+                });
+            }
+            internals.wait_starts.push(start);
+            record_waiters(&self.0, &internals);
+            let timed_out = cond.wait_until(&mut internals, end).timed_out();
+            internals.waiters -= 1;
+            if let Some(ref tagged_waiter) = tagged_waiter {
+                internals
+                    .tagged_waiters
+                    .retain(|w| !Arc::ptr_eq(&w.cond, tagged_waiter));
+            }
+            if let Some(i) = internals.wait_starts.iter().position(|&s| s == start) {
+                internals.wait_starts.swap_remove(i);
+            }
+            record_waiters(&self.0, &internals);
+
+            if timed_out {
+                if let Some(ref waiter) = waiter {
+                    internals.waiter_queue.retain(|w| !Arc::ptr_eq(w, waiter));
+                    if !internals.conns.is_empty() {
+                        notify_waiter(&self.0, &internals);
+                    }
+                }
+
+                pool_log!(
+                    warn,
+                    pool: self.0,
+                    "timed out after {:?} waiting for a connection",
+                    timeout
+                );
+                self.0.stats.record_timeout(start.elapsed());
+                let checked_out = self.checked_out();
+                #[cfg(feature = "log")]
+                for conn in &checked_out {
+                    if let Some(ref backtrace) = conn.backtrace {
+                        log::warn!(
+                            "{}connection {} has been checked out for {:?} from:\n{}",
+                            self.0.log_prefix(),
+                            conn.id,
+                            conn.duration,
+                            backtrace
+                        );
+                    }
+                }
+                let event = TimeoutEvent {
+                    pool_name: self.0.pool_name(),
+                    timeout,
+                    waiters: internals.waiters,
+                    checked_out,
+                };
+                self.0.publish(Event::Timeout(event));
+
+                return Err(Error::timeout(internals.last_error.take()));
+            }
+        }
+    }
+
+    /// Opens a new connection which is not managed by the pool.
+    ///
+    /// The connection is set up by the pool's connection customizer, but does
+    /// not count against `max_size` and is not returned to the pool when
+    /// dropped. The attempt does count against `max_concurrent_connects`, and
+    /// is given a deadline derived from `connect_timeout`.
+    pub fn dedicated_connection(&self) -> Result<M::Connection, M::Error> {
+        let config = self.0.config();
+        // waiting without a deadline always ends with a permit
+        let permit = self.0.connect_permit(SlotWait::Forever).unwrap();
+        let manager = self.0.manager();
+        let context = ConnectContext {
+            attempt: 0,
+            deadline: connect_deadline(&config, None),
+            reason: ConnectReason::Dedicated,
+            tag: None,
+        };
+        match connect(&self.0, &*manager, &context, permit, None) {
+            Ok((conn, _)) => Ok(conn),
+            Err(ConnectError::Manager(e)) => Err(e),
+            Err(ConnectError::TooSlow(_)) => unreachable!("connect_timeout isn't enforced"),
+        }
+    }
+
+    /// Runs a closure with a connection from the pool.
+    ///
+    /// The connection is returned to the pool if the closure succeeds. If it
+    /// returns an error, the connection is assumed to be in an unknown state
+    /// and is closed instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns the closure's error, or an error converted from this crate's
+    /// `Error` if a connection could not be checked out.
+    pub fn with<F, T, E>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&mut M::Connection) -> Result<T, E>,
+        E: From<Error>,
+    {
+        let mut conn = self.get()?;
+        let r = f(&mut conn);
+        if r.is_err() {
+            PooledConnection::invalidate(&mut conn);
+        }
+        r
+    }
 
-                return Err(Error(internals.last_error.take()));
+    /// Like `with`, but retries the closure on a fresh connection up to
+    /// `retries` times if it returns an error.
+    ///
+    /// The closure should only return an error for failures which are safe to
+    /// retry. Errors checking out a connection are not retried.
+    pub fn with_retries<F, T, E>(&self, retries: u32, mut f: F) -> Result<T, E>
+    where
+        F: FnMut(&mut M::Connection) -> Result<T, E>,
+        E: From<Error>,
+    {
+        let mut attempt = 0;
+        loop {
+            let mut conn = self.get()?;
+            match f(&mut conn) {
+                Err(_) if attempt < retries => {
+                    PooledConnection::invalidate(&mut conn);
+                    attempt += 1;
+                }
+                r => {
+                    if r.is_err() {
+                        PooledConnection::invalidate(&mut conn);
+                    }
+                    return r;
+                }
             }
         }
     }
 
+    /// Returns a future which resolves to a connection from the pool.
+    ///
+    /// Unlike `get`, waiting for a connection does not block the calling
+    /// thread. Connections are opened, checked out and tested on the pool's
+    /// worker threads. Waiting futures count towards `max_waiters` and take
+    /// their turn with blocked callers if `fair_queueing` is set. A
+    /// synchronous pool has no worker threads, so that work is done by the
+    /// thread polling the future instead. Managers which are themselves
+    /// asynchronous can be used through `manager::AsyncManager`.
+    ///
+    /// The future does not time out on its own, so callers should apply
+    /// their runtime's timeout to it.
+    pub fn get_async(&self) -> GetFuture<M> {
+        GetFuture {
+            pool: self.clone(),
+            start: Instant::now(),
+            waiter: None,
+            fair: false,
+            admitted: false,
+            check: None,
+        }
+    }
+
+    /// Retrieves a connection from the pool, waiting until at most `deadline`.
+    ///
+    /// This is useful when a single deadline applies to several operations.
+    /// If the deadline has already passed, an idle connection is still
+    /// returned if one is immediately available.
+    pub fn get_deadline(&self, deadline: Instant) -> Result<PooledConnection<M>, Error> {
+        self.get_timeout(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Retrieves `count` connections from the pool, waiting for at most
+    /// `timeout`.
+    ///
+    /// Either all of the connections are returned, or none are. Callers of
+    /// this method take turns, so two batches cannot each hold part of the
+    /// connections the other needs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `count` connections cannot be checked out within
+    /// `timeout`. This always happens if `count` exceeds the pool's
+    /// `max_size`.
+    pub fn get_many(
+        &self,
+        count: u32,
+        timeout: Duration,
+    ) -> Result<Vec<PooledConnection<M>>, Error> {
+        let deadline = Instant::now() + timeout;
+        let _batch = match self.0.batch.try_lock_until(deadline) {
+            Some(batch) => batch,
+            None => return Err(Error::timeout(None)),
+        };
+
+        // any connections already checked out are returned if this fails
+        (0..count).map(|_| self.get_deadline(deadline)).collect()
+    }
+
     /// Attempts to retrieve a connection from the pool if there is one
     /// available.
     ///
     /// Returns `None` if there are no idle connections available in the pool.
     /// This method will not block waiting to establish a new connection.
     pub fn try_get(&self) -> Option<PooledConnection<M>> {
-        self.try_get_inner(self.0.internals.lock()).ok()
+        let internals = self.0.internals.lock();
+        if internals.closed || !internals.waiter_queue.is_empty() {
+            return None;
+        }
+        self.try_get_inner(&self.0.config(), internals, None, None)
+            .ok()
     }
 
     fn try_get_inner<'a>(
         &'a self,
+        config: &Config<M::Connection, M::Error>,
         mut internals: MutexGuard<'a, PoolInternals<M::Connection>>,
+        deadline: Option<Instant>,
+        tag: Option<&str>,
     ) -> Result<PooledConnection<M>, MutexGuard<'a, PoolInternals<M::Connection>>> {
         loop {
-            if let Some(mut conn) = internals.conns.pop() {
-                establish_idle_connections(&self.0, &mut internals);
+            if let Some(mut conn) = pop_idle(config, &mut internals.conns, tag) {
+                record_idle(&self.0, &internals);
+                let expired = conn.conn.expired(config, Instant::now());
+                if expired || conn.conn.generation != internals.generation {
+                    if expired {
+                        self.0.stats.record_expired();
+                    }
+                    drop_conns(&self.0, internals, vec![conn.conn]);
+                    internals = self.0.internals.lock();
+                    continue;
+                }
+                establish_idle_connections(&self.0, &mut internals, ConnectReason::Refill);
                 drop(internals);
 
-                if self.0.config.test_on_check_out {
-                    if let Err(e) = self.0.manager.is_valid(&mut conn.conn.conn) {
+                if config.test_on_check_out {
+                    let start = Instant::now();
+                    if let Err(e) = self
+                        .0
+                        .manager()
+                        .is_valid_with_deadline(&mut conn.conn.conn, deadline)
+                    {
                         let msg = e.to_string();
-                        self.0.config.error_handler.handle_error(e);
+                        self.0.validation_failed(conn.conn.id, start, msg.clone());
+                        config.error_handler.handle_error(e);
                         // FIXME we shouldn't have to lock, unlock, and relock here
                         internals = self.0.internals.lock();
-                        internals.last_error = Some(msg);
+                        internals.set_last_error(msg);
+                        self.0.stats.record_broken();
                         drop_conns(&self.0, internals, vec![conn.conn]);
                         internals = self.0.internals.lock();
                         continue;
                     }
+                    self.0.stats.record_validation(conn.conn.validated(start));
+                    record_health(&self.0, true);
+                }
+
+                conn.conn.uses += 1;
+                self.0.stats.record_checkout();
+
+                let checkout = Instant::now();
+                {
+                    let mut internals = self.0.internals.lock();
+                    internals
+                        .in_use
+                        .insert(conn.conn.id, (conn.conn.statistics(true), checkout));
+                    self.0.stats.record_in_use(internals.in_use.len());
+                }
+                if tracks_leases(config) {
+                    let backtrace = if config.capture_backtraces {
+                        Backtrace::force_capture()
+                    } else {
+                        Backtrace::capture()
+                    };
+                    let lease = Lease {
+                        checkout,
+                        backtrace: match backtrace.status() {
+                            BacktraceStatus::Captured => Some(Arc::new(backtrace)),
+                            _ => None,
+                        },
+                        reported: false,
+                    };
+                    self.0.leases.lock().insert(conn.conn.id, lease);
                 }
 
                 return Ok(PooledConnection {
                     pool: self.clone(),
-                    checkout: Instant::now(),
+                    checkout,
                     conn: Some(conn.conn),
+                    invalid: false,
                 });
             } else {
                 return Err(internals);
@@ -486,18 +2552,115 @@ where
         }
     }
 
-    fn put_back(&self, checkout: Instant, mut conn: Conn<M::Connection>) {
+    fn slow_checkout(&self, id: u64, duration: Duration) {
+        let state = self.state();
+        pool_log!(
+            warn,
+            pool: self.0,
+            "checkout of connection {} waited {:?}: {:?}",
+            id,
+            duration,
+            state
+        );
+        let event = SlowCheckoutEvent {
+            pool_name: self.0.pool_name(),
+            id,
+            duration,
+            state,
+        };
+        self.0.publish(Event::SlowCheckout(event));
+    }
+
+    fn end_lease(&self, id: u64) {
+        if tracks_leases(&self.0.config()) {
+            self.0.leases.lock().remove(&id);
+        }
+    }
+
+    /// Returns the connections which are currently checked out of the pool.
+    ///
+    /// Checkouts are only tracked if `leak_detection_threshold` or
+    /// `capture_backtraces` is set, and this returns an empty list otherwise.
+    /// The backtrace of each checkout is included if `capture_backtraces` is
+    /// set. Connections are listed from the longest to the most recently
+    /// checked out.
+    pub fn checked_out(&self) -> Vec<CheckedOutConnection> {
+        let mut conns = self
+            .0
+            .leases
+            .lock()
+            .iter()
+            .map(|(&id, lease)| CheckedOutConnection {
+                id,
+                duration: lease.checkout.elapsed(),
+                backtrace: lease.backtrace.clone(),
+            })
+            .collect::<Vec<_>>();
+        conns.sort_by_key(|conn| cmp::Reverse(conn.duration));
+        conns
+    }
+
+    fn put_back(&self, checkout: Instant, mut conn: Conn<M::Connection>, invalid: bool) {
+        self.end_lease(conn.id);
+        let duration = checkout.elapsed();
+        conn.busy += duration;
         let event = CheckinEvent {
+            pool_name: self.0.pool_name(),
             id: conn.id,
-            duration: checkout.elapsed(),
+            duration,
         };
-        self.0.config.event_handler.handle_checkin(event);
+        self.0.publish(Event::Checkin(event));
 
+        let config = self.0.config();
         // This is specified to be fast, but call it before locking anyways
-        let broken = self.0.manager.has_broken(&mut conn.conn);
+        let mut broken = invalid
+            || self.0.manager().has_broken(&mut conn.conn)
+            || config
+                .connection_customizer
+                .discard_on_return(&mut conn.conn);
+        let expired = conn.expired(&config, Instant::now());
+
+        let mut error = None;
+        if !broken && !expired {
+            if let Err(e) = config.connection_customizer.on_check_in(&mut conn.conn) {
+                error = Some(e.to_string());
+                config.error_handler.handle_error(e);
+                broken = true;
+            }
+        }
+        if !broken && !expired && config.test_on_check_in {
+            let start = Instant::now();
+            if let Err(e) = self
+                .0
+                .manager()
+                .is_valid_with_deadline(&mut conn.conn, None)
+            {
+                self.0.validation_failed(conn.id, start, e.to_string());
+                error = Some(e.to_string());
+                config.error_handler.handle_error(e);
+                broken = true;
+            } else {
+                self.0.stats.record_validation(conn.validated(start));
+                record_health(&self.0, true);
+            }
+        }
 
         let mut internals = self.0.internals.lock();
+        internals.in_use.remove(&conn.id);
+        if let Some(error) = error {
+            internals.set_last_error(error);
+        }
         if broken {
+            self.0.stats.record_broken();
+        } else if expired {
+            self.0.stats.record_expired();
+        }
+        if broken
+            || expired
+            || (internals.closed && !internals.reclaiming)
+            || conn.generation != internals.generation
+            || internals.num_conns > config.max_size
+        {
             drop_conns(&self.0, internals, vec![conn]);
         } else {
             let conn = IdleConn {
@@ -505,78 +2668,598 @@ where
                 idle_start: Instant::now(),
             };
             internals.conns.push(conn);
-            self.0.cond.notify_one();
+            record_idle(&self.0, &internals);
+            notify_waiter(&self.0, &internals);
+            self.0.ready.notify_all();
+            check_recovered(&self.0, &mut internals);
+        }
+    }
+
+    /// Changes the configuration of the pool while it is running.
+    ///
+    /// Existing connections are kept. If the pool has more connections than
+    /// the new `max_size`, idle connections are closed immediately and checked
+    /// out connections are closed when they are returned. New connections are
+    /// opened in the background to reach a larger `min_idle` or to serve
+    /// callers waiting for a connection.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error and leaves the configuration unchanged if the updated
+    /// configuration is invalid.
+    pub fn update_config(&self, update: ConfigUpdate) -> Result<(), Error> {
+        {
+            let mut config = self.0.config.write();
+            let mut new_config = Config::clone(&config);
+            update.apply(&mut new_config)?;
+            *config = Arc::new(new_config);
+        }
+
+        let max_size = self.0.config().max_size;
+        let mut internals = self.0.internals.lock();
+        let excess = internals.num_conns.saturating_sub(max_size) as usize;
+        let excess = cmp::min(excess, internals.conns.len());
+        let to_drop = internals
+            .conns
+            .drain(..excess)
+            .map(|conn| conn.conn)
+            .collect::<Vec<_>>();
+        for _ in 0..internals.waiters {
+            add_connection(&self.0, &mut internals, ConnectReason::Refill);
+        }
+        drop_conns(&self.0, internals, to_drop);
+
+        Ok(())
+    }
+
+    /// Changes the maximum number of connections managed by the pool.
+    ///
+    /// This is shorthand for `update_config` with only `max_size` changed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `max_size` is zero or smaller than the pool's
+    /// `min_idle`.
+    pub fn resize(&self, max_size: u32) -> Result<(), Error> {
+        self.update_config(ConfigUpdate::new().max_size(max_size))
+    }
+
+    /// Closes all idle connections in the pool.
+    ///
+    /// Connections which are currently checked out will be closed when they
+    /// are returned rather than being reused. New connections are opened in
+    /// the background to maintain `min_idle`. This is useful after a database
+    /// failover, when all existing connections point at the old server.
+    pub fn clear(&self) {
+        let mut internals = self.0.internals.lock();
+        internals.generation += 1;
+        let conns = internals.conns.drain(..).map(|conn| conn.conn).collect();
+        drop_conns(&self.0, internals, conns);
+    }
+
+    /// Shuts down the pool.
+    ///
+    /// New checkouts fail immediately with an error, idle connections are
+    /// closed, and the pool's background maintenance tasks are cancelled. This
+    /// method then waits up to `timeout` for checked out connections to be
+    /// returned and for pending connection attempts to finish, closing each of
+    /// them as it does.
+    ///
+    /// Returns `true` if every connection was closed before the timeout
+    /// expired. Connections returned after that are closed as they come back.
+    pub fn close(&self, timeout: Duration) -> bool {
+        self.shut_down(timeout, None)
+    }
+
+    /// Shuts down the pool, returning its connections instead of closing them.
+    ///
+    /// This behaves like `close`, but idle connections and connections
+    /// returned within `timeout` are handed back to the caller. This allows
+    /// connections to be shut down in ways that their `Drop` implementations
+    /// cannot, such as with a protocol-level goodbye message. Connections which
+    /// are returned after the timeout expires are closed as usual.
+    pub fn into_inner(self, timeout: Duration) -> Vec<M::Connection> {
+        let mut conns = vec![];
+        self.shut_down(timeout, Some(&mut conns));
+
+        conns
+            .into_iter()
+            .map(|conn| {
+                let event = ReleaseEvent {
+                    pool_name: self.0.pool_name(),
+                    id: conn.id,
+                    age: conn.birth.elapsed(),
+                };
+                self.0.publish(Event::Release(event));
+                conn.conn
+            })
+            .collect()
+    }
+
+    fn shut_down(
+        &self,
+        timeout: Duration,
+        mut reclaim: Option<&mut Vec<Conn<M::Connection>>>,
+    ) -> bool {
+        let end = Instant::now() + timeout;
+        let mut internals = self.0.internals.lock();
+        if !internals.closed {
+            internals.closed = true;
+            pool_log!(info, pool: self.0, "closing connection pool");
+            for job in internals.jobs.drain(..) {
+                job.cancel();
+            }
+            // wake every waiting caller so it can fail
+            self.0.cond.notify_all();
+            self.0.ready.notify_all();
+            for waiter in internals
+                .waiter_queue
+                .iter()
+                .chain(&internals.async_waiters)
+            {
+                waiter.notify();
+            }
+            for waiter in &internals.tagged_waiters {
+                waiter.cond.notify_one();
+            }
+        }
+
+        internals.reclaiming = reclaim.is_some();
+        let done = loop {
+            if !internals.conns.is_empty() {
+                let conns = internals
+                    .conns
+                    .drain(..)
+                    .map(|conn| conn.conn)
+                    .collect::<Vec<_>>();
+                match reclaim {
+                    Some(ref mut reclaimed) => {
+                        internals.num_conns -= conns.len() as u32;
+                        record_idle(&self.0, &internals);
+                        reclaimed.extend(conns);
+                    }
+                    None => {
+                        drop_conns(&self.0, internals, conns);
+                        internals = self.0.internals.lock();
+                    }
+                }
+                continue;
+            }
+            if internals.num_conns == 0 && internals.pending_conns == 0 {
+                break true;
+            }
+            if self.0.cond.wait_until(&mut internals, end).timed_out() {
+                break false;
+            }
+        };
+        internals.reclaiming = false;
+        done
+    }
+
+    /// Returns `true` if the pool has been shut down with `close`.
+    pub fn is_closed(&self) -> bool {
+        self.0.internals.lock().closed
+    }
+
+    fn detach(&self, checkout: Instant, conn: Conn<M::Connection>) -> M::Connection {
+        self.end_lease(conn.id);
+        let event = CheckinEvent {
+            pool_name: self.0.pool_name(),
+            id: conn.id,
+            duration: checkout.elapsed(),
+        };
+        self.0.publish(Event::Checkin(event));
+        let event = ReleaseEvent {
+            pool_name: self.0.pool_name(),
+            id: conn.id,
+            age: conn.birth.elapsed(),
+        };
+        self.0.publish(Event::Release(event));
+
+        let mut internals = self.0.internals.lock();
+        internals.in_use.remove(&conn.id);
+        internals.num_conns -= 1;
+        establish_idle_connections(&self.0, &mut internals, ConnectReason::Replacement);
+        if internals.closed {
+            self.0.cond.notify_all();
         }
+
+        conn.conn
+    }
+
+    /// Returns a receiver of the pool's events.
+    ///
+    /// Every event passed to the pool's event handler is also sent to each
+    /// subscriber. Events are buffered without limit, so the receiver should
+    /// be drained promptly. Dropping the receiver unsubscribes it.
+    pub fn subscribe(&self) -> mpsc::Receiver<Event> {
+        let (tx, rx) = mpsc::channel();
+        let mut subscribers = self.0.subscribers.lock();
+        subscribers.push(tx);
+        self.0.has_subscribers.store(true, Ordering::Relaxed);
+        rx
+    }
+
+    /// Returns a shared reference to the extensions associated with the pool.
+    ///
+    /// Extensions can be used to attach application data, such as a service
+    /// name or metrics handles, to a pool. The pool is locked against calls to
+    /// `extensions_mut` while the returned reference is alive.
+    pub fn extensions(&self) -> impl Deref<Target = Extensions> + '_ {
+        self.0.extensions.read()
+    }
+
+    /// Returns a mutable reference to the extensions associated with the pool.
+    pub fn extensions_mut(&self) -> impl DerefMut<Target = Extensions> + '_ {
+        self.0.extensions.write()
+    }
+
+    /// Returns the number of idle connections which can be checked out without
+    /// waiting.
+    ///
+    /// Unlike `state`, this does not lock the pool, so the value may be
+    /// slightly out of date by the time it is used.
+    pub fn idle_available(&self) -> u32 {
+        self.0.idle_count.load(Ordering::Relaxed) as u32
     }
 
     /// Returns information about the current state of the pool.
     pub fn state(&self) -> State {
+        state(&self.0, &self.0.internals.lock())
+    }
+
+    /// Returns cumulative statistics about the pool.
+    ///
+    /// The counters are monotonic over the lifetime of the pool, so rates can
+    /// be computed from the difference between two snapshots. Checkout wait
+    /// times only cover `get`, `get_timeout` and the other blocking checkout
+    /// methods.
+    pub fn statistics(&self) -> Statistics {
+        self.0.stats.snapshot()
+    }
+
+    /// Returns the most recent error encountered while opening or checking
+    /// the health of a connection, if there has been one.
+    ///
+    /// Unlike the error reported by a checkout timeout, this is kept after
+    /// the pool recovers until a newer error replaces it.
+    pub fn last_error(&self) -> Option<LastError> {
+        self.0.internals.lock().recent_error.clone()
+    }
+
+    /// Resets the high-watermarks reported by `statistics` to the pool's
+    /// current usage.
+    ///
+    /// The other statistics are not affected.
+    pub fn reset_watermarks(&self) {
+        let internals = self.0.internals.lock();
+        self.0
+            .stats
+            .reset_watermarks(internals.in_use.len(), internals.waiters);
+    }
+
+    /// Returns the health of the pool.
+    ///
+    /// The pool is unhealthy once 3 of its 8 most recent connection attempts
+    /// and health checks have failed, so that an occasional failure doesn't
+    /// make it flap. Changes are reported to
+    /// `HandleEvent::handle_health_change`.
+    pub fn health(&self) -> Health {
+        self.0.internals.lock().health
+    }
+
+    /// Returns statistics about each connection currently managed by the pool.
+    ///
+    /// Connections are listed in no particular order. Connections which are
+    /// being opened are not included.
+    pub fn connection_statistics(&self) -> Vec<ConnectionStatistics> {
         let internals = self.0.internals.lock();
-        State {
-            connections: internals.num_conns,
-            idle_connections: internals.conns.len() as u32,
-            _p: (),
+        let idle = internals
+            .conns
+            .iter()
+            .map(|conn| conn.conn.statistics(false));
+        let in_use = internals.in_use.values().map(|(stats, checkout)| {
+            let mut stats = stats.clone();
+            stats.busy += checkout.elapsed();
+            stats
+        });
+        idle.chain(in_use).collect()
+    }
+
+    /// Returns a snapshot of the pool's state and statistics.
+    ///
+    /// This is the snapshot which is sent to the pool's `StatsSink`.
+    pub fn stats_snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            taken_at: Instant::now(),
+            pool_id: self.0.id,
+            pool_name: self.0.pool_name(),
+            state: self.state(),
+            statistics: self.statistics(),
+            connections: self.connection_statistics(),
+        }
+    }
+
+    /// Starts periodically sending the pool's statistics to a StatsD server.
+    ///
+    /// Reports are sent from the pool's thread pool until the pool is closed
+    /// or dropped. Since they are sent over UDP, failures are ignored. This
+    /// has no effect on a synchronous pool, which has no background threads.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the reporter's interval is 0.
+    pub fn report_statsd(&self, reporter: StatsdReporter) -> Result<(), Error> {
+        let interval = reporter.report_interval();
+        if interval == Duration::from_secs(0) {
+            return Err(Error::config("statsd interval must be positive"));
         }
+        let thread_pool = match self.0.config().thread_pool {
+            Some(ref thread_pool) => thread_pool.clone(),
+            None => return Ok(()),
+        };
+        let s = Arc::downgrade(&self.0);
+        let job = thread_pool.execute_at_fixed_rate(interval, interval, move || {
+            if let Some(shared) = s.upgrade() {
+                reporter.report(&Pool(shared).stats_snapshot());
+            }
+        });
+        self.0.internals.lock().jobs.push(job);
+        Ok(())
     }
 
     /// Returns the configured maximum pool size.
     pub fn max_size(&self) -> u32 {
-        self.0.config.max_size
+        self.0.config().max_size
     }
 
     /// Returns the configured mimimum idle connection count.
     pub fn min_idle(&self) -> Option<u32> {
-        self.0.config.min_idle
+        self.0.config().min_idle
+    }
+
+    /// Returns the configured warm-up connection count.
+    pub fn warmup_count(&self) -> Option<u32> {
+        self.0.config().warmup_count
     }
 
     /// Returns if the pool is configured to test connections on check out.
     pub fn test_on_check_out(&self) -> bool {
-        self.0.config.test_on_check_out
+        self.0.config().test_on_check_out
+    }
+
+    /// Returns if the pool is configured to test connections on check in.
+    pub fn test_on_check_in(&self) -> bool {
+        self.0.config().test_on_check_in
+    }
+
+    /// Returns the configured idle connection test interval.
+    pub fn test_while_idle(&self) -> Option<Duration> {
+        self.0.config().test_while_idle
     }
 
     /// Returns the configured maximum connection lifetime.
     pub fn max_lifetime(&self) -> Option<Duration> {
-        self.0.config.max_lifetime
+        self.0.config().max_lifetime
+    }
+
+    /// Returns the configured maximum connection lifetime jitter.
+    pub fn lifetime_jitter(&self) -> Option<Duration> {
+        self.0.config().lifetime_jitter
+    }
+
+    /// Returns the configured maximum number of checkouts per connection.
+    pub fn max_uses(&self) -> Option<u32> {
+        self.0.config().max_uses
     }
 
     /// Returns the configured idle connection timeout.
     pub fn idle_timeout(&self) -> Option<Duration> {
-        self.0.config.idle_timeout
+        self.0.config().idle_timeout
+    }
+
+    /// Returns the configured maintenance rate.
+    pub fn maintenance_rate(&self) -> Duration {
+        self.0.config().maintenance_rate
+    }
+
+    /// Returns if the pool is configured to hand out connections in the order
+    /// callers started waiting for them.
+    pub fn fair_queueing(&self) -> bool {
+        self.0.config().fair_queueing
+    }
+
+    /// Returns the configured idle connection selection strategy.
+    pub fn idle_strategy(&self) -> IdleStrategy {
+        self.0.config().idle_strategy
+    }
+
+    /// Returns if the pool is configured to run without background threads.
+    pub fn synchronous(&self) -> bool {
+        self.0.config().synchronous
+    }
+
+    /// Returns the configured maximum number of waiting callers.
+    pub fn max_waiters(&self) -> Option<u32> {
+        self.0.config().max_waiters
+    }
+
+    /// Returns the configured maximum number of concurrent connection
+    /// attempts.
+    pub fn max_concurrent_connects(&self) -> Option<u32> {
+        self.0.config().max_concurrent_connects
+    }
+
+    /// Returns if connections are disconnected on the pool's helper threads.
+    pub fn background_disconnect(&self) -> bool {
+        self.0.config().background_disconnect
+    }
+
+    /// Returns the configured leak detection threshold.
+    pub fn leak_detection_threshold(&self) -> Option<Duration> {
+        self.0.config().leak_detection_threshold
+    }
+
+    /// Returns the configured slow checkout threshold.
+    pub fn slow_checkout_threshold(&self) -> Option<Duration> {
+        self.0.config().slow_checkout_threshold
+    }
+
+    /// Returns the configured stats interval.
+    pub fn stats_interval(&self) -> Duration {
+        self.0.config().stats_interval
+    }
+
+    /// Returns if the backtrace of every checkout is captured.
+    pub fn capture_backtraces(&self) -> bool {
+        self.0.config().capture_backtraces
+    }
+
+    /// Returns the name of the pool, if it has one.
+    pub fn pool_name(&self) -> Option<String> {
+        self.0.pool_name().map(|name| name.to_string())
+    }
+
+    /// Returns the configured hedge delay.
+    pub fn hedge_delay(&self) -> Option<Duration> {
+        self.0.config().hedge_delay
+    }
+
+    /// Returns the configured connect timeout.
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        self.0.config().connect_timeout
+    }
+
+    /// Returns the configured initialization timeout.
+    pub fn initialization_timeout(&self) -> Option<Duration> {
+        self.0.config().initialization_timeout
     }
 
     /// Returns the configured connection timeout.
     pub fn connection_timeout(&self) -> Duration {
-        self.0.config.connection_timeout
+        self.0.config().connection_timeout
+    }
+
+    /// Returns the configured wait timeout.
+    pub fn wait_timeout(&self) -> Option<Duration> {
+        self.0.config().wait_timeout
     }
 }
 
 /// The error type returned by methods in this crate.
 #[derive(Debug)]
-pub struct Error(Option<String>);
+pub struct Error(ErrorKind);
+
+#[derive(Debug)]
+enum ErrorKind {
+    Timeout(Option<String>),
+    Initialization(Option<String>),
+    Connect(String),
+    Config(&'static str),
+    Env { var: String, value: String },
+    Saturated,
+    Closed,
+}
+
+impl Error {
+    fn timeout(last_error: Option<String>) -> Error {
+        Error(ErrorKind::Timeout(last_error))
+    }
+
+    fn initialization(last_error: Option<String>) -> Error {
+        Error(ErrorKind::Initialization(last_error))
+    }
+
+    fn connect(error: String) -> Error {
+        Error(ErrorKind::Connect(error))
+    }
+
+    fn config(message: &'static str) -> Error {
+        Error(ErrorKind::Config(message))
+    }
+
+    fn env(var: String, value: String) -> Error {
+        Error(ErrorKind::Env { var, value })
+    }
+
+    fn saturated() -> Error {
+        Error(ErrorKind::Saturated)
+    }
+
+    fn closed() -> Error {
+        Error(ErrorKind::Closed)
+    }
+}
 
 impl fmt::Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt.write_str(error::Error::description(self))?;
-        if let Some(ref err) = self.0 {
-            write!(fmt, ": {}", err)?;
+        match self.0 {
+            ErrorKind::Timeout(ref last_error) => {
+                fmt.write_str("timed out waiting for connection")?;
+                if let Some(ref err) = *last_error {
+                    write!(fmt, ": {}", err)?;
+                }
+                Ok(())
+            }
+            ErrorKind::Initialization(ref last_error) => {
+                fmt.write_str("timed out waiting for pool initialization")?;
+                if let Some(ref err) = *last_error {
+                    write!(fmt, ": {}", err)?;
+                }
+                Ok(())
+            }
+            ErrorKind::Connect(ref err) => write!(fmt, "unable to open connection: {}", err),
+            ErrorKind::Config(message) => write!(fmt, "invalid pool configuration: {}", message),
+            ErrorKind::Env { ref var, ref value } => write!(
+                fmt,
+                "invalid pool configuration: unable to parse {} value `{}`",
+                var, value
+            ),
+            ErrorKind::Saturated => fmt.write_str("too many callers waiting for a connection"),
+            ErrorKind::Closed => fmt.write_str("connection pool closed"),
         }
-        Ok(())
     }
 }
 
-impl error::Error for Error {
-    fn description(&self) -> &str {
-        "timed out waiting for connection"
+impl error::Error for Error {}
+
+fn state<M>(shared: &SharedPool<M>, internals: &PoolInternals<M::Connection>) -> State
+where
+    M: ManageConnection,
+{
+    let idle_connections = internals.conns.len() as u32;
+    State {
+        connections: internals.num_conns,
+        idle_connections,
+        in_use_connections: internals.num_conns - idle_connections,
+        pending_connections: internals.pending_conns,
+        connecting: shared.connects_in_flight.load(Ordering::Relaxed),
+        waiters: internals.waiters,
+        longest_wait: internals.wait_starts.iter().min().map(Instant::elapsed),
     }
 }
 
 /// Information about the state of a `Pool`.
+#[derive(Copy, Clone)]
+#[non_exhaustive]
 pub struct State {
     /// The number of connections currently being managed by the pool.
     pub connections: u32,
     /// The number of idle connections.
     pub idle_connections: u32,
-    _p: (),
+    /// The number of connections currently checked out of the pool.
+    pub in_use_connections: u32,
+    /// The number of connections currently being established.
+    pub pending_connections: u32,
+    /// The number of calls to `ManageConnection::connect` currently running.
+    ///
+    /// Unlike `pending_connections`, this does not include connections
+    /// waiting to be retried or for a `max_concurrent_connects` slot.
+    pub connecting: u32,
+    /// The number of callers blocked waiting for a connection.
+    pub waiters: u32,
+    /// How long the longest waiting caller has been waiting.
+    pub longest_wait: Option<Duration>,
 }
 
 impl fmt::Debug for State {
@@ -584,11 +3267,59 @@ impl fmt::Debug for State {
         fmt.debug_struct("State")
             .field("connections", &self.connections)
             .field("idle_connections", &self.idle_connections)
+            .field("in_use_connections", &self.in_use_connections)
+            .field("pending_connections", &self.pending_connections)
+            .field("connecting", &self.connecting)
+            .field("waiters", &self.waiters)
+            .field("longest_wait", &self.longest_wait)
             .finish()
     }
 }
 
+/// An error encountered by a `Pool` while opening or checking the health of a
+/// connection.
+///
+/// See `Pool::last_error`.
+#[derive(Clone, Debug)]
+pub struct LastError {
+    message: String,
+    occurred_at: Instant,
+}
+
+impl LastError {
+    /// Returns the error message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the time at which the error occurred.
+    pub fn occurred_at(&self) -> Instant {
+        self.occurred_at
+    }
+}
+
+/// The health of a `Pool`, derived from the outcomes of its most recent
+/// connection attempts and health checks.
+///
+/// See `Pool::health`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Health {
+    /// Most recent connection attempts and health checks succeeded.
+    Healthy,
+    /// Recent connection attempts or health checks have failed, but the pool
+    /// still has open connections.
+    Degraded,
+    /// Recent connection attempts or health checks have failed, and the pool
+    /// has no open connections.
+    Unavailable,
+}
+
 /// A smart pointer wrapping a connection.
+///
+/// The connection keeps its pool alive, so it does not borrow from the `Pool`
+/// it was checked out of and can be moved to other threads or stored in
+/// long-lived structures.
 pub struct PooledConnection<M>
 where
     M: ManageConnection,
@@ -596,15 +3327,21 @@ where
     pool: Pool<M>,
     checkout: Instant,
     conn: Option<Conn<M::Connection>>,
+    invalid: bool,
 }
 
+// manual to avoid bounds on M
 impl<M> fmt::Debug for PooledConnection<M>
 where
     M: ManageConnection,
-    M::Connection: fmt::Debug,
 {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Debug::fmt(&self.conn.as_ref().unwrap().conn, fmt)
+        let conn = self.conn.as_ref().unwrap();
+        fmt.debug_struct("PooledConnection")
+            .field("id", &conn.id)
+            .field("age", &conn.birth.elapsed())
+            .field("checked_out", &self.checkout.elapsed())
+            .finish()
     }
 }
 
@@ -613,7 +3350,9 @@ where
     M: ManageConnection,
 {
     fn drop(&mut self) {
-        self.pool.put_back(self.checkout, self.conn.take().unwrap());
+        if let Some(conn) = self.conn.take() {
+            self.pool.put_back(self.checkout, conn, self.invalid);
+        }
     }
 }
 
@@ -641,6 +3380,11 @@ impl<M> PooledConnection<M>
 where
     M: ManageConnection,
 {
+    /// Returns the tags of this connection.
+    pub fn tags(this: &Self) -> &[String] {
+        &this.conn.as_ref().unwrap().tags
+    }
+
     /// Returns a shared reference to the extensions associated with this connection.
     pub fn extensions(this: &Self) -> &Extensions {
         &this.conn.as_ref().unwrap().extensions
@@ -650,4 +3394,449 @@ where
     pub fn extensions_mut(this: &mut Self) -> &mut Extensions {
         &mut this.conn.as_mut().unwrap().extensions
     }
+
+    /// Returns the ID of the connection.
+    ///
+    /// This is shorthand for `PooledConnection::info(this).id()`.
+    pub fn id(this: &Self) -> u64 {
+        this.conn.as_ref().unwrap().id
+    }
+
+    /// Returns the time since the connection was established.
+    pub fn age(this: &Self) -> Duration {
+        this.conn.as_ref().unwrap().birth.elapsed()
+    }
+
+    /// Returns the number of times the connection has been checked out,
+    /// including the current checkout.
+    pub fn checkout_count(this: &Self) -> u32 {
+        this.conn.as_ref().unwrap().uses
+    }
+
+    /// Returns information about the connection.
+    pub fn info(this: &Self) -> ConnectionInfo {
+        let conn = this.conn.as_ref().unwrap();
+        ConnectionInfo {
+            id: conn.id,
+            created_at: conn.birth,
+            checked_out_at: this.checkout,
+            checkouts: conn.uses,
+        }
+    }
+
+    /// Marks the connection as broken.
+    ///
+    /// The connection will be closed rather than returned to the pool when
+    /// this `PooledConnection` is dropped.
+    pub fn invalidate(this: &mut Self) {
+        this.invalid = true;
+    }
+
+    /// Removes the connection from the pool, returning it.
+    ///
+    /// The connection no longer counts against the pool's `max_size`, and the
+    /// pool will open a new connection in its place if needed. This is useful
+    /// for long running operations which should not tie up a pooled
+    /// connection.
+    pub fn detach(mut this: Self) -> M::Connection {
+        let conn = this.conn.take().unwrap();
+        this.pool.detach(this.checkout, conn)
+    }
+
+    /// Makes a new guard for a component of the connection.
+    ///
+    /// The connection is returned to the pool when the new guard is dropped.
+    pub fn map<U, F>(this: Self, f: F) -> MappedPooledConnection<M, U>
+    where
+        U: ?Sized,
+        F: FnOnce(&mut M::Connection) -> &mut U,
+    {
+        // boxing the guard keeps the connection at a stable address. The
+        // projection is taken through the raw pointer rather than the box so
+        // that it isn't invalidated when the box gives up ownership, and the
+        // guard is only accessed through it again once `value` is dead.
+        let guard = BoxedGuard(Box::into_raw(Box::new(this)));
+        // SAFETY: `guard` came from `Box::into_raw`, so it is valid and unique
+        let value = f(unsafe { &mut *guard.0 }) as *mut U;
+        let guard = guard.into_raw();
+        MappedPooledConnection { guard, value }
+    }
+}
+
+// Owns a boxed guard while `PooledConnection::map` runs its closure, so the
+// connection still goes back to the pool if the closure panics.
+struct BoxedGuard<M>(*mut PooledConnection<M>)
+where
+    M: ManageConnection;
+
+impl<M> BoxedGuard<M>
+where
+    M: ManageConnection,
+{
+    fn into_raw(self) -> *mut PooledConnection<M> {
+        let guard = self.0;
+        mem::forget(self);
+        guard
+    }
+}
+
+impl<M> Drop for BoxedGuard<M>
+where
+    M: ManageConnection,
+{
+    fn drop(&mut self) {
+        // SAFETY: the pointer came from `Box::into_raw` and is only freed here
+        // or by the `MappedPooledConnection` it is handed to
+        unsafe {
+            drop(Box::from_raw(self.0));
+        }
+    }
+}
+
+/// A future which resolves to a connection from the pool.
+///
+/// It is created by `Pool::get_async`.
+pub struct GetFuture<M>
+where
+    M: ManageConnection,
+{
+    pool: Pool<M>,
+    start: Instant,
+    // set while the future is waiting in one of the pool's queues
+    waiter: Option<Arc<Waiter>>,
+    // whether that queue is the fair queue
+    fair: bool,
+    // whether the future has been let in past `max_waiters`
+    admitted: bool,
+    // set while a connection is being checked out on the pool's threads
+    check: Option<Arc<Mutex<Check<M>>>>,
+}
+
+// a checkout run on the pool's threads on behalf of a `GetFuture`
+struct Check<M>
+where
+    M: ManageConnection,
+{
+    done: bool,
+    conn: Option<PooledConnection<M>>,
+    waker: Option<Waker>,
+}
+
+impl<M> GetFuture<M>
+where
+    M: ManageConnection,
+{
+    // removes the future from its queue, returning whether it was at the front of the fair queue
+    fn deregister(&mut self, internals: &mut PoolInternals<M::Connection>) -> bool {
+        let waiter = match self.waiter.take() {
+            Some(waiter) => waiter,
+            None => return false,
+        };
+        let queue = if self.fair {
+            &mut internals.waiter_queue
+        } else {
+            &mut internals.async_waiters
+        };
+        let front = queue.front().is_some_and(|w| Arc::ptr_eq(w, &waiter));
+        queue.retain(|w| !Arc::ptr_eq(w, &waiter));
+        internals.waiters -= 1;
+        if let Some(i) = internals.wait_starts.iter().position(|&s| s == self.start) {
+            internals.wait_starts.swap_remove(i);
+        }
+        record_waiters(&self.pool.0, internals);
+        self.fair && front
+    }
+
+    fn checked_out(&self, conn: PooledConnection<M>) -> PooledConnection<M> {
+        let inner = conn.conn.as_ref().unwrap();
+        let event = CheckoutEvent {
+            pool_name: self.pool.0.pool_name(),
+            id: inner.id,
+            duration: self.start.elapsed(),
+            validation_duration: inner.validated_since(self.start),
+            waiters: self.pool.0.waiter_count.load(Ordering::Relaxed) as u32,
+        };
+        self.pool.0.publish(Event::Checkout(event));
+        conn
+    }
+}
+
+impl<M> fmt::Debug for GetFuture<M>
+where
+    M: ManageConnection,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("GetFuture")
+            .field("pool", &self.pool)
+            .finish()
+    }
+}
+
+impl<M> Future for GetFuture<M>
+where
+    M: ManageConnection,
+{
+    type Output = Result<PooledConnection<M>, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+
+        if let Some(check) = this.check.take() {
+            let mut guard = check.lock();
+            if !guard.done {
+                guard.waker = Some(cx.waker().clone());
+                drop(guard);
+                this.check = Some(check);
+                return Poll::Pending;
+            }
+            if let Some(conn) = guard.conn.take() {
+                return Poll::Ready(Ok(this.checked_out(conn)));
+            }
+            // the idle connections were taken or failed validation in the meantime
+        }
+
+        let shared = this.pool.0.clone();
+        let mut internals = shared.internals.lock();
+        let mut front = false;
+        loop {
+            if internals.closed {
+                this.deregister(&mut internals);
+                return Poll::Ready(Err(Error::closed()));
+            }
+
+            let config = shared.config();
+            let turn = match (internals.waiter_queue.front(), &this.waiter) {
+                (None, _) => true,
+                (Some(front), Some(waiter)) => Arc::ptr_eq(front, waiter),
+                (Some(_), None) => false,
+            };
+
+            if turn {
+                if !internals.conns.is_empty() {
+                    front = this.deregister(&mut internals);
+                    if front && internals.conns.len() > 1 {
+                        notify_waiter(&shared, &internals);
+                    }
+
+                    // validating the connection or closing stale ones can
+                    // block, so the checkout is left to the pool's threads
+                    if let Some(ref thread_pool) = config.thread_pool {
+                        let check = Arc::new(Mutex::new(Check {
+                            done: false,
+                            conn: None,
+                            waker: Some(cx.waker().clone()),
+                        }));
+                        let pool = this.pool.clone();
+                        let new_check = check.clone();
+                        thread_pool.execute(move || {
+                            let internals = pool.0.internals.lock();
+                            let conn = if internals.closed {
+                                None
+                            } else {
+                                pool.try_get_inner(&pool.0.config(), internals, None, None)
+                                    .ok()
+                            };
+                            let mut check = new_check.lock();
+                            check.done = true;
+                            check.conn = conn;
+                            if let Some(waker) = check.waker.take() {
+                                waker.wake();
+                            }
+                        });
+                        this.check = Some(check);
+                        return Poll::Pending;
+                    }
+                }
+
+                match this.pool.try_get_inner(&config, internals, None, None) {
+                    Ok(conn) => return Poll::Ready(Ok(this.checked_out(conn))),
+                    Err(i) => internals = i,
+                }
+
+                let count = internals.num_conns + internals.pending_conns;
+                if config.synchronous && count < config.max_size {
+                    drop(internals);
+                    let timeout = config.wait_timeout.unwrap_or(config.connection_timeout);
+                    let deadline = Instant::now() + timeout;
+                    if let Err(e) = connect_inline(
+                        &shared,
+                        count + 1,
+                        ConnectReason::Refill,
+                        None,
+                        deadline,
+                        Error::timeout,
+                    ) {
+                        return Poll::Ready(Err(e));
+                    }
+                    internals = shared.internals.lock();
+                    continue;
+                }
+            }
+
+            add_connection(&shared, &mut internals, ConnectReason::Refill);
+
+            let waiter = match this.waiter {
+                Some(ref waiter) => waiter.clone(),
+                None => {
+                    if !this.admitted {
+                        if let Some(max_waiters) = config.max_waiters {
+                            if internals.waiters >= max_waiters {
+                                return Poll::Ready(Err(Error::saturated()));
+                            }
+                        }
+                        this.admitted = true;
+                    }
+                    internals.waiters += 1;
+                    internals.wait_starts.push(this.start);
+                    record_waiters(&shared, &internals);
+                    let waiter = Arc::new(Waiter::new());
+                    this.fair = config.fair_queueing;
+                    if !this.fair {
+                        internals.async_waiters.push_back(waiter.clone());
+                    } else if front {
+                        // a future which was already at the front keeps its place
+                        internals.waiter_queue.push_front(waiter.clone());
+                    } else {
+                        internals.waiter_queue.push_back(waiter.clone());
+                    }
+                    this.waiter = Some(waiter.clone());
+                    waiter
+                }
+            };
+            *waiter.waker.lock() = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+    }
+}
+
+impl<M> Drop for GetFuture<M>
+where
+    M: ManageConnection,
+{
+    fn drop(&mut self) {
+        if self.waiter.is_none() {
+            return;
+        }
+        let shared = self.pool.0.clone();
+        let mut internals = shared.internals.lock();
+        self.deregister(&mut internals);
+        // pass on a wakeup the future may have received
+        if !internals.conns.is_empty() {
+            notify_waiter(&shared, &internals);
+        }
+    }
+}
+
+/// Information about a pooled connection.
+///
+/// It is returned by `PooledConnection::info`.
+#[derive(Copy, Clone, Debug)]
+pub struct ConnectionInfo {
+    id: u64,
+    created_at: Instant,
+    checked_out_at: Instant,
+    checkouts: u32,
+}
+
+impl ConnectionInfo {
+    /// Returns the ID of the connection.
+    ///
+    /// This matches the IDs reported to the pool's event handler.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the time at which the connection was established.
+    pub fn created_at(&self) -> Instant {
+        self.created_at
+    }
+
+    /// Returns the time at which the connection was most recently checked
+    /// out.
+    pub fn checked_out_at(&self) -> Instant {
+        self.checked_out_at
+    }
+
+    /// Returns the number of times the connection has been checked out,
+    /// including the current checkout.
+    pub fn checkouts(&self) -> u32 {
+        self.checkouts
+    }
+}
+
+/// A smart pointer to a component of a pooled connection.
+///
+/// It is created by `PooledConnection::map`.
+pub struct MappedPooledConnection<M, U>
+where
+    M: ManageConnection,
+    U: ?Sized,
+{
+    guard: *mut PooledConnection<M>,
+    value: *mut U,
+}
+
+// SAFETY: `value` only refers to data owned by the guard, which moves with it
+unsafe impl<M, U> Send for MappedPooledConnection<M, U>
+where
+    M: ManageConnection,
+    U: ?Sized + Send,
+{
+}
+
+// SAFETY: a shared reference only gives out `&U`, and the guard itself is
+// not reachable through it
+unsafe impl<M, U> Sync for MappedPooledConnection<M, U>
+where
+    M: ManageConnection,
+    U: ?Sized + Sync,
+{
+}
+
+impl<M, U> Drop for MappedPooledConnection<M, U>
+where
+    M: ManageConnection,
+    U: ?Sized,
+{
+    fn drop(&mut self) {
+        // SAFETY: `guard` came from `Box::into_raw` and is never used again
+        unsafe {
+            drop(Box::from_raw(self.guard));
+        }
+    }
+}
+
+impl<M, U> fmt::Debug for MappedPooledConnection<M, U>
+where
+    M: ManageConnection,
+    U: ?Sized + fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, fmt)
+    }
+}
+
+impl<M, U> Deref for MappedPooledConnection<M, U>
+where
+    M: ManageConnection,
+    U: ?Sized,
+{
+    type Target = U;
+
+    fn deref(&self) -> &U {
+        // SAFETY: `value` was derived from `guard`, which is alive until drop
+        // and not otherwise accessed
+        unsafe { &*self.value }
+    }
+}
+
+impl<M, U> DerefMut for MappedPooledConnection<M, U>
+where
+    M: ManageConnection,
+    U: ?Sized,
+{
+    fn deref_mut(&mut self) -> &mut U {
+        // SAFETY: as for `deref`, and `&mut self` makes the access unique
+        unsafe { &mut *self.value }
+    }
 }
@@ -0,0 +1,1378 @@
+//! `ManageConnection` adapters.
+//!
+//! Each adapter wraps one or more managers and is itself a
+//! `ManageConnection`, so adapters can be layered to combine their behavior:
+//!
+//! ```rust,ignore
+//! let manager = LoggingManager::new(CountingManager::new(FooConnectionManager::new(url)));
+//! let pool = r2d2::Pool::new(manager)?;
+//! ```
+#[cfg(feature = "log")]
+use log::{debug, warn};
+use parking_lot::Mutex;
+use std::error;
+use std::fmt;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{ConnectContext, ConnectReason, Error, ManageConnection, Pool, PooledConnection};
+
+/// A connection opened by one of several wrapped managers.
+///
+/// It dereferences to the underlying connection, and remembers which manager
+/// opened it so that the same manager is used to check its health.
+#[derive(Debug)]
+pub struct HostConnection<C> {
+    conn: C,
+    host: usize,
+}
+
+impl<C> HostConnection<C> {
+    /// Returns the index of the manager which opened the connection.
+    pub fn host(&self) -> usize {
+        self.host
+    }
+
+    /// Consumes the `HostConnection`, returning the underlying connection.
+    pub fn into_inner(self) -> C {
+        self.conn
+    }
+}
+
+impl<C> Deref for HostConnection<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        &self.conn
+    }
+}
+
+impl<C> DerefMut for HostConnection<C> {
+    fn deref_mut(&mut self) -> &mut C {
+        &mut self.conn
+    }
+}
+
+// error counts for one host of a multi-host manager
+#[derive(Debug, Default)]
+struct HostErrors {
+    connect: AtomicUsize,
+    validation: AtomicUsize,
+}
+
+impl HostErrors {
+    fn for_hosts(hosts: usize) -> Vec<HostErrors> {
+        (0..hosts).map(|_| HostErrors::default()).collect()
+    }
+
+    fn validated<E>(&self, result: Result<(), E>) -> Result<(), E> {
+        if result.is_err() {
+            self.validation.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+/// A `ManageConnection` which spreads connections across several equivalent
+/// hosts.
+///
+/// Each connection attempt starts at the next manager in turn. If that manager
+/// fails to connect, the others are tried in order, and the error of the last
+/// one is returned if none of them succeed.
+#[derive(Debug)]
+pub struct MultiHostManager<M> {
+    managers: Vec<M>,
+    next: AtomicUsize,
+    errors: Vec<HostErrors>,
+}
+
+impl<M> MultiHostManager<M>
+where
+    M: ManageConnection,
+{
+    /// Creates a new `MultiHostManager` wrapping one manager for each host.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `managers` is empty.
+    pub fn new(managers: Vec<M>) -> MultiHostManager<M> {
+        assert!(!managers.is_empty(), "at least one manager is required");
+        let errors = HostErrors::for_hosts(managers.len());
+        MultiHostManager {
+            managers,
+            next: AtomicUsize::new(0),
+            errors,
+        }
+    }
+
+    /// Returns the wrapped managers.
+    pub fn managers(&self) -> &[M] {
+        &self.managers
+    }
+
+    /// Returns the number of failed attempts to connect to the host at index
+    /// `host`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `host` is out of bounds.
+    pub fn connect_errors(&self, host: usize) -> usize {
+        self.errors[host].connect.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of times a connection to the host at index `host`
+    /// failed a health check.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `host` is out of bounds.
+    pub fn validation_errors(&self, host: usize) -> usize {
+        self.errors[host].validation.load(Ordering::Relaxed)
+    }
+
+    fn connect_next<F>(&self, connect: F) -> Result<HostConnection<M::Connection>, M::Error>
+    where
+        F: Fn(&M) -> Result<M::Connection, M::Error>,
+    {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let mut error = None;
+        for i in 0..self.managers.len() {
+            let host = start.wrapping_add(i) % self.managers.len();
+            match connect(&self.managers[host]) {
+                Ok(conn) => return Ok(HostConnection { conn, host }),
+                Err(e) => {
+                    self.errors[host].connect.fetch_add(1, Ordering::Relaxed);
+                    error = Some(e);
+                }
+            }
+        }
+        Err(error.unwrap())
+    }
+}
+
+impl<M> ManageConnection for MultiHostManager<M>
+where
+    M: ManageConnection,
+{
+    type Connection = HostConnection<M::Connection>;
+    type Error = M::Error;
+
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        self.connect_next(|manager| manager.connect())
+    }
+
+    fn connect_with_context(
+        &self,
+        context: &ConnectContext,
+    ) -> Result<Self::Connection, Self::Error> {
+        self.connect_next(|manager| manager.connect_with_context(context))
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        let result = self.managers[conn.host].is_valid(&mut conn.conn);
+        self.errors[conn.host].validated(result)
+    }
+
+    fn is_valid_with_deadline(
+        &self,
+        conn: &mut Self::Connection,
+        deadline: Option<Instant>,
+    ) -> Result<(), Self::Error> {
+        let result = self.managers[conn.host].is_valid_with_deadline(&mut conn.conn, deadline);
+        self.errors[conn.host].validated(result)
+    }
+
+    fn deep_check(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        let result = self.managers[conn.host].deep_check(&mut conn.conn);
+        self.errors[conn.host].validated(result)
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        self.managers[conn.host].has_broken(&mut conn.conn)
+    }
+
+    fn retry_after(&self, error: &Self::Error) -> Option<Duration> {
+        self.managers
+            .iter()
+            .find_map(|manager| manager.retry_after(error))
+    }
+
+    fn on_destroy(&self, conn: &mut Self::Connection) {
+        self.managers[conn.host].on_destroy(&mut conn.conn)
+    }
+
+    fn disconnect(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        self.managers[conn.host].disconnect(&mut conn.conn)
+    }
+
+    fn expires_at(&self, conn: &Self::Connection) -> Option<Instant> {
+        self.managers[conn.host].expires_at(&conn.conn)
+    }
+
+    fn tags(&self, conn: &Self::Connection) -> Vec<String> {
+        self.managers[conn.host].tags(&conn.conn)
+    }
+}
+
+/// A `ManageConnection` which prefers a primary manager, falling back to
+/// secondaries when it cannot connect.
+///
+/// Managers are tried in priority order. A manager which fails to connect is
+/// skipped until its `probe_interval` has elapsed, after which the next
+/// connection attempt probes it again, so new connections fail back to the
+/// primary once it recovers. Existing connections to a secondary are kept
+/// until they are closed; set `Builder::max_lifetime` to bound how long that
+/// takes.
+#[derive(Debug)]
+pub struct FailoverManager<M> {
+    managers: Vec<M>,
+    probe_interval: Duration,
+    failed_until: Mutex<Vec<Option<Instant>>>,
+    errors: Vec<HostErrors>,
+}
+
+impl<M> FailoverManager<M>
+where
+    M: ManageConnection,
+{
+    /// Creates a new `FailoverManager` wrapping managers in priority order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `managers` is empty.
+    pub fn new(managers: Vec<M>) -> FailoverManager<M> {
+        assert!(!managers.is_empty(), "at least one manager is required");
+        let failed_until = Mutex::new(vec![None; managers.len()]);
+        let errors = HostErrors::for_hosts(managers.len());
+        FailoverManager {
+            managers,
+            probe_interval: Duration::from_secs(30),
+            failed_until,
+            errors,
+        }
+    }
+
+    /// Sets how long a manager which failed to connect is skipped before it
+    /// is tried again.
+    ///
+    /// Defaults to 30 seconds.
+    pub fn probe_interval(mut self, probe_interval: Duration) -> FailoverManager<M> {
+        self.probe_interval = probe_interval;
+        self
+    }
+
+    /// Returns the wrapped managers.
+    pub fn managers(&self) -> &[M] {
+        &self.managers
+    }
+
+    /// Returns the number of failed attempts to connect to the host at index
+    /// `host`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `host` is out of bounds.
+    pub fn connect_errors(&self, host: usize) -> usize {
+        self.errors[host].connect.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of times a connection to the host at index `host`
+    /// failed a health check.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `host` is out of bounds.
+    pub fn validation_errors(&self, host: usize) -> usize {
+        self.errors[host].validation.load(Ordering::Relaxed)
+    }
+
+    fn connect_first<F>(&self, connect: F) -> Result<HostConnection<M::Connection>, M::Error>
+    where
+        F: Fn(&M) -> Result<M::Connection, M::Error>,
+    {
+        let now = Instant::now();
+        let mut hosts = self
+            .failed_until
+            .lock()
+            .iter()
+            .enumerate()
+            .filter(|&(_, until)| until.filter(|&until| until > now).is_none())
+            .map(|(host, _)| host)
+            .collect::<Vec<_>>();
+        // if every manager is being skipped, try them all anyway
+        if hosts.is_empty() {
+            hosts = (0..self.managers.len()).collect();
+        }
+
+        let mut error = None;
+        for host in hosts {
+            match connect(&self.managers[host]) {
+                Ok(conn) => {
+                    self.failed_until.lock()[host] = None;
+                    return Ok(HostConnection { conn, host });
+                }
+                Err(e) => {
+                    self.errors[host].connect.fetch_add(1, Ordering::Relaxed);
+                    self.failed_until.lock()[host] = Some(Instant::now() + self.probe_interval);
+                    error = Some(e);
+                }
+            }
+        }
+        Err(error.unwrap())
+    }
+}
+
+impl<M> ManageConnection for FailoverManager<M>
+where
+    M: ManageConnection,
+{
+    type Connection = HostConnection<M::Connection>;
+    type Error = M::Error;
+
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        self.connect_first(|manager| manager.connect())
+    }
+
+    fn connect_with_context(
+        &self,
+        context: &ConnectContext,
+    ) -> Result<Self::Connection, Self::Error> {
+        self.connect_first(|manager| manager.connect_with_context(context))
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        let result = self.managers[conn.host].is_valid(&mut conn.conn);
+        self.errors[conn.host].validated(result)
+    }
+
+    fn is_valid_with_deadline(
+        &self,
+        conn: &mut Self::Connection,
+        deadline: Option<Instant>,
+    ) -> Result<(), Self::Error> {
+        let result = self.managers[conn.host].is_valid_with_deadline(&mut conn.conn, deadline);
+        self.errors[conn.host].validated(result)
+    }
+
+    fn deep_check(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        let result = self.managers[conn.host].deep_check(&mut conn.conn);
+        self.errors[conn.host].validated(result)
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        self.managers[conn.host].has_broken(&mut conn.conn)
+    }
+
+    fn retry_after(&self, error: &Self::Error) -> Option<Duration> {
+        self.managers
+            .iter()
+            .find_map(|manager| manager.retry_after(error))
+    }
+
+    fn on_destroy(&self, conn: &mut Self::Connection) {
+        self.managers[conn.host].on_destroy(&mut conn.conn)
+    }
+
+    fn disconnect(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        self.managers[conn.host].disconnect(&mut conn.conn)
+    }
+
+    fn expires_at(&self, conn: &Self::Connection) -> Option<Instant> {
+        self.managers[conn.host].expires_at(&conn.conn)
+    }
+
+    fn tags(&self, conn: &Self::Connection) -> Vec<String> {
+        self.managers[conn.host].tags(&conn.conn)
+    }
+}
+
+/// A source of credentials used to open connections.
+///
+/// This allows short-lived credentials such as authentication tokens to be
+/// fetched fresh for each connection rather than fixed when the manager is
+/// created.
+pub trait CredentialProvider: Send + Sync + 'static {
+    /// The credentials returned by the provider.
+    type Credentials;
+
+    /// The error type returned when credentials cannot be obtained.
+    type Error;
+
+    /// Returns the credentials to use for a new connection.
+    fn credentials(&self) -> Result<Self::Credentials, Self::Error>;
+}
+
+impl<F, C, E> CredentialProvider for F
+where
+    F: Fn() -> Result<C, E> + Send + Sync + 'static,
+{
+    type Credentials = C;
+    type Error = E;
+
+    fn credentials(&self) -> Result<C, E> {
+        self()
+    }
+}
+
+/// A `ManageConnection` which can open connections with credentials supplied
+/// by the caller.
+pub trait ConnectWithCredentials<C>: ManageConnection {
+    /// Attempts to create a new connection using `credentials`.
+    fn connect_with_credentials(
+        &self,
+        credentials: C,
+        context: &ConnectContext,
+    ) -> Result<Self::Connection, Self::Error>;
+}
+
+/// A `ManageConnection` which fetches credentials from a `CredentialProvider`
+/// before each connection attempt.
+#[derive(Debug)]
+pub struct CredentialManager<M, P> {
+    manager: M,
+    provider: P,
+}
+
+impl<M, P> CredentialManager<M, P>
+where
+    P: CredentialProvider,
+    M: ConnectWithCredentials<P::Credentials>,
+    M::Error: From<P::Error>,
+{
+    /// Creates a new `CredentialManager`.
+    pub fn new(manager: M, provider: P) -> CredentialManager<M, P> {
+        CredentialManager { manager, provider }
+    }
+
+    /// Returns the wrapped manager.
+    pub fn manager(&self) -> &M {
+        &self.manager
+    }
+
+    /// Returns the credential provider.
+    pub fn provider(&self) -> &P {
+        &self.provider
+    }
+}
+
+impl<M, P> ManageConnection for CredentialManager<M, P>
+where
+    P: CredentialProvider,
+    M: ConnectWithCredentials<P::Credentials>,
+    M::Error: From<P::Error>,
+{
+    type Connection = M::Connection;
+    type Error = M::Error;
+
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        let context = ConnectContext {
+            attempt: 0,
+            deadline: None,
+            reason: ConnectReason::Refill,
+            tag: None,
+        };
+        self.connect_with_context(&context)
+    }
+
+    fn connect_with_context(
+        &self,
+        context: &ConnectContext,
+    ) -> Result<Self::Connection, Self::Error> {
+        let credentials = self.provider.credentials()?;
+        self.manager.connect_with_credentials(credentials, context)
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        self.manager.is_valid(conn)
+    }
+
+    fn is_valid_with_deadline(
+        &self,
+        conn: &mut Self::Connection,
+        deadline: Option<Instant>,
+    ) -> Result<(), Self::Error> {
+        self.manager.is_valid_with_deadline(conn, deadline)
+    }
+
+    fn deep_check(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        self.manager.deep_check(conn)
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        self.manager.has_broken(conn)
+    }
+
+    fn retry_after(&self, error: &Self::Error) -> Option<Duration> {
+        self.manager.retry_after(error)
+    }
+
+    fn on_destroy(&self, conn: &mut Self::Connection) {
+        self.manager.on_destroy(conn)
+    }
+
+    fn disconnect(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        self.manager.disconnect(conn)
+    }
+
+    fn expires_at(&self, conn: &Self::Connection) -> Option<Instant> {
+        self.manager.expires_at(conn)
+    }
+
+    fn tags(&self, conn: &Self::Connection) -> Vec<String> {
+        self.manager.tags(conn)
+    }
+}
+
+/// The class of a connection opened by a `ReadWriteManager`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionClass {
+    /// A connection to a read-only replica.
+    Read,
+    /// A connection to the writable primary.
+    Write,
+}
+
+impl ConnectionClass {
+    /// Returns the tag of connections of this class.
+    pub fn tag(self) -> &'static str {
+        match self {
+            ConnectionClass::Read => "read",
+            ConnectionClass::Write => "write",
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            ConnectionClass::Read => 0,
+            ConnectionClass::Write => 1,
+        }
+    }
+
+    fn from_tag(tag: &str) -> Option<ConnectionClass> {
+        match tag {
+            "read" => Some(ConnectionClass::Read),
+            "write" => Some(ConnectionClass::Write),
+            _ => None,
+        }
+    }
+}
+
+/// The error returned by a `ReadWriteManager` when a class already has as many
+/// connections as its size allows.
+///
+/// The wrapped manager's error type must be convertible from it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassFull(ConnectionClass);
+
+impl ClassFull {
+    /// Returns the class which has no room for another connection.
+    pub fn class(&self) -> ConnectionClass {
+        self.0
+    }
+}
+
+impl fmt::Display for ClassFull {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "no room for another {} connection", self.0.tag())
+    }
+}
+
+impl error::Error for ClassFull {}
+
+/// A connection opened by a `ReadWriteManager`.
+///
+/// It dereferences to the underlying connection.
+#[derive(Debug)]
+pub struct ClassConnection<C> {
+    conn: C,
+    class: ConnectionClass,
+    counts: Arc<[AtomicUsize; 2]>,
+}
+
+impl<C> ClassConnection<C> {
+    /// Returns the class of the connection.
+    pub fn class(&self) -> ConnectionClass {
+        self.class
+    }
+}
+
+impl<C> Drop for ClassConnection<C> {
+    fn drop(&mut self) {
+        self.counts[self.class.index()].fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl<C> Deref for ClassConnection<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        &self.conn
+    }
+}
+
+impl<C> DerefMut for ClassConnection<C> {
+    fn deref_mut(&mut self) -> &mut C {
+        &mut self.conn
+    }
+}
+
+/// A `ManageConnection` which partitions a pool's capacity between read-only
+/// and writable connections.
+///
+/// Each new connection is opened with the manager of the class requested
+/// through `ConnectContext::tag`, or otherwise of the class furthest below its
+/// share of the pool. A class never has more connections than its size, and
+/// attempts to open another fail with `ClassFull`. The pool's `max_size` should
+/// be the sum of the two class sizes. Connections are tagged with
+/// `ConnectionClass::tag`, and can be checked out with `Pool::get_read` and
+/// `Pool::get_write`.
+#[derive(Debug)]
+pub struct ReadWriteManager<M> {
+    managers: [M; 2],
+    sizes: [u32; 2],
+    counts: Arc<[AtomicUsize; 2]>,
+}
+
+impl<M> ReadWriteManager<M>
+where
+    M: ManageConnection,
+    M::Error: From<ClassFull>,
+{
+    /// Creates a new `ReadWriteManager` which opens up to `read_size`
+    /// connections with `read` and up to `write_size` connections with
+    /// `write`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if both sizes are 0.
+    pub fn new(read: M, read_size: u32, write: M, write_size: u32) -> ReadWriteManager<M> {
+        assert!(
+            read_size > 0 || write_size > 0,
+            "at least one class must have connections"
+        );
+        ReadWriteManager {
+            managers: [read, write],
+            sizes: [read_size, write_size],
+            counts: Arc::new([AtomicUsize::new(0), AtomicUsize::new(0)]),
+        }
+    }
+
+    /// Returns the manager of the specified class.
+    pub fn manager(&self, class: ConnectionClass) -> &M {
+        &self.managers[class.index()]
+    }
+
+    /// Returns the maximum number of connections of the specified class.
+    pub fn size(&self, class: ConnectionClass) -> u32 {
+        self.sizes[class.index()]
+    }
+
+    /// Returns the number of connections of the specified class, including
+    /// those being opened.
+    pub fn connections(&self, class: ConnectionClass) -> u32 {
+        self.counts[class.index()].load(Ordering::SeqCst) as u32
+    }
+
+    fn next_class(&self) -> ConnectionClass {
+        // compare the fraction of each class in use, weighting by the sizes
+        let read = self.connections(ConnectionClass::Read) as u64 * self.sizes[1] as u64;
+        let write = self.connections(ConnectionClass::Write) as u64 * self.sizes[0] as u64;
+        if self.sizes[1] == 0 || (self.sizes[0] != 0 && read <= write) {
+            ConnectionClass::Read
+        } else {
+            ConnectionClass::Write
+        }
+    }
+
+    // claims a slot of the class before connecting, so that concurrent
+    // attempts can't push it past its size
+    fn reserve(&self, class: ConnectionClass) -> bool {
+        let size = self.sizes[class.index()] as usize;
+        self.counts[class.index()]
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                if count < size {
+                    Some(count + 1)
+                } else {
+                    None
+                }
+            })
+            .is_ok()
+    }
+
+    fn connect_class<F>(
+        &self,
+        tag: Option<&str>,
+        connect: F,
+    ) -> Result<ClassConnection<M::Connection>, M::Error>
+    where
+        F: Fn(&M) -> Result<M::Connection, M::Error>,
+    {
+        let class = match tag.and_then(ConnectionClass::from_tag) {
+            Some(class) if self.reserve(class) => class,
+            Some(class) => return Err(ClassFull(class).into()),
+            None => {
+                let preferred = self.next_class();
+                let other = match preferred {
+                    ConnectionClass::Read => ConnectionClass::Write,
+                    ConnectionClass::Write => ConnectionClass::Read,
+                };
+                if self.reserve(preferred) {
+                    preferred
+                } else if self.reserve(other) {
+                    other
+                } else {
+                    return Err(ClassFull(preferred).into());
+                }
+            }
+        };
+        match connect(&self.managers[class.index()]) {
+            Ok(conn) => Ok(ClassConnection {
+                conn,
+                class,
+                counts: self.counts.clone(),
+            }),
+            Err(e) => {
+                self.counts[class.index()].fetch_sub(1, Ordering::SeqCst);
+                Err(e)
+            }
+        }
+    }
+}
+
+impl<M> ManageConnection for ReadWriteManager<M>
+where
+    M: ManageConnection,
+    M::Error: From<ClassFull>,
+{
+    type Connection = ClassConnection<M::Connection>;
+    type Error = M::Error;
+
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        self.connect_class(None, |manager| manager.connect())
+    }
+
+    fn connect_with_context(
+        &self,
+        context: &ConnectContext,
+    ) -> Result<Self::Connection, Self::Error> {
+        self.connect_class(context.tag(), |manager| {
+            manager.connect_with_context(context)
+        })
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        self.managers[conn.class.index()].is_valid(&mut conn.conn)
+    }
+
+    fn is_valid_with_deadline(
+        &self,
+        conn: &mut Self::Connection,
+        deadline: Option<Instant>,
+    ) -> Result<(), Self::Error> {
+        self.managers[conn.class.index()].is_valid_with_deadline(&mut conn.conn, deadline)
+    }
+
+    fn deep_check(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        self.managers[conn.class.index()].deep_check(&mut conn.conn)
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        self.managers[conn.class.index()].has_broken(&mut conn.conn)
+    }
+
+    fn retry_after(&self, error: &Self::Error) -> Option<Duration> {
+        self.managers
+            .iter()
+            .find_map(|manager| manager.retry_after(error))
+    }
+
+    fn on_destroy(&self, conn: &mut Self::Connection) {
+        self.managers[conn.class.index()].on_destroy(&mut conn.conn)
+    }
+
+    fn disconnect(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        self.managers[conn.class.index()].disconnect(&mut conn.conn)
+    }
+
+    fn expires_at(&self, conn: &Self::Connection) -> Option<Instant> {
+        self.managers[conn.class.index()].expires_at(&conn.conn)
+    }
+
+    fn tags(&self, conn: &Self::Connection) -> Vec<String> {
+        let mut tags = self.managers[conn.class.index()].tags(&conn.conn);
+        tags.push(conn.class.tag().to_string());
+        tags
+    }
+}
+
+impl<M> Pool<ReadWriteManager<M>>
+where
+    M: ManageConnection,
+    M::Error: From<ClassFull>,
+{
+    /// Retrieves a read-only connection from the pool.
+    ///
+    /// Waits for as long as `get` before returning an error.
+    pub fn get_read(&self) -> Result<PooledConnection<ReadWriteManager<M>>, Error> {
+        self.get_tagged(ConnectionClass::Read.tag())
+    }
+
+    /// Retrieves a writable connection from the pool.
+    ///
+    /// Waits for as long as `get` before returning an error.
+    pub fn get_write(&self) -> Result<PooledConnection<ReadWriteManager<M>>, Error> {
+        self.get_tagged(ConnectionClass::Write.tag())
+    }
+}
+
+/// A `ManageConnection` which logs connection attempts and health checks.
+///
+/// Requires the `log` feature.
+///
+/// Successful connections are logged at the debug level, with how long they
+/// took, and failures at the warn level.
+#[cfg(feature = "log")]
+#[derive(Debug)]
+pub struct LoggingManager<M> {
+    manager: M,
+}
+
+#[cfg(feature = "log")]
+impl<M> LoggingManager<M>
+where
+    M: ManageConnection,
+{
+    /// Creates a new `LoggingManager`.
+    pub fn new(manager: M) -> LoggingManager<M> {
+        LoggingManager { manager }
+    }
+
+    /// Returns the wrapped manager.
+    pub fn manager(&self) -> &M {
+        &self.manager
+    }
+}
+
+#[cfg(feature = "log")]
+impl<M> ManageConnection for LoggingManager<M>
+where
+    M: ManageConnection,
+{
+    type Connection = M::Connection;
+    type Error = M::Error;
+
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        let start = Instant::now();
+        let result = self.manager.connect();
+        log_connect(&result, start);
+        result
+    }
+
+    fn connect_with_context(
+        &self,
+        context: &ConnectContext,
+    ) -> Result<Self::Connection, Self::Error> {
+        let start = Instant::now();
+        let result = self.manager.connect_with_context(context);
+        log_connect(&result, start);
+        result
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        let result = self.manager.is_valid(conn);
+        if let Err(ref e) = result {
+            warn!("connection failed validation: {}", e);
+        }
+        result
+    }
+
+    fn is_valid_with_deadline(
+        &self,
+        conn: &mut Self::Connection,
+        deadline: Option<Instant>,
+    ) -> Result<(), Self::Error> {
+        let result = self.manager.is_valid_with_deadline(conn, deadline);
+        if let Err(ref e) = result {
+            warn!("connection failed validation: {}", e);
+        }
+        result
+    }
+
+    fn deep_check(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        let result = self.manager.deep_check(conn);
+        if let Err(ref e) = result {
+            warn!("connection failed deep check: {}", e);
+        }
+        result
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        let broken = self.manager.has_broken(conn);
+        if broken {
+            debug!("connection broken");
+        }
+        broken
+    }
+
+    fn retry_after(&self, error: &Self::Error) -> Option<Duration> {
+        self.manager.retry_after(error)
+    }
+
+    fn on_destroy(&self, conn: &mut Self::Connection) {
+        self.manager.on_destroy(conn)
+    }
+
+    fn disconnect(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        self.manager.disconnect(conn)
+    }
+
+    fn expires_at(&self, conn: &Self::Connection) -> Option<Instant> {
+        self.manager.expires_at(conn)
+    }
+
+    fn tags(&self, conn: &Self::Connection) -> Vec<String> {
+        self.manager.tags(conn)
+    }
+}
+
+#[cfg(feature = "log")]
+fn log_connect<C, E>(result: &Result<C, E>, start: Instant)
+where
+    E: fmt::Display,
+{
+    match *result {
+        Ok(_) => debug!("connection established in {:?}", start.elapsed()),
+        Err(ref e) => warn!("connection failed after {:?}: {}", start.elapsed(), e),
+    }
+}
+
+/// A `ManageConnection` which delays connection attempts and health checks.
+///
+/// This is intended for testing how an application behaves when its database
+/// is slow to respond.
+#[derive(Debug)]
+pub struct LatencyManager<M> {
+    manager: M,
+    connect_delay: Duration,
+    validation_delay: Duration,
+}
+
+impl<M> LatencyManager<M>
+where
+    M: ManageConnection,
+{
+    /// Creates a new `LatencyManager` which waits `connect_delay` before each
+    /// connection attempt.
+    pub fn new(manager: M, connect_delay: Duration) -> LatencyManager<M> {
+        LatencyManager {
+            manager,
+            connect_delay,
+            validation_delay: Duration::from_secs(0),
+        }
+    }
+
+    /// Sets how long to wait before each call to `is_valid`.
+    ///
+    /// Defaults to 0.
+    pub fn validation_delay(mut self, validation_delay: Duration) -> LatencyManager<M> {
+        self.validation_delay = validation_delay;
+        self
+    }
+
+    /// Returns the wrapped manager.
+    pub fn manager(&self) -> &M {
+        &self.manager
+    }
+}
+
+impl<M> ManageConnection for LatencyManager<M>
+where
+    M: ManageConnection,
+{
+    type Connection = M::Connection;
+    type Error = M::Error;
+
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        thread::sleep(self.connect_delay);
+        self.manager.connect()
+    }
+
+    fn connect_with_context(
+        &self,
+        context: &ConnectContext,
+    ) -> Result<Self::Connection, Self::Error> {
+        thread::sleep(self.connect_delay);
+        self.manager.connect_with_context(context)
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        thread::sleep(self.validation_delay);
+        self.manager.is_valid(conn)
+    }
+
+    fn is_valid_with_deadline(
+        &self,
+        conn: &mut Self::Connection,
+        deadline: Option<Instant>,
+    ) -> Result<(), Self::Error> {
+        thread::sleep(self.validation_delay);
+        self.manager.is_valid_with_deadline(conn, deadline)
+    }
+
+    fn deep_check(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        thread::sleep(self.validation_delay);
+        self.manager.deep_check(conn)
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        self.manager.has_broken(conn)
+    }
+
+    fn retry_after(&self, error: &Self::Error) -> Option<Duration> {
+        self.manager.retry_after(error)
+    }
+
+    fn on_destroy(&self, conn: &mut Self::Connection) {
+        self.manager.on_destroy(conn)
+    }
+
+    fn disconnect(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        self.manager.disconnect(conn)
+    }
+
+    fn expires_at(&self, conn: &Self::Connection) -> Option<Instant> {
+        self.manager.expires_at(conn)
+    }
+
+    fn tags(&self, conn: &Self::Connection) -> Vec<String> {
+        self.manager.tags(conn)
+    }
+}
+
+/// A `ManageConnection` which counts calls to the wrapped manager.
+#[derive(Debug)]
+pub struct CountingManager<M> {
+    manager: M,
+    connects: AtomicUsize,
+    connect_errors: AtomicUsize,
+    validations: AtomicUsize,
+    validation_errors: AtomicUsize,
+    broken: AtomicUsize,
+}
+
+impl<M> CountingManager<M>
+where
+    M: ManageConnection,
+{
+    /// Creates a new `CountingManager`.
+    pub fn new(manager: M) -> CountingManager<M> {
+        CountingManager {
+            manager,
+            connects: AtomicUsize::new(0),
+            connect_errors: AtomicUsize::new(0),
+            validations: AtomicUsize::new(0),
+            validation_errors: AtomicUsize::new(0),
+            broken: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the wrapped manager.
+    pub fn manager(&self) -> &M {
+        &self.manager
+    }
+
+    /// Returns the number of connection attempts.
+    pub fn connects(&self) -> usize {
+        self.connects.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of failed connection attempts.
+    pub fn connect_errors(&self) -> usize {
+        self.connect_errors.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of validation checks.
+    ///
+    /// This counts calls to `is_valid`, `is_valid_with_deadline` and
+    /// `deep_check`.
+    pub fn validations(&self) -> usize {
+        self.validations.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of validation checks which returned an error.
+    pub fn validation_errors(&self) -> usize {
+        self.validation_errors.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of calls to `has_broken` which returned `true`.
+    pub fn broken(&self) -> usize {
+        self.broken.load(Ordering::Relaxed)
+    }
+}
+
+impl<M> ManageConnection for CountingManager<M>
+where
+    M: ManageConnection,
+{
+    type Connection = M::Connection;
+    type Error = M::Error;
+
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        let result = self.manager.connect();
+        count(result, &self.connects, &self.connect_errors)
+    }
+
+    fn connect_with_context(
+        &self,
+        context: &ConnectContext,
+    ) -> Result<Self::Connection, Self::Error> {
+        let result = self.manager.connect_with_context(context);
+        count(result, &self.connects, &self.connect_errors)
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        let result = self.manager.is_valid(conn);
+        count(result, &self.validations, &self.validation_errors)
+    }
+
+    fn is_valid_with_deadline(
+        &self,
+        conn: &mut Self::Connection,
+        deadline: Option<Instant>,
+    ) -> Result<(), Self::Error> {
+        let result = self.manager.is_valid_with_deadline(conn, deadline);
+        count(result, &self.validations, &self.validation_errors)
+    }
+
+    fn deep_check(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        let result = self.manager.deep_check(conn);
+        count(result, &self.validations, &self.validation_errors)
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        let broken = self.manager.has_broken(conn);
+        if broken {
+            self.broken.fetch_add(1, Ordering::Relaxed);
+        }
+        broken
+    }
+
+    fn retry_after(&self, error: &Self::Error) -> Option<Duration> {
+        self.manager.retry_after(error)
+    }
+
+    fn on_destroy(&self, conn: &mut Self::Connection) {
+        self.manager.on_destroy(conn)
+    }
+
+    fn disconnect(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        self.manager.disconnect(conn)
+    }
+
+    fn expires_at(&self, conn: &Self::Connection) -> Option<Instant> {
+        self.manager.expires_at(conn)
+    }
+
+    fn tags(&self, conn: &Self::Connection) -> Vec<String> {
+        self.manager.tags(conn)
+    }
+}
+
+fn count<T, E>(result: Result<T, E>, calls: &AtomicUsize, errors: &AtomicUsize) -> Result<T, E> {
+    calls.fetch_add(1, Ordering::Relaxed);
+    if result.is_err() {
+        errors.fetch_add(1, Ordering::Relaxed);
+    }
+    result
+}
+
+/// A `ManageConnection` which replaces the wrapped manager's `is_valid` with
+/// a closure.
+///
+/// This allows a health check query to be chosen for the environment without
+/// changing the manager itself.
+pub struct ValidatedManager<M, F> {
+    manager: M,
+    validate: F,
+}
+
+impl<M, F> fmt::Debug for ValidatedManager<M, F>
+where
+    M: fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ValidatedManager")
+            .field("manager", &self.manager)
+            .finish()
+    }
+}
+
+impl<M, F> ValidatedManager<M, F>
+where
+    M: ManageConnection,
+    F: Fn(&mut M::Connection) -> Result<(), M::Error> + Send + Sync + 'static,
+{
+    /// Creates a new `ValidatedManager` which checks connections with
+    /// `validate`.
+    pub fn new(manager: M, validate: F) -> ValidatedManager<M, F> {
+        ValidatedManager { manager, validate }
+    }
+
+    /// Returns the wrapped manager.
+    pub fn manager(&self) -> &M {
+        &self.manager
+    }
+}
+
+impl<M, F> ManageConnection for ValidatedManager<M, F>
+where
+    M: ManageConnection,
+    F: Fn(&mut M::Connection) -> Result<(), M::Error> + Send + Sync + 'static,
+{
+    type Connection = M::Connection;
+    type Error = M::Error;
+
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        self.manager.connect()
+    }
+
+    fn connect_with_context(
+        &self,
+        context: &ConnectContext,
+    ) -> Result<Self::Connection, Self::Error> {
+        self.manager.connect_with_context(context)
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        (self.validate)(conn)
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        self.manager.has_broken(conn)
+    }
+
+    fn retry_after(&self, error: &Self::Error) -> Option<Duration> {
+        self.manager.retry_after(error)
+    }
+
+    fn on_destroy(&self, conn: &mut Self::Connection) {
+        self.manager.on_destroy(conn)
+    }
+
+    fn disconnect(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        self.manager.disconnect(conn)
+    }
+
+    fn expires_at(&self, conn: &Self::Connection) -> Option<Instant> {
+        self.manager.expires_at(conn)
+    }
+
+    fn tags(&self, conn: &Self::Connection) -> Vec<String> {
+        self.manager.tags(conn)
+    }
+}
+
+/// A boxed future returned by an `AsyncManageConnection`.
+pub type ManagerFuture<'a, T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send + 'a>>;
+
+/// An asynchronous version of `ManageConnection`.
+///
+/// A pool uses it through an `AsyncManager`.
+pub trait AsyncManageConnection: Send + Sync + 'static {
+    /// The connection type this manager deals with.
+    type Connection: Send + 'static;
+
+    /// The error type returned by `Connection`s.
+    type Error: fmt::Debug + fmt::Display + 'static;
+
+    /// Attempts to create a new connection.
+    fn connect(&self) -> ManagerFuture<'_, Self::Connection, Self::Error>;
+
+    /// Determines if the connection is still connected to the database.
+    fn is_valid<'a>(&'a self, conn: &'a mut Self::Connection)
+        -> ManagerFuture<'a, (), Self::Error>;
+
+    /// *Quickly* determines if the connection is no longer usable.
+    ///
+    /// This will be called synchronously every time a connection is returned
+    /// to the pool, so it should *not* block.
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool;
+}
+
+/// A `ManageConnection` which opens and checks connections with an
+/// `AsyncManageConnection`.
+///
+/// The manager's futures are run to completion on the pool's worker threads,
+/// or on the thread polling a `GetFuture` for a synchronous pool. Those threads
+/// are not part of any async runtime, so the futures must not rely on
+/// facilities of a particular runtime. Use `Pool::get_async` to wait for
+/// connections without blocking.
+#[derive(Debug)]
+pub struct AsyncManager<M> {
+    manager: M,
+}
+
+impl<M> AsyncManager<M>
+where
+    M: AsyncManageConnection,
+{
+    /// Creates a new `AsyncManager`.
+    pub fn new(manager: M) -> AsyncManager<M> {
+        AsyncManager { manager }
+    }
+
+    /// Returns the wrapped manager.
+    pub fn manager(&self) -> &M {
+        &self.manager
+    }
+}
+
+impl<M> ManageConnection for AsyncManager<M>
+where
+    M: AsyncManageConnection,
+{
+    type Connection = M::Connection;
+    type Error = M::Error;
+
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        block_on(self.manager.connect())
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        block_on(self.manager.is_valid(conn))
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        self.manager.has_broken(conn)
+    }
+}
+
+// runs a future to completion, parking the current thread while it is pending
+fn block_on<F>(future: F) -> F::Output
+where
+    F: Future,
+{
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use parking_lot::{Condvar, Mutex};
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::Error;
+
+type Job<C, E> = Box<dyn FnOnce(&mut Option<C>, &dyn Fn() -> Result<C, E>) + Send>;
+
+struct PinnedShared<C, E> {
+    workers: Vec<Mutex<Sender<Job<C, E>>>>,
+    idle: Mutex<Vec<usize>>,
+    cond: Condvar,
+}
+
+/// A pool of connections which are not `Send`.
+///
+/// Each connection lives on a dedicated worker thread for its entire
+/// lifetime, and callers run closures against it on that thread through a
+/// `PinnedConnection`. Connections are opened the first time they are used,
+/// and again after one is invalidated.
+pub struct PinnedPool<C, E> {
+    shared: Arc<PinnedShared<C, E>>,
+}
+
+impl<C, E> Clone for PinnedPool<C, E> {
+    fn clone(&self) -> PinnedPool<C, E> {
+        PinnedPool {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<C, E> fmt::Debug for PinnedPool<C, E> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("PinnedPool")
+            .field("size", &self.shared.workers.len())
+            .field("idle", &self.shared.idle.lock().len())
+            .finish()
+    }
+}
+
+impl<C, E> PinnedPool<C, E>
+where
+    C: 'static,
+    E: Send + 'static,
+{
+    /// Creates a new pool of `size` worker threads, each of which opens its
+    /// connection with `connect`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0 or a worker thread cannot be spawned.
+    pub fn new<F>(size: usize, connect: F) -> PinnedPool<C, E>
+    where
+        F: Fn() -> Result<C, E> + Send + Sync + 'static,
+    {
+        assert!(size > 0, "size must be positive");
+        let connect = Arc::new(connect);
+        let workers = (0..size)
+            .map(|i| {
+                let (tx, rx) = mpsc::channel();
+                let connect = connect.clone();
+                thread::Builder::new()
+                    .name(format!("r2d2-pinned-{}", i))
+                    .spawn(move || run_worker(rx, &*connect))
+                    .expect("failed to spawn pinned connection thread");
+                Mutex::new(tx)
+            })
+            .collect();
+
+        PinnedPool {
+            shared: Arc::new(PinnedShared {
+                workers,
+                idle: Mutex::new((0..size).rev().collect()),
+                cond: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Retrieves a connection from the pool.
+    ///
+    /// Waits for at most 30 seconds before returning an error.
+    pub fn get(&self) -> Result<PinnedConnection<C, E>, Error> {
+        self.get_timeout(Duration::from_secs(30))
+    }
+
+    /// Retrieves a connection from the pool, waiting for at most `timeout`.
+    pub fn get_timeout(&self, timeout: Duration) -> Result<PinnedConnection<C, E>, Error> {
+        let end = Instant::now() + timeout;
+        let mut idle = self.shared.idle.lock();
+        loop {
+            if let Some(worker) = idle.pop() {
+                return Ok(PinnedConnection {
+                    shared: self.shared.clone(),
+                    worker,
+                });
+            }
+            if self.shared.cond.wait_until(&mut idle, end).timed_out() {
+                return Err(Error::timeout(None));
+            }
+        }
+    }
+
+    /// Returns the number of worker threads in the pool.
+    pub fn size(&self) -> usize {
+        self.shared.workers.len()
+    }
+}
+
+fn run_worker<C, E>(rx: Receiver<Job<C, E>>, connect: &dyn Fn() -> Result<C, E>) {
+    let mut conn = None;
+    // exits once the pool and all of its connections have been dropped
+    for job in rx {
+        job(&mut conn, connect);
+    }
+}
+
+/// A connection checked out of a `PinnedPool`.
+///
+/// The connection itself stays on its worker thread. It is returned to the
+/// pool when this guard is dropped.
+pub struct PinnedConnection<C, E> {
+    shared: Arc<PinnedShared<C, E>>,
+    worker: usize,
+}
+
+impl<C, E> fmt::Debug for PinnedConnection<C, E> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("PinnedConnection")
+            .field("worker", &self.worker)
+            .finish()
+    }
+}
+
+impl<C, E> PinnedConnection<C, E>
+where
+    C: 'static,
+    E: Send + 'static,
+{
+    /// Runs a closure with the connection on its worker thread, returning the
+    /// closure's result.
+    ///
+    /// The connection is opened first if necessary.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from opening the connection if it fails.
+    ///
+    /// # Panics
+    ///
+    /// If the closure panics, the connection is closed and the panic is
+    /// propagated to the caller.
+    pub fn run<F, R>(&self, f: F) -> Result<R, E>
+    where
+        F: FnOnce(&mut C) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel::<Result<Result<R, E>, Box<dyn Any + Send>>>();
+        self.send(Box::new(move |slot, connect| {
+            if slot.is_none() {
+                match connect() {
+                    Ok(conn) => *slot = Some(conn),
+                    Err(e) => {
+                        let _ = tx.send(Ok(Err(e)));
+                        return;
+                    }
+                }
+            }
+            let conn = slot.as_mut().unwrap();
+            match panic::catch_unwind(AssertUnwindSafe(|| f(conn))) {
+                Ok(r) => {
+                    let _ = tx.send(Ok(Ok(r)));
+                }
+                Err(payload) => {
+                    // the connection may have been left in an inconsistent state
+                    *slot = None;
+                    let _ = tx.send(Err(payload));
+                }
+            }
+        }));
+
+        match rx.recv().expect("pinned connection thread exited") {
+            Ok(result) => result,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    /// Closes the connection.
+    ///
+    /// A new connection will be opened on the worker thread the next time it
+    /// is used.
+    pub fn invalidate(&self) {
+        self.send(Box::new(|slot, _| *slot = None));
+    }
+
+    fn send(&self, job: Job<C, E>) {
+        self.shared.workers[self.worker]
+            .lock()
+            .send(job)
+            .expect("pinned connection thread exited");
+    }
+}
+
+impl<C, E> Drop for PinnedConnection<C, E> {
+    fn drop(&mut self) {
+        self.shared.idle.lock().push(self.worker);
+        self.shared.cond.notify_one();
+    }
+}
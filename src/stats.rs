@@ -0,0 +1,334 @@
+use parking_lot::Mutex;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::State;
+
+// bucket i holds durations below 2^i microseconds; the last bucket is unbounded
+const BUCKETS: usize = 32;
+
+/// A histogram of durations.
+///
+/// Durations are counted in exponentially sized buckets, the `i`th of which
+/// holds durations shorter than `2^i` microseconds. Quantiles are therefore
+/// approximate, and are reported as the upper bound of the bucket they fall
+/// into.
+#[derive(Clone)]
+pub struct Histogram {
+    buckets: [u64; BUCKETS],
+    count: u64,
+    sum: Duration,
+    max: Duration,
+}
+
+impl Default for Histogram {
+    fn default() -> Histogram {
+        Histogram {
+            buckets: [0; BUCKETS],
+            count: 0,
+            sum: Duration::from_secs(0),
+            max: Duration::from_secs(0),
+        }
+    }
+}
+
+impl fmt::Debug for Histogram {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Histogram")
+            .field("count", &self.count)
+            .field("mean", &self.mean())
+            .field("p50", &self.quantile(0.5))
+            .field("p99", &self.quantile(0.99))
+            .field("max", &self.max)
+            .finish()
+    }
+}
+
+impl Histogram {
+    pub(crate) fn record(&mut self, duration: Duration) {
+        let micros = duration.as_micros();
+        let bucket = (128 - micros.leading_zeros() as usize).min(BUCKETS - 1);
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum += duration;
+        self.max = self.max.max(duration);
+    }
+
+    /// Returns the number of recorded durations.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the sum of all recorded durations.
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    /// Returns the mean of the recorded durations, or zero if none have been
+    /// recorded.
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::from_secs(0),
+            count => Duration::from_nanos((self.sum.as_nanos() / count as u128) as u64),
+        }
+    }
+
+    /// Returns the longest recorded duration.
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Returns an upper bound on the duration below which the fraction `q`
+    /// of recorded durations fall.
+    ///
+    /// `q` is clamped to the range `[0, 1]`. Returns zero if no durations
+    /// have been recorded.
+    pub fn quantile(&self, q: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::from_secs(0);
+        }
+        let q = q.clamp(0., 1.);
+        let target = ((self.count as f64 * q).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= target {
+                return upper_bound(i).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    /// Returns an iterator over the non-empty buckets of the histogram.
+    ///
+    /// Each item is the exclusive upper bound of the bucket along with the
+    /// number of durations it holds. The bound of the last bucket is
+    /// `Duration::MAX`.
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|&(_, &n)| n > 0)
+            .map(|(i, &n)| (upper_bound(i), n))
+    }
+}
+
+fn upper_bound(bucket: usize) -> Duration {
+    if bucket == BUCKETS - 1 {
+        Duration::MAX
+    } else {
+        Duration::from_micros(1 << bucket)
+    }
+}
+
+/// Cumulative statistics about a `Pool`.
+///
+/// See `Pool::statistics`.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Statistics {
+    /// The number of connections checked out of the pool.
+    pub checkouts: u64,
+    /// The number of checkout attempts which timed out.
+    pub checkout_timeouts: u64,
+    /// How long callers waited for a connection in successful checkouts.
+    pub checkout_wait: Histogram,
+    /// The number of connections opened by the pool.
+    pub connections_created: u64,
+    /// The number of attempts to open a connection which failed.
+    pub connect_failures: u64,
+    /// How long attempts to open a connection took, whether or not they
+    /// succeeded.
+    pub connect_duration: Histogram,
+    /// The number of connections closed by the pool, for any reason.
+    pub connections_closed: u64,
+    /// The number of connections closed because they were broken or failed
+    /// validation.
+    pub closed_broken: u64,
+    /// The number of connections closed because they were idle for longer
+    /// than the idle timeout.
+    pub closed_idle: u64,
+    /// The number of connections closed because they reached their maximum
+    /// lifetime or number of uses.
+    pub closed_expired: u64,
+    /// The number of times a connection failed validation.
+    pub validation_failures: u64,
+    /// How long health checks of connections took, whether or not they
+    /// passed.
+    pub validation_duration: Histogram,
+    /// The largest number of connections checked out at once.
+    ///
+    /// This and the other high-watermarks cover the time since the pool was
+    /// created or `Pool::reset_watermarks` was last called.
+    pub peak_in_use: u64,
+    /// The largest number of callers waiting for a connection at once.
+    pub peak_waiters: u64,
+    /// The longest time a caller waited for a connection, including callers
+    /// which timed out.
+    pub peak_wait: Duration,
+}
+
+/// A trait which is periodically sent snapshots of a pool's statistics.
+///
+/// See `Builder::stats_sink`.
+pub trait StatsSink: fmt::Debug + Sync + Send {
+    /// Called with each snapshot.
+    fn report(&self, snapshot: &StatsSnapshot);
+}
+
+/// A point-in-time snapshot of a `Pool`'s statistics.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct StatsSnapshot {
+    /// The time at which the snapshot was taken.
+    pub taken_at: Instant,
+    /// The name of the pool, if it has one.
+    pub pool_name: Option<Arc<str>>,
+    /// The state of the pool.
+    pub state: State,
+    /// The cumulative statistics of the pool.
+    pub statistics: Statistics,
+    /// Statistics about each connection in the pool.
+    pub connections: Vec<ConnectionStatistics>,
+    pub(crate) pool_id: u64,
+}
+
+/// Statistics about a single connection in a `Pool`.
+///
+/// See `Pool::connection_statistics`.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct ConnectionStatistics {
+    /// The ID of the connection.
+    pub id: u64,
+    /// The time at which the connection was established.
+    pub created_at: Instant,
+    /// Whether the connection is currently checked out.
+    pub checked_out: bool,
+    /// The number of times the connection has been checked out.
+    pub checkouts: u32,
+    /// The total time the connection has spent checked out.
+    pub busy: Duration,
+    /// The time at which the connection last passed a health check.
+    ///
+    /// Connections which fail a health check are closed.
+    pub last_validated_at: Option<Instant>,
+    /// The time taken by the connection's last health check.
+    pub last_validation_duration: Option<Duration>,
+}
+
+#[derive(Default)]
+pub(crate) struct Stats {
+    checkouts: AtomicU64,
+    checkout_timeouts: AtomicU64,
+    checkout_wait: Mutex<Histogram>,
+    connections_created: AtomicU64,
+    connect_failures: AtomicU64,
+    connect_duration: Mutex<Histogram>,
+    connections_closed: AtomicU64,
+    closed_broken: AtomicU64,
+    closed_idle: AtomicU64,
+    closed_expired: AtomicU64,
+    validation_failures: AtomicU64,
+    validation_duration: Mutex<Histogram>,
+    peak_in_use: AtomicU64,
+    peak_waiters: AtomicU64,
+    // in nanoseconds
+    peak_wait: AtomicU64,
+}
+
+impl Stats {
+    pub(crate) fn record_checkout(&self) {
+        self.checkouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_checkout_wait(&self, wait: Duration) {
+        self.checkout_wait.lock().record(wait);
+        self.record_wait(wait);
+    }
+
+    pub(crate) fn record_timeout(&self, wait: Duration) {
+        self.checkout_timeouts.fetch_add(1, Ordering::Relaxed);
+        self.record_wait(wait);
+    }
+
+    fn record_wait(&self, wait: Duration) {
+        let nanos = wait.as_nanos().min(u64::MAX as u128) as u64;
+        self.peak_wait.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_in_use(&self, in_use: usize) {
+        self.peak_in_use.fetch_max(in_use as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_waiters(&self, waiters: u32) {
+        self.peak_waiters
+            .fetch_max(u64::from(waiters), Ordering::Relaxed);
+    }
+
+    pub(crate) fn reset_watermarks(&self, in_use: usize, waiters: u32) {
+        self.peak_in_use.store(in_use as u64, Ordering::Relaxed);
+        self.peak_waiters
+            .store(u64::from(waiters), Ordering::Relaxed);
+        self.peak_wait.store(0, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_created(&self) {
+        self.connections_created.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_connect(&self, duration: Duration) {
+        self.connect_duration.lock().record(duration);
+    }
+
+    pub(crate) fn record_connect_failure(&self) {
+        self.connect_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_closed(&self, count: usize) {
+        self.connections_closed
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_broken(&self) {
+        self.closed_broken.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_idle(&self) {
+        self.closed_idle.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_expired(&self) {
+        self.closed_expired.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_validation(&self, duration: Duration) {
+        self.validation_duration.lock().record(duration);
+    }
+
+    pub(crate) fn record_validation_failure(&self) {
+        self.validation_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> Statistics {
+        Statistics {
+            checkouts: self.checkouts.load(Ordering::Relaxed),
+            checkout_timeouts: self.checkout_timeouts.load(Ordering::Relaxed),
+            checkout_wait: self.checkout_wait.lock().clone(),
+            connections_created: self.connections_created.load(Ordering::Relaxed),
+            connect_failures: self.connect_failures.load(Ordering::Relaxed),
+            connect_duration: self.connect_duration.lock().clone(),
+            connections_closed: self.connections_closed.load(Ordering::Relaxed),
+            closed_broken: self.closed_broken.load(Ordering::Relaxed),
+            closed_idle: self.closed_idle.load(Ordering::Relaxed),
+            closed_expired: self.closed_expired.load(Ordering::Relaxed),
+            validation_failures: self.validation_failures.load(Ordering::Relaxed),
+            validation_duration: self.validation_duration.lock().clone(),
+            peak_in_use: self.peak_in_use.load(Ordering::Relaxed),
+            peak_waiters: self.peak_waiters.load(Ordering::Relaxed),
+            peak_wait: Duration::from_nanos(self.peak_wait.load(Ordering::Relaxed)),
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+use crate::{StatsSink, StatsSnapshot};
+
+/// A reporter which periodically sends pool statistics to a StatsD server.
+///
+/// Gauges are sent for the pool's connection and waiter counts and for
+/// checkout wait, connect, and health check time quantiles. Counters are sent
+/// for checkouts, timeouts, and connections opened, failing to open, closed,
+/// and failing validation. Tags are sent in the DogStatsD format.
+///
+/// The reporter can be started on a running pool with `Pool::report_statsd`,
+/// or used as the pool's `StatsSink`, in which case it reports every
+/// `stats_interval` rather than its own `interval`.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let reporter = StatsdReporter::new("127.0.0.1:8125")?
+///     .prefix("myapp.db")
+///     .tag("env", "production");
+/// pool.report_statsd(reporter);
+/// ```
+#[derive(Debug)]
+pub struct StatsdReporter {
+    socket: UdpSocket,
+    prefix: String,
+    tags: Vec<(String, String)>,
+    interval: Duration,
+    // the counters last sent for each pool, since one reporter can serve
+    // several pools built from the same `Builder`
+    last: Mutex<HashMap<u64, [u64; 6]>>,
+}
+
+// the largest packet sent, which keeps packets within a typical 1500 byte MTU
+const MAX_PACKET_SIZE: usize = 1432;
+
+impl StatsdReporter {
+    /// Creates a reporter which sends to the StatsD server at `addr`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `addr` can't be resolved or a socket can't be
+    /// opened.
+    pub fn new<A>(addr: A) -> io::Result<StatsdReporter>
+    where
+        A: ToSocketAddrs,
+    {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to send to"))?;
+        let local = match addr {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+
+        Ok(StatsdReporter {
+            socket,
+            prefix: "r2d2".to_owned(),
+            tags: vec![],
+            interval: Duration::from_secs(10),
+            last: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Sets the prefix of every metric name.
+    ///
+    /// Defaults to `r2d2`.
+    pub fn prefix(mut self, prefix: &str) -> StatsdReporter {
+        self.prefix = prefix.to_owned();
+        self
+    }
+
+    /// Adds a tag to every metric.
+    pub fn tag(mut self, key: &str, value: &str) -> StatsdReporter {
+        self.tags.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Sets how often statistics are sent by `Pool::report_statsd`, which
+    /// rejects an interval of 0.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn interval(mut self, interval: Duration) -> StatsdReporter {
+        self.interval = interval;
+        self
+    }
+
+    pub(crate) fn report_interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn send(&self, snapshot: &StatsSnapshot) -> io::Result<()> {
+        let state = &snapshot.state;
+        let statistics = &snapshot.statistics;
+        let wait = &statistics.checkout_wait;
+        let connect = &statistics.connect_duration;
+        let validation = &statistics.validation_duration;
+        let counters = [
+            ("checkouts", statistics.checkouts),
+            ("checkout_timeouts", statistics.checkout_timeouts),
+            ("connections_created", statistics.connections_created),
+            ("connect_failures", statistics.connect_failures),
+            ("connections_closed", statistics.connections_closed),
+            ("validation_failures", statistics.validation_failures),
+        ];
+
+        let gauges = [
+            ("connections", f64::from(state.connections)),
+            ("idle_connections", f64::from(state.idle_connections)),
+            ("in_use_connections", f64::from(state.in_use_connections)),
+            ("pending_connections", f64::from(state.pending_connections)),
+            ("connecting", f64::from(state.connecting)),
+            ("waiters", f64::from(state.waiters)),
+            (
+                "longest_wait",
+                millis(state.longest_wait.unwrap_or_default()),
+            ),
+            ("checkout_wait.p50", millis(wait.quantile(0.5))),
+            ("checkout_wait.p99", millis(wait.quantile(0.99))),
+            ("checkout_wait.max", millis(wait.max())),
+            ("connect_duration.p99", millis(connect.quantile(0.99))),
+            ("connect_duration.max", millis(connect.max())),
+            ("validation_duration.p99", millis(validation.quantile(0.99))),
+            ("validation_duration.max", millis(validation.max())),
+        ];
+
+        let mut lines = vec![];
+        for &(name, value) in &gauges {
+            lines.push(self.metric(snapshot, name, value, "g"));
+        }
+        // StatsD counters are deltas since the last report
+        {
+            let mut last = self.last.lock();
+            let last = last.entry(snapshot.pool_id).or_default();
+            for (&(name, value), last) in counters.iter().zip(last.iter_mut()) {
+                lines.push(self.metric(snapshot, name, value - *last, "c"));
+                *last = value;
+            }
+        }
+
+        let mut packet = String::new();
+        for line in lines {
+            if !packet.is_empty() && packet.len() + 1 + line.len() > MAX_PACKET_SIZE {
+                self.socket.send(packet.as_bytes())?;
+                packet.clear();
+            }
+            if !packet.is_empty() {
+                packet.push('\n');
+            }
+            packet.push_str(&line);
+        }
+        self.socket.send(packet.as_bytes()).map(|_| ())
+    }
+
+    fn metric<T>(&self, snapshot: &StatsSnapshot, name: &str, value: T, kind: &str) -> String
+    where
+        T: std::fmt::Display,
+    {
+        let mut line = format!("{}.{}:{}|{}", self.prefix, name, value, kind);
+        let pool = snapshot.pool_name.as_ref().map(|name| ("pool", &**name));
+        let tags = self.tags.iter().map(|(key, value)| (&**key, &**value));
+        for (i, (key, value)) in pool.into_iter().chain(tags).enumerate() {
+            let sep = if i == 0 { "|#" } else { "," };
+            let _ = write!(line, "{}{}:{}", sep, key, value);
+        }
+        line
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.
+}
+
+impl StatsSink for StatsdReporter {
+    fn report(&self, snapshot: &StatsSnapshot) {
+        #[allow(unused_variables)]
+        if let Err(e) = self.send(snapshot) {
+            pool_log!(debug, "failed to send statsd report: {}", e);
+        }
+    }
+}
@@ -3,10 +3,21 @@ use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering};
 use std::sync::mpsc::{self, Receiver, SyncSender};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use std::{error, fmt, mem, thread};
-
-use crate::event::{AcquireEvent, CheckinEvent, CheckoutEvent, ReleaseEvent, TimeoutEvent};
-use crate::{CustomizeConnection, HandleEvent, ManageConnection, Pool, PooledConnection};
+use std::{env, error, fmt, mem, panic, thread};
+
+use crate::event::{AcquireEvent, CheckinEvent, CheckoutEvent, Event, ReleaseEvent, TimeoutEvent};
+#[cfg(feature = "log")]
+use crate::manager::LoggingManager;
+use crate::manager::{
+    AsyncManageConnection, AsyncManager, ClassFull, ConnectWithCredentials, ConnectionClass,
+    CountingManager, CredentialManager, FailoverManager, LatencyManager, ManagerFuture,
+    MultiHostManager, ReadWriteManager, ValidatedManager,
+};
+use crate::{
+    Backoff, Builder, ConfigUpdate, ConnectContext, ConnectReason, CustomizeConnection,
+    ExponentialBackoff, FixedBackoff, HandleEvent, Health, IdleStrategy, ManageConnection,
+    PinnedPool, Pool, PooledConnection, StatsSink, StatsSnapshot, StatsdReporter,
+};
 
 #[derive(Debug)]
 pub struct Error;
@@ -147,6 +158,30 @@ fn test_is_send_sync() {
     is_send_sync::<Pool<OkManager>>();
 }
 
+#[test]
+fn owned_connection() {
+    struct Holder {
+        conn: PooledConnection<OkManager>,
+    }
+
+    fn is_static<T: 'static>(_: &T) {}
+
+    let pool = Pool::builder().max_size(1).build(OkManager).unwrap();
+    let conn = pool.get().unwrap();
+    let holder = thread::spawn(move || Holder { conn }).join().unwrap();
+    is_static(&holder);
+    let _: &FakeConnection = &holder.conn;
+    assert_eq!(pool.state().in_use_connections, 1);
+    drop(holder);
+    assert_eq!(pool.state().idle_connections, 1);
+
+    // the connection can outlive every handle to the pool
+    let conn = pool.get().unwrap();
+    drop(pool);
+    let _: &FakeConnection = &conn;
+    thread::spawn(move || drop(conn)).join().unwrap();
+}
+
 #[test]
 fn test_issue_2_unlocked_during_is_valid() {
     struct BlockingChecker {
@@ -164,7 +199,11 @@ fn test_issue_2_unlocked_during_is_valid() {
         }
 
         fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
-            if self.first.compare_and_swap(true, false, Ordering::SeqCst) {
+            if self
+                .first
+                .compare_exchange(true, false, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
                 self.s.lock().send(()).unwrap();
                 self.r.lock().recv().unwrap();
             }
@@ -287,6 +326,9 @@ fn test_connection_customizer() {
     static DROPPED: AtomicBool = AtomicBool::new(false);
     DROPPED.store(false, Ordering::SeqCst);
 
+    static DISCONNECTED: AtomicBool = AtomicBool::new(false);
+    DISCONNECTED.store(false, Ordering::SeqCst);
+
     struct Connection(i32);
 
     impl Drop for Connection {
@@ -312,6 +354,11 @@ fn test_connection_customizer() {
         fn has_broken(&self, _: &mut Connection) -> bool {
             true
         }
+
+        fn disconnect(&self, _: &mut Connection) -> Result<(), Error> {
+            DISCONNECTED.store(true, Ordering::SeqCst);
+            Ok(())
+        }
     }
 
     #[derive(Debug)]
@@ -342,10 +389,46 @@ fn test_connection_customizer() {
         assert_eq!(1, conn.0);
         assert!(!RELEASED.load(Ordering::SeqCst));
         assert!(DROPPED.load(Ordering::SeqCst));
+        assert!(DISCONNECTED.load(Ordering::SeqCst));
     }
     assert!(RELEASED.load(Ordering::SeqCst));
 }
 
+#[test]
+fn test_on_check_in_customizer() {
+    static CHECKED_IN: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug)]
+    struct Customizer;
+
+    impl CustomizeConnection<FakeConnection, Error> for Customizer {
+        fn on_check_in(&self, conn: &mut FakeConnection) -> Result<(), Error> {
+            CHECKED_IN.fetch_add(1, Ordering::SeqCst);
+            if conn.0 {
+                Ok(())
+            } else {
+                Err(Error)
+            }
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_customizer(Box::new(Customizer))
+        .build(OkManager)
+        .unwrap();
+
+    drop(pool.get().unwrap());
+    assert_eq!(CHECKED_IN.load(Ordering::SeqCst), 1);
+    assert_eq!(pool.state().idle_connections, 1);
+
+    pool.get().unwrap().0 = false;
+    assert_eq!(CHECKED_IN.load(Ordering::SeqCst), 2);
+    assert_eq!(pool.state().idle_connections, 0);
+
+    assert!(pool.get().unwrap().0);
+}
+
 #[test]
 fn test_idle_timeout() {
     static DROPPED: AtomicUsize = AtomicUsize::new(0);
@@ -384,12 +467,15 @@ fn test_idle_timeout() {
     let pool = Pool::builder()
         .max_size(5)
         .idle_timeout(Some(Duration::from_secs(1)))
-        .reaper_rate(Duration::from_secs(1))
+        .maintenance_rate(Duration::from_secs(1))
         .build(Handler(AtomicIsize::new(5)))
         .unwrap();
     let conn = pool.get().unwrap();
-    thread::sleep(Duration::from_secs(2));
-    assert_eq!(4, DROPPED.load(Ordering::SeqCst));
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while DROPPED.load(Ordering::SeqCst) < 4 {
+        assert!(Instant::now() < deadline, "{:?}", pool.state());
+        thread::sleep(Duration::from_millis(10));
+    }
     drop(conn);
     assert_eq!(4, DROPPED.load(Ordering::SeqCst));
 }
@@ -432,7 +518,7 @@ fn idle_timeout_partial_use() {
     let pool = Pool::builder()
         .max_size(5)
         .idle_timeout(Some(Duration::from_secs(1)))
-        .reaper_rate(Duration::from_secs(1))
+        .maintenance_rate(Duration::from_secs(1))
         .build(Handler(AtomicIsize::new(5)))
         .unwrap();
     for _ in 0..8 {
@@ -482,7 +568,7 @@ fn test_max_lifetime() {
         .max_size(5)
         .max_lifetime(Some(Duration::from_secs(1)))
         .connection_timeout(Duration::from_secs(1))
-        .reaper_rate(Duration::from_secs(1))
+        .maintenance_rate(Duration::from_secs(1))
         .build(Handler(AtomicIsize::new(5)))
         .unwrap();
     let conn = pool.get().unwrap();
@@ -494,6 +580,111 @@ fn test_max_lifetime() {
     assert!(pool.get().is_err());
 }
 
+#[test]
+fn max_lifetime_on_return() {
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Connection;
+
+    impl Drop for Connection {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    struct Handler(AtomicIsize);
+
+    impl ManageConnection for Handler {
+        type Connection = Connection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<Connection, Error> {
+            if self.0.fetch_sub(1, Ordering::SeqCst) > 0 {
+                Ok(Connection)
+            } else {
+                Err(Error)
+            }
+        }
+
+        fn is_valid(&self, _: &mut Connection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut Connection) -> bool {
+            false
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .max_lifetime(Some(Duration::from_secs(1)))
+        .maintenance_rate(Duration::from_secs(60))
+        .build(Handler(AtomicIsize::new(1)))
+        .unwrap();
+    let conn = pool.get().unwrap();
+    thread::sleep(Duration::from_millis(1100));
+    assert_eq!(0, DROPPED.load(Ordering::SeqCst));
+    drop(conn);
+    assert_eq!(1, DROPPED.load(Ordering::SeqCst));
+    assert_eq!(0, pool.state().connections);
+}
+
+#[test]
+fn lifetime_jitter() {
+    let pool = Pool::builder()
+        .max_size(5)
+        .max_lifetime(Some(Duration::from_secs(1)))
+        .lifetime_jitter(Some(Duration::from_millis(500)))
+        .maintenance_rate(Duration::from_secs(60))
+        .build(OkManager)
+        .unwrap();
+    assert_eq!(pool.lifetime_jitter(), Some(Duration::from_millis(500)));
+
+    let conns = (0..5).map(|_| pool.get().unwrap()).collect::<Vec<_>>();
+    for conn in &conns {
+        assert!(conn.conn.as_ref().unwrap().lifetime_jitter < Duration::from_millis(500));
+    }
+
+    // Every connection is expired once the full max_lifetime has passed.
+    thread::sleep(Duration::from_millis(1100));
+    drop(conns);
+    assert_eq!(0, pool.state().connections);
+
+    let err = Pool::builder()
+        .max_lifetime(Some(Duration::from_secs(1)))
+        .lifetime_jitter(Some(Duration::from_secs(1)))
+        .build(OkManager)
+        .err()
+        .unwrap();
+    assert!(err
+        .to_string()
+        .contains("lifetime_jitter must be smaller than max_lifetime"));
+}
+
+#[test]
+fn max_uses() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .max_uses(Some(2))
+        .build(OkManager)
+        .unwrap();
+    assert_eq!(pool.max_uses(), Some(2));
+
+    let first = pool.get().unwrap().conn.as_ref().unwrap().id;
+    let second = pool.get().unwrap().conn.as_ref().unwrap().id;
+    assert_eq!(first, second);
+
+    let third = pool.get().unwrap().conn.as_ref().unwrap().id;
+    assert_ne!(first, third);
+
+    let err = Pool::builder()
+        .max_uses(Some(0))
+        .build(OkManager)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("max_uses must be positive"));
+}
+
 #[test]
 fn min_idle() {
     struct Connection;
@@ -534,6 +725,54 @@ fn min_idle() {
     assert_eq!(5, pool.state().connections);
 }
 
+#[test]
+fn state_in_use_and_pending() {
+    struct Handler(Arc<AtomicBool>);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            while !self.0.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let release = Arc::new(AtomicBool::new(false));
+    let pool = Pool::builder()
+        .max_size(2)
+        .build_unchecked(Handler(release.clone()));
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while pool.state().pending_connections < 2 {
+        assert!(Instant::now() < deadline, "{:?}", pool.state());
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(0, pool.state().connections);
+    assert_eq!(0, pool.state().in_use_connections);
+
+    release.store(true, Ordering::SeqCst);
+    pool.wait_until_ready(2, Duration::from_secs(5)).unwrap();
+    let conn = pool.get().unwrap();
+    let state = pool.state();
+    assert_eq!(1, state.idle_connections);
+    assert_eq!(1, state.in_use_connections);
+    assert_eq!(0, state.pending_connections);
+    drop(conn);
+    assert_eq!(0, pool.state().in_use_connections);
+}
+
 #[test]
 fn conns_drop_on_pool_drop() {
     static DROPPED: AtomicUsize = AtomicUsize::new(0);
@@ -722,3 +961,3245 @@ fn extensions() {
     let conn = pool.get().unwrap();
     assert_eq!(PooledConnection::extensions(&conn).get::<i32>(), Some(&1));
 }
+
+#[test]
+fn test_on_check_in() {
+    struct Handler(AtomicBool);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            if self.0.load(Ordering::SeqCst) {
+                Ok(())
+            } else {
+                Err(Error)
+            }
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .test_on_check_out(false)
+        .test_on_check_in(true)
+        .build(Handler(AtomicBool::new(true)))
+        .unwrap();
+
+    drop(pool.get().unwrap());
+    assert_eq!(1, pool.state().idle_connections);
+
+    let conn = pool.get().unwrap();
+    pool.0.manager().0.store(false, Ordering::SeqCst);
+    drop(conn);
+    assert_eq!(0, pool.state().idle_connections);
+}
+
+#[test]
+fn test_while_idle() {
+    struct Handler {
+        valid: AtomicBool,
+        remaining: AtomicIsize,
+    }
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            if self.remaining.fetch_sub(1, Ordering::SeqCst) > 0 {
+                Ok(FakeConnection(true))
+            } else {
+                Err(Error)
+            }
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            if self.valid.load(Ordering::SeqCst) {
+                Ok(())
+            } else {
+                Err(Error)
+            }
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(3)
+        .test_while_idle(Some(Duration::from_millis(100)))
+        .build(Handler {
+            valid: AtomicBool::new(true),
+            remaining: AtomicIsize::new(3),
+        })
+        .unwrap();
+
+    thread::sleep(Duration::from_millis(250));
+    assert_eq!(3, pool.state().idle_connections);
+
+    pool.0.manager().valid.store(false, Ordering::SeqCst);
+    thread::sleep(Duration::from_millis(250));
+    assert_eq!(0, pool.state().idle_connections);
+    assert_eq!(0, pool.state().connections);
+}
+
+#[test]
+fn invalid_config() {
+    let err = Pool::builder().max_size(0).build(OkManager).err().unwrap();
+    assert!(err.to_string().contains("max_size must be positive"));
+
+    let err = Pool::builder()
+        .max_size(2)
+        .min_idle(Some(3))
+        .build(OkManager)
+        .err()
+        .unwrap();
+    assert!(err
+        .to_string()
+        .contains("min_idle must be no larger than max_size"));
+
+    let err = Pool::builder()
+        .connection_timeout(Duration::from_secs(0))
+        .build(OkManager)
+        .err()
+        .unwrap();
+    assert!(err
+        .to_string()
+        .contains("connection_timeout must be positive"));
+}
+
+#[test]
+#[should_panic(expected = "idle_timeout must be positive")]
+fn invalid_config_unchecked() {
+    Pool::builder()
+        .idle_timeout(Some(Duration::from_secs(0)))
+        .build_unchecked(OkManager);
+}
+
+#[test]
+fn max_waiters() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .max_waiters(Some(1))
+        .connection_timeout(Duration::from_secs(1))
+        .build(OkManager)
+        .unwrap();
+
+    let conn = pool.get().unwrap();
+
+    let p2 = pool.clone();
+    let t = thread::spawn(move || p2.get().is_ok());
+    while pool.0.internals.lock().waiters == 0 {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let started = Instant::now();
+    let err = pool.get().err().unwrap();
+    assert!(started.elapsed() < Duration::from_millis(500));
+    assert!(err.to_string().contains("too many callers"));
+
+    drop(conn);
+    assert!(t.join().unwrap());
+}
+
+#[test]
+fn fair_queueing() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .fair_queueing(true)
+        .build(OkManager)
+        .unwrap();
+    let order = Arc::new(Mutex::new(vec![]));
+
+    let conn = pool.get().unwrap();
+
+    let mut threads = vec![];
+    for i in 0..3 {
+        let p2 = pool.clone();
+        let order = order.clone();
+        threads.push(thread::spawn(move || {
+            let _conn = p2.get().unwrap();
+            order.lock().push(i);
+            thread::sleep(Duration::from_millis(10));
+        }));
+        while pool.0.internals.lock().waiter_queue.len() != i + 1 {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    assert!(pool.try_get().is_none());
+    drop(conn);
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(*order.lock(), vec![0, 1, 2]);
+}
+
+#[test]
+fn idle_strategy() {
+    fn checkout_order(strategy: IdleStrategy) -> Vec<i32> {
+        let pool = Pool::builder()
+            .max_size(3)
+            .idle_strategy(strategy)
+            .build(OkManager)
+            .unwrap();
+
+        let mut conns = (0..3).map(|_| pool.get().unwrap()).collect::<Vec<_>>();
+        for (i, conn) in conns.iter_mut().enumerate() {
+            PooledConnection::extensions_mut(conn).insert(i as i32);
+        }
+        // return the connections in order 0, 1, 2
+        for conn in conns {
+            drop(conn);
+        }
+
+        let conn = pool.get().unwrap();
+        let first = *PooledConnection::extensions(&conn).get::<i32>().unwrap();
+        let conn2 = pool.get().unwrap();
+        let second = *PooledConnection::extensions(&conn2).get::<i32>().unwrap();
+        vec![first, second]
+    }
+
+    assert_eq!(checkout_order(IdleStrategy::Lifo), vec![2, 1]);
+    assert_eq!(checkout_order(IdleStrategy::Fifo), vec![0, 1]);
+
+    let order = checkout_order(IdleStrategy::Random);
+    assert_ne!(order[0], order[1]);
+    assert!(order.iter().all(|i| (0..3).contains(i)));
+
+    // every idle connection is eventually picked
+    let pool = Pool::builder()
+        .max_size(3)
+        .idle_strategy(IdleStrategy::Random)
+        .build(OkManager)
+        .unwrap();
+    let mut conns = (0..3).map(|_| pool.get().unwrap()).collect::<Vec<_>>();
+    for (i, conn) in conns.iter_mut().enumerate() {
+        PooledConnection::extensions_mut(conn).insert(i);
+    }
+    drop(conns);
+    let mut seen = [false; 3];
+    for _ in 0..100 {
+        let conn = pool.get().unwrap();
+        seen[*PooledConnection::extensions(&conn).get::<usize>().unwrap()] = true;
+    }
+    assert_eq!(seen, [true; 3]);
+}
+
+#[test]
+fn exponential_backoff() {
+    let backoff = ExponentialBackoff::new(Duration::from_millis(100), Duration::from_secs(1));
+    assert_eq!(backoff.delay(1), Duration::from_millis(100));
+    assert_eq!(backoff.delay(2), Duration::from_millis(200));
+    assert_eq!(backoff.delay(4), Duration::from_millis(800));
+    assert_eq!(backoff.delay(5), Duration::from_secs(1));
+    assert_eq!(backoff.delay(100), Duration::from_secs(1));
+
+    let backoff = backoff.jitter(true);
+    for attempt in 1..10 {
+        let delay = backoff.delay(attempt);
+        assert!(delay <= Duration::from_secs(1));
+        assert!(delay >= Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn custom_backoff() {
+    struct Handler(AtomicIsize);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            if self.0.fetch_sub(1, Ordering::SeqCst) > 0 {
+                Err(Error)
+            } else {
+                Ok(FakeConnection(true))
+            }
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let started = Instant::now();
+    let pool = Pool::builder()
+        .max_size(1)
+        .backoff(Box::new(FixedBackoff(Duration::from_millis(10))))
+        .build(Handler(AtomicIsize::new(5)))
+        .unwrap();
+    // the default backoff would take over 10 seconds to get through 5 failures
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(1, pool.state().connections);
+}
+
+#[test]
+fn connect_timeout() {
+    struct Handler(Arc<AtomicUsize>);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            thread::sleep(Duration::from_millis(100));
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+
+        fn disconnect(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    let disconnects = Arc::new(AtomicUsize::new(0));
+
+    let err = Pool::builder()
+        .max_size(1)
+        .connect_timeout(Some(Duration::from_millis(50)))
+        .connection_timeout(Duration::from_millis(500))
+        .build(Handler(disconnects.clone()))
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("exceeding the connect timeout"));
+    // connections which took too long are closed rather than leaked
+    assert!(disconnects.load(Ordering::SeqCst) > 0);
+
+    Pool::builder()
+        .max_size(1)
+        .connect_timeout(Some(Duration::from_millis(250)))
+        .build(Handler(disconnects))
+        .unwrap();
+}
+
+#[test]
+fn wait_timeout() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_secs(30))
+        .wait_timeout(Some(Duration::from_millis(100)))
+        .build(OkManager)
+        .unwrap();
+    assert_eq!(pool.wait_timeout(), Some(Duration::from_millis(100)));
+
+    let _conn = pool.get().unwrap();
+    let start = Instant::now();
+    pool.get().unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(pool.get_tagged("tag").is_err());
+
+    // the wait timeout can be changed separately from the connection timeout
+    pool.update_config(ConfigUpdate::new().wait_timeout(None))
+        .unwrap();
+    assert_eq!(pool.wait_timeout(), None);
+}
+
+#[test]
+fn thread_name_prefix() {
+    struct Handler(Mutex<Option<String>>);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            *self.0.lock() = thread::current().name().map(|s| s.to_string());
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .thread_name_prefix("r2d2-test-worker-".to_string())
+        .build(Handler(Mutex::new(None)))
+        .unwrap();
+    let name = pool.0.manager().0.lock().clone().unwrap();
+    assert!(name.starts_with("r2d2-test-worker-"));
+}
+
+#[test]
+fn pool_name() {
+    struct Handler(Mutex<Option<String>>);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            *self.0.lock() = thread::current().name().map(|s| s.to_string());
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    #[derive(Debug)]
+    struct TestEventHandler(Arc<Mutex<Vec<Option<String>>>>);
+
+    impl HandleEvent for TestEventHandler {
+        fn handle_checkout(&self, event: CheckoutEvent) {
+            self.0.lock().push(event.pool_name().map(|s| s.to_string()));
+        }
+    }
+
+    let names = Arc::new(Mutex::new(vec![]));
+    let pool = Pool::builder()
+        .max_size(1)
+        .pool_name("primary".to_string())
+        .event_handler(Box::new(TestEventHandler(names.clone())))
+        .build(Handler(Mutex::new(None)))
+        .unwrap();
+    assert_eq!(pool.pool_name().as_deref(), Some("primary"));
+
+    let thread = pool.0.manager().0.lock().clone().unwrap();
+    assert!(thread.starts_with("r2d2-primary-worker-"));
+
+    drop(pool.get().unwrap());
+    assert_eq!(*names.lock(), vec![Some("primary".to_string())]);
+    assert_eq!(pool.stats_snapshot().pool_name.as_deref(), Some("primary"));
+}
+
+#[test]
+fn builder_from_env() {
+    env::set_var("R2D2_FROM_ENV_MAX_SIZE", "3");
+    env::set_var("R2D2_FROM_ENV_MIN_IDLE", "1");
+    env::set_var("R2D2_FROM_ENV_IDLE_TIMEOUT", "none");
+    env::set_var("R2D2_FROM_ENV_CONNECTION_TIMEOUT", "1500ms");
+    env::set_var("R2D2_FROM_ENV_WAIT_TIMEOUT", "500ms");
+    env::set_var("R2D2_FROM_ENV_MAX_LIFETIME", "2m");
+    env::set_var("R2D2_FROM_ENV_LIFETIME_JITTER", "30s");
+    env::set_var("R2D2_FROM_ENV_TEST_ON_CHECK_OUT", "false");
+    env::set_var("R2D2_FROM_ENV_IDLE_STRATEGY", "fifo");
+    env::set_var("R2D2_FROM_ENV_MAX_CONCURRENT_CONNECTS", "2");
+    env::set_var("R2D2_FROM_ENV_LEAK_DETECTION_THRESHOLD", "5m");
+    env::set_var("R2D2_FROM_ENV_SLOW_CHECKOUT_THRESHOLD", "250ms");
+    env::set_var("R2D2_FROM_ENV_STATS_INTERVAL", "1m");
+    env::set_var("R2D2_FROM_ENV_CAPTURE_BACKTRACES", "true");
+
+    let pool = Builder::from_env("R2D2_FROM_ENV")
+        .unwrap()
+        .build(OkManager)
+        .unwrap();
+    assert_eq!(pool.max_size(), 3);
+    assert_eq!(pool.min_idle(), Some(1));
+    assert_eq!(pool.idle_timeout(), None);
+    assert_eq!(pool.connection_timeout(), Duration::from_millis(1500));
+    assert_eq!(pool.wait_timeout(), Some(Duration::from_millis(500)));
+    assert_eq!(pool.max_lifetime(), Some(Duration::from_secs(120)));
+    assert_eq!(pool.lifetime_jitter(), Some(Duration::from_secs(30)));
+    assert!(!pool.test_on_check_out());
+    assert_eq!(pool.idle_strategy(), IdleStrategy::Fifo);
+    assert_eq!(pool.max_concurrent_connects(), Some(2));
+    assert_eq!(
+        pool.leak_detection_threshold(),
+        Some(Duration::from_secs(300))
+    );
+    assert_eq!(
+        pool.slow_checkout_threshold(),
+        Some(Duration::from_millis(250))
+    );
+    assert_eq!(pool.stats_interval(), Duration::from_secs(60));
+    assert!(pool.capture_backtraces());
+
+    env::set_var("R2D2_FROM_ENV_BAD_CONNECTION_TIMEOUT", "soon");
+    let err = Builder::<OkManager>::from_env("R2D2_FROM_ENV_BAD")
+        .err()
+        .unwrap();
+    assert!(err
+        .to_string()
+        .contains("R2D2_FROM_ENV_BAD_CONNECTION_TIMEOUT value `soon`"));
+}
+
+#[test]
+fn warmup_count() {
+    struct Handler;
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            thread::sleep(Duration::from_millis(100));
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(3)
+        .warmup_count(Some(0))
+        .build(Handler)
+        .unwrap();
+    assert_eq!(0, pool.state().connections);
+
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(3, pool.state().connections);
+
+    let err = Pool::builder()
+        .max_size(3)
+        .min_idle(Some(1))
+        .warmup_count(Some(2))
+        .build(Handler)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("warmup_count"));
+}
+
+#[test]
+fn initialization_timeout() {
+    let manager = NthConnectFailManager { n: Mutex::new(0) };
+    let started = Instant::now();
+    let err = Pool::builder()
+        .initialization_timeout(Some(Duration::from_millis(500)))
+        .build(manager)
+        .err()
+        .unwrap();
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(err.to_string().contains("pool initialization"));
+    assert!(err.to_string().contains("blammo"));
+}
+
+#[test]
+fn builder_clone() {
+    #[derive(Debug)]
+    struct TestEventHandler(Arc<AtomicUsize>);
+
+    impl HandleEvent for TestEventHandler {
+        fn handle_acquire(&self, _: AcquireEvent) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let acquired = Arc::new(AtomicUsize::new(0));
+    let builder = Pool::builder()
+        .max_size(2)
+        .event_handler(Box::new(TestEventHandler(acquired.clone())));
+
+    let a = builder.clone().build(OkManager).unwrap();
+    let b = builder.max_size(3).build(OkManager).unwrap();
+    assert_eq!(a.max_size(), 2);
+    assert_eq!(b.max_size(), 3);
+    assert_eq!(acquired.load(Ordering::SeqCst), 5);
+}
+
+#[test]
+fn update_config() {
+    let pool = Pool::builder().max_size(2).build(OkManager).unwrap();
+
+    let a = pool.get().unwrap();
+    let b = pool.get().unwrap();
+    assert!(pool.get_timeout(Duration::from_millis(100)).is_err());
+
+    pool.update_config(ConfigUpdate::new().max_size(3).test_on_check_out(false))
+        .unwrap();
+    assert_eq!(pool.max_size(), 3);
+    assert!(!pool.test_on_check_out());
+    let c = pool.get_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(pool.state().connections, 3);
+
+    pool.update_config(ConfigUpdate::new().max_size(1)).unwrap();
+    drop((a, b, c));
+    assert_eq!(pool.state().connections, 1);
+    assert_eq!(pool.state().idle_connections, 1);
+
+    let err = pool
+        .update_config(ConfigUpdate::new().min_idle(Some(2)))
+        .err()
+        .unwrap();
+    assert!(err
+        .to_string()
+        .contains("min_idle must be no larger than max_size"));
+    assert_eq!(pool.min_idle(), None);
+
+    // updates are held to the same rules as the builder
+    let pool = Pool::builder()
+        .max_size(4)
+        .warmup_count(Some(2))
+        .build(OkManager)
+        .unwrap();
+    let err = pool
+        .update_config(ConfigUpdate::new().max_size(1))
+        .err()
+        .unwrap();
+    assert!(err
+        .to_string()
+        .contains("warmup_count must be no larger than min_idle"));
+    assert_eq!(pool.max_size(), 4);
+
+    // the default backoff follows the connection timeout, a custom one is kept
+    pool.update_config(ConfigUpdate::new().connection_timeout(Duration::from_secs(4)))
+        .unwrap();
+    assert!(format!("{:?}", pool).contains("max: 2s"));
+    let pool = Pool::builder()
+        .backoff(Box::new(FixedBackoff(Duration::from_millis(100))))
+        .build(OkManager)
+        .unwrap();
+    pool.update_config(ConfigUpdate::new().connection_timeout(Duration::from_secs(4)))
+        .unwrap();
+    assert!(format!("{:?}", pool).contains("FixedBackoff(100ms)"));
+}
+
+#[test]
+fn resize() {
+    let pool = Pool::builder().max_size(2).build(OkManager).unwrap();
+    assert_eq!(pool.state().connections, 2);
+
+    pool.resize(4).unwrap();
+    assert_eq!(pool.max_size(), 4);
+    let conns = (0..4).map(|_| pool.get().unwrap()).collect::<Vec<_>>();
+    assert_eq!(pool.state().in_use_connections, 4);
+
+    pool.resize(1).unwrap();
+    drop(conns);
+    assert_eq!(pool.state().connections, 1);
+
+    assert!(pool.resize(0).is_err());
+    assert_eq!(pool.max_size(), 1);
+}
+
+#[test]
+fn close() {
+    let pool = Pool::builder().max_size(1).build(OkManager).unwrap();
+    let conn = pool.get().unwrap();
+
+    let p2 = pool.clone();
+    let t = thread::spawn(move || p2.get().err().unwrap());
+    thread::sleep(Duration::from_millis(100));
+
+    assert!(!pool.close(Duration::from_millis(100)));
+    assert!(pool.is_closed());
+    let err = t.join().unwrap();
+    assert!(err.to_string().contains("connection pool closed"));
+    assert!(pool.get().is_err());
+    assert!(pool.try_get().is_none());
+
+    drop(conn);
+    assert_eq!(pool.state().connections, 0);
+    assert!(pool.close(Duration::from_secs(1)));
+}
+
+#[test]
+fn clear() {
+    let pool = Pool::builder().max_size(2).build(OkManager).unwrap();
+    let conn = pool.get().unwrap();
+    let old_id = conn.conn.as_ref().unwrap().id;
+
+    pool.clear();
+    drop(conn);
+
+    // the cleared connections are replaced in the background
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(pool.state().connections, 2);
+    assert_eq!(pool.state().idle_connections, 2);
+    let a = pool.get().unwrap();
+    let b = pool.get().unwrap();
+    assert_ne!(a.conn.as_ref().unwrap().id, old_id);
+    assert_ne!(b.conn.as_ref().unwrap().id, old_id);
+}
+
+#[test]
+fn invalidate() {
+    let pool = Pool::builder().max_size(1).build(OkManager).unwrap();
+
+    let mut conn = pool.get().unwrap();
+    let id = conn.conn.as_ref().unwrap().id;
+    PooledConnection::invalidate(&mut conn);
+    drop(conn);
+
+    let conn = pool.get().unwrap();
+    assert_ne!(conn.conn.as_ref().unwrap().id, id);
+}
+
+#[test]
+fn detach() {
+    let pool = Pool::builder().max_size(1).build(OkManager).unwrap();
+
+    let conn = PooledConnection::detach(pool.get().unwrap());
+    assert!(conn.0);
+    assert_eq!(pool.state().in_use_connections, 0);
+
+    // the detached connection's slot is refilled
+    let conn = pool.get_timeout(Duration::from_secs(1)).unwrap();
+    drop(conn);
+    assert_eq!(pool.state().connections, 1);
+}
+
+#[test]
+fn debug() {
+    #[derive(Debug)]
+    struct AppState {
+        pool: Pool<OkManager>,
+    }
+
+    let state = AppState {
+        pool: Pool::builder().max_size(1).build(OkManager).unwrap(),
+    };
+    let conn = state.pool.get().unwrap();
+
+    let s = format!("{:?}", state);
+    assert!(s.contains("in_use_connections: 1"));
+    assert!(s.contains("waiters: 0"));
+    let s = format!("{:?}", conn);
+    assert!(s.starts_with("PooledConnection { id: "));
+}
+
+#[test]
+fn get_deadline() {
+    let pool = Pool::builder().max_size(1).build(OkManager).unwrap();
+
+    let deadline = Instant::now() + Duration::from_millis(100);
+    let conn = pool.get_deadline(deadline).unwrap();
+    assert!(pool.get_deadline(deadline).is_err());
+    assert!(Instant::now() >= deadline);
+    drop(conn);
+
+    // a passed deadline still hands out an idle connection
+    pool.get_deadline(deadline).unwrap();
+}
+
+#[test]
+fn wait_until_ready() {
+    struct Handler(AtomicBool);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            thread::sleep(Duration::from_millis(100));
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            if self.0.load(Ordering::SeqCst) {
+                Ok(())
+            } else {
+                Err(Error)
+            }
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(2)
+        .build_unchecked(Handler(AtomicBool::new(false)));
+    assert!(!pool.is_ready());
+    assert!(pool.wait_until_ready(2, Duration::from_millis(10)).is_err());
+
+    // connections which fail their health check don't count
+    assert!(pool
+        .wait_until_ready(2, Duration::from_millis(500))
+        .is_err());
+    assert!(pool.statistics().validation_failures > 0);
+
+    pool.manager().0.store(true, Ordering::SeqCst);
+    pool.wait_until_ready(2, Duration::from_secs(5)).unwrap();
+    assert!(pool.is_ready());
+    assert_eq!(pool.state().connections, 2);
+    assert!(pool
+        .connection_statistics()
+        .iter()
+        .all(|stats| stats.last_validated_at.is_some()));
+}
+
+#[test]
+fn weak_pool() {
+    let pool = Pool::builder().max_size(1).build(OkManager).unwrap();
+    let weak = pool.downgrade();
+
+    let upgraded = weak.upgrade().unwrap();
+    assert_eq!(upgraded.state().connections, 1);
+    drop(upgraded);
+
+    drop(pool);
+    assert!(weak.upgrade().is_none());
+}
+
+#[test]
+fn with() {
+    #[derive(Debug)]
+    enum TestError {
+        Pool,
+        Query,
+    }
+
+    impl From<crate::Error> for TestError {
+        fn from(_: crate::Error) -> TestError {
+            TestError::Pool
+        }
+    }
+
+    #[derive(Debug)]
+    struct TestEventHandler(Arc<AtomicUsize>);
+
+    impl HandleEvent for TestEventHandler {
+        fn handle_release(&self, _: ReleaseEvent) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let released = Arc::new(AtomicUsize::new(0));
+    let pool = Pool::builder()
+        .max_size(1)
+        .event_handler(Box::new(TestEventHandler(released.clone())))
+        .build(OkManager)
+        .unwrap();
+
+    assert_eq!(pool.with(|_| Ok::<_, TestError>(1)).unwrap(), 1);
+    assert_eq!(released.load(Ordering::SeqCst), 0);
+
+    let mut attempts = 0;
+    let r = pool.with_retries(2, |_| {
+        attempts += 1;
+        Err::<(), _>(TestError::Query)
+    });
+    assert!(matches!(r, Err(TestError::Query)));
+    assert_eq!(attempts, 3);
+    assert_eq!(released.load(Ordering::SeqCst), 3);
+
+    let mut attempts = 0;
+    let r = pool.with_retries(2, |_| {
+        attempts += 1;
+        if attempts < 2 {
+            Err(TestError::Query)
+        } else {
+            Ok(attempts)
+        }
+    });
+    assert_eq!(r.unwrap(), 2);
+
+    // checkout failures are returned without retrying
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_millis(100))
+        .build(OkManager)
+        .unwrap();
+    let _conn = pool.get().unwrap();
+    let start = Instant::now();
+    let r = pool.with_retries(3, |_| Ok::<_, TestError>(()));
+    assert!(matches!(r, Err(TestError::Pool)));
+    assert!(start.elapsed() < Duration::from_millis(200));
+}
+
+#[test]
+fn map() {
+    let pool = Pool::builder().max_size(1).build(OkManager).unwrap();
+
+    let mut valid = PooledConnection::map(pool.get().unwrap(), |conn| &mut conn.0);
+    assert!(*valid);
+    *valid = false;
+    assert_eq!(pool.state().in_use_connections, 1);
+    drop(valid);
+
+    let conn = pool.get().unwrap();
+    assert!(!conn.0);
+    drop(conn);
+
+    // a panicking projection still returns the connection to the pool
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        PooledConnection::map(pool.get().unwrap(), |_| -> &mut bool { panic!("boom") })
+    }));
+    assert!(result.is_err());
+    let state = pool.state();
+    assert_eq!(state.connections, 1);
+    assert_eq!(state.idle_connections, 1);
+    assert!(pool.try_get().is_some());
+}
+
+#[test]
+fn dedicated_connection() {
+    let pool = Pool::builder().max_size(1).build(OkManager).unwrap();
+    let _conn = pool.get().unwrap();
+
+    let dedicated = pool.dedicated_connection().unwrap();
+    assert!(dedicated.0);
+    assert_eq!(pool.state().connections, 1);
+    assert_eq!(pool.state().idle_connections, 0);
+}
+
+#[test]
+fn dedicated_connection_setup() {
+    #[derive(Default)]
+    struct Handler {
+        connecting: AtomicUsize,
+        most_connecting: AtomicUsize,
+        deadlines: Mutex<Vec<Option<Instant>>>,
+        destroyed: AtomicUsize,
+        disconnected: AtomicUsize,
+    }
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            unreachable!()
+        }
+
+        fn connect_with_context(&self, context: &ConnectContext) -> Result<FakeConnection, Error> {
+            if context.reason() == ConnectReason::Dedicated {
+                self.deadlines.lock().push(context.deadline());
+            }
+            let connecting = self.connecting.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most_connecting.fetch_max(connecting, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(50));
+            self.connecting.fetch_sub(1, Ordering::SeqCst);
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+
+        fn on_destroy(&self, _: &mut FakeConnection) {
+            self.destroyed.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn disconnect(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            self.disconnected.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[derive(Debug)]
+    struct Customizer(Arc<AtomicBool>);
+
+    impl CustomizeConnection<FakeConnection, Error> for Customizer {
+        fn on_acquire(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            if self.0.load(Ordering::SeqCst) {
+                Err(Error)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    let fail = Arc::new(AtomicBool::new(false));
+    let pool = Pool::builder()
+        .max_size(1)
+        .min_idle(Some(0))
+        .max_concurrent_connects(Some(1))
+        .connect_timeout(Some(Duration::from_secs(5)))
+        .connection_customizer(Box::new(Customizer(fail.clone())))
+        .build(Handler::default())
+        .unwrap();
+
+    // the dedicated connection waits for the pool's own attempt to finish
+    let pool2 = pool.clone();
+    let t = thread::spawn(move || drop(pool2.get().unwrap()));
+    let start = Instant::now();
+    pool.dedicated_connection().unwrap();
+    t.join().unwrap();
+    assert_eq!(pool.manager().most_connecting.load(Ordering::SeqCst), 1);
+    let deadlines = pool.manager().deadlines.lock().clone();
+    assert!(deadlines[0].unwrap() <= start + Duration::from_secs(6));
+
+    // a connection the customizer fails to set up is closed like any other
+    fail.store(true, Ordering::SeqCst);
+    assert!(pool.dedicated_connection().is_err());
+    assert_eq!(pool.manager().destroyed.load(Ordering::SeqCst), 1);
+    assert_eq!(pool.manager().disconnected.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn prewarm() {
+    let pool = Pool::builder()
+        .max_size(5)
+        .min_idle(Some(1))
+        .build(OkManager)
+        .unwrap();
+    assert_eq!(pool.state().connections, 1);
+
+    pool.prewarm(4, Duration::from_secs(1)).unwrap();
+    assert_eq!(pool.state().connections, 4);
+
+    pool.prewarm(10, Duration::from_secs(1)).unwrap();
+    assert_eq!(pool.state().connections, 5);
+}
+
+#[test]
+fn manager() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .build(NthConnectFailManager { n: Mutex::new(1) })
+        .unwrap();
+    assert_eq!(*pool.manager().n.lock(), 0);
+}
+
+#[test]
+fn pool_extensions() {
+    #[derive(Debug, PartialEq)]
+    struct ShardId(u32);
+
+    let pool = Pool::builder().max_size(1).build(OkManager).unwrap();
+    assert!(pool.extensions().get::<ShardId>().is_none());
+
+    pool.extensions_mut().insert(ShardId(3));
+    let p2 = pool.clone();
+    assert_eq!(p2.extensions().get::<ShardId>(), Some(&ShardId(3)));
+}
+
+#[test]
+fn subscribe() {
+    let pool = Pool::builder().max_size(1).build(OkManager).unwrap();
+    let events = pool.subscribe();
+
+    let mut conn = pool.get().unwrap();
+    PooledConnection::invalidate(&mut conn);
+    drop(conn);
+
+    let id = match events.recv().unwrap() {
+        Event::Checkout(event) => event.connection_id(),
+        event => panic!("unexpected event {:?}", event),
+    };
+    match events.recv().unwrap() {
+        Event::Checkin(event) => assert_eq!(event.connection_id(), id),
+        event => panic!("unexpected event {:?}", event),
+    }
+    match events.recv().unwrap() {
+        Event::Release(event) => assert_eq!(event.connection_id(), id),
+        event => panic!("unexpected event {:?}", event),
+    }
+    // the replacement is opened after the release is reported
+    match events.recv().unwrap() {
+        Event::Acquire(event) => assert_ne!(event.connection_id(), id),
+        event => panic!("unexpected event {:?}", event),
+    }
+
+    drop(events);
+    pool.get().unwrap();
+    assert!(pool.0.subscribers.lock().is_empty());
+    assert!(!pool.0.has_subscribers.load(Ordering::Relaxed));
+
+    let manager = ValidatedManager::new(
+        OkManager,
+        |conn: &mut FakeConnection| {
+            if conn.0 {
+                Ok(())
+            } else {
+                Err(Error)
+            }
+        },
+    );
+    let pool = Pool::builder().max_size(1).build(manager).unwrap();
+    pool.get().unwrap().0 = false;
+    let events = pool.subscribe();
+    pool.get().unwrap();
+    let failure = events
+        .try_iter()
+        .find_map(|event| match event {
+            Event::ValidationFailure(event) => Some(event),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(failure.error(), "blammo");
+}
+
+#[test]
+fn into_inner() {
+    let pool = Pool::builder().max_size(2).build(OkManager).unwrap();
+    let conn = pool.get().unwrap();
+    let p2 = pool.clone();
+
+    let t = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        drop(conn);
+    });
+    let conns = pool.into_inner(Duration::from_secs(1));
+    t.join().unwrap();
+
+    assert_eq!(conns.len(), 2);
+    assert!(p2.is_closed());
+    assert_eq!(p2.state().connections, 0);
+}
+
+#[test]
+fn get_many() {
+    let pool = Pool::builder().max_size(3).build(OkManager).unwrap();
+
+    let conns = pool.get_many(2, Duration::from_secs(1)).unwrap();
+    assert_eq!(conns.len(), 2);
+
+    assert!(pool.get_many(2, Duration::from_millis(100)).is_err());
+    assert_eq!(pool.state().idle_connections, 1);
+    drop(conns);
+
+    let p2 = pool.clone();
+    let t = thread::spawn(move || p2.get_many(3, Duration::from_secs(5)).unwrap().len());
+    let conns = pool.get_many(3, Duration::from_secs(5)).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    drop(conns);
+    assert_eq!(t.join().unwrap(), 3);
+}
+
+#[test]
+fn idle_available() {
+    let pool = Pool::builder().max_size(2).build(OkManager).unwrap();
+    assert_eq!(pool.idle_available(), 2);
+
+    let conn = pool.get().unwrap();
+    assert_eq!(pool.idle_available(), 1);
+    let conn2 = pool.get().unwrap();
+    assert_eq!(pool.idle_available(), 0);
+
+    drop(conn);
+    assert_eq!(pool.idle_available(), 1);
+    drop(conn2);
+    pool.clear();
+    pool.wait_until_ready(2, Duration::from_secs(1)).unwrap();
+    assert_eq!(pool.idle_available(), 2);
+}
+
+#[test]
+fn synchronous() {
+    struct Handler(Mutex<Vec<thread::ThreadId>>);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            self.0.lock().push(thread::current().id());
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(2)
+        .min_idle(Some(1))
+        .synchronous(true)
+        .build(Handler(Mutex::new(vec![])))
+        .unwrap();
+    assert!(pool.synchronous());
+    assert_eq!(pool.state().connections, 1);
+
+    let a = pool.get().unwrap();
+    let b = pool.get().unwrap();
+    assert_eq!(pool.state().connections, 2);
+    let ids = pool.manager().0.lock().clone();
+    assert_eq!(ids, vec![thread::current().id(); 2]);
+    drop((a, b));
+
+    let pool = Pool::builder()
+        .max_size(2)
+        .min_idle(Some(0))
+        .synchronous(true)
+        .build(NthConnectFailManager { n: Mutex::new(1) })
+        .unwrap();
+    let _conn = pool.get().unwrap();
+    let start = Instant::now();
+    let err = pool.get().unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(err.to_string(), "unable to open connection: blammo");
+
+    // a synchronous pool has no use for a thread pool
+    let thread_pool = Arc::new(scheduled_thread_pool::ScheduledThreadPool::new(1));
+    assert!(Pool::builder()
+        .synchronous(true)
+        .thread_pool(thread_pool)
+        .build(OkManager)
+        .is_err());
+}
+
+#[test]
+fn synchronous_connect_timeout() {
+    struct Handler(Mutex<Vec<Option<Instant>>>);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            unreachable!()
+        }
+
+        fn connect_with_context(&self, context: &ConnectContext) -> Result<FakeConnection, Error> {
+            self.0.lock().push(context.deadline());
+            thread::sleep(Duration::from_millis(100));
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .min_idle(Some(0))
+        .synchronous(true)
+        .connect_timeout(Some(Duration::from_millis(50)))
+        .connection_timeout(Duration::from_secs(5))
+        .build(Handler(Mutex::new(vec![])))
+        .unwrap();
+
+    let start = Instant::now();
+    let err = pool.get().unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert!(err.to_string().contains("exceeding the connect timeout"));
+    assert_eq!(pool.state().connections, 0);
+
+    // the manager is told to give up by the connect timeout
+    let deadlines = pool.manager().0.lock().clone();
+    assert_eq!(deadlines.len(), 1);
+    assert!(deadlines[0].unwrap() < start + Duration::from_secs(1));
+}
+
+#[test]
+fn swap_manager() {
+    let pool = Pool::builder()
+        .max_size(2)
+        .build(NthConnectFailManager { n: Mutex::new(2) })
+        .unwrap();
+    let conn = pool.get().unwrap();
+    let old = conn.conn.as_ref().unwrap().id;
+
+    let old_manager = pool.swap_manager(NthConnectFailManager { n: Mutex::new(2) });
+    assert_eq!(*old_manager.n.lock(), 0);
+
+    // the idle connection is replaced on checkout and the checked out one on return
+    drop(conn);
+    let a = pool.get().unwrap();
+    let b = pool.get().unwrap();
+    assert!(a.conn.as_ref().unwrap().id > old);
+    assert!(b.conn.as_ref().unwrap().id > old);
+    assert_eq!(*pool.manager().n.lock(), 0);
+    assert!(!Arc::ptr_eq(&pool.manager(), &old_manager));
+
+    let new_manager = NthConnectFailManager { n: Mutex::new(7) };
+    pool.swap_manager(new_manager);
+    assert_eq!(*pool.manager().n.lock(), 7);
+}
+
+#[test]
+fn swap_manager_while_connecting() {
+    struct Handler(Arc<AtomicBool>, u32);
+
+    impl ManageConnection for Handler {
+        type Connection = u32;
+        type Error = Error;
+
+        fn connect(&self) -> Result<u32, Error> {
+            while !self.0.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            Ok(self.1)
+        }
+
+        fn is_valid(&self, _: &mut u32) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut u32) -> bool {
+            false
+        }
+    }
+
+    let release = Arc::new(AtomicBool::new(false));
+    let pool = Pool::builder()
+        .max_size(1)
+        .build_unchecked(Handler(release.clone(), 1));
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while pool.state().connecting == 0 {
+        assert!(Instant::now() < deadline);
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    // the connection being opened by the old manager must not be kept
+    pool.swap_manager(Handler(Arc::new(AtomicBool::new(true)), 2));
+    release.store(true, Ordering::SeqCst);
+    assert_eq!(*pool.get().unwrap(), 2);
+
+    // but it is still counted as opened and closed
+    let statistics = pool.statistics();
+    assert_eq!(statistics.connections_created, 2);
+    assert_eq!(statistics.connections_closed, 1);
+}
+
+#[test]
+fn drop_releases_idle_connections() {
+    #[derive(Debug)]
+    struct Customizer(Arc<AtomicUsize>);
+
+    impl CustomizeConnection<FakeConnection, Error> for Customizer {
+        fn on_release(&self, _: FakeConnection) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let released = Arc::new(AtomicUsize::new(0));
+    let pool = Pool::builder()
+        .max_size(2)
+        .connection_customizer(Box::new(Customizer(released.clone())))
+        .build(OkManager)
+        .unwrap();
+    pool.wait_until_ready(2, Duration::from_secs(5)).unwrap();
+    drop(pool);
+
+    // a helper thread may hold the last reference for a moment
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while released.load(Ordering::SeqCst) < 2 {
+        assert!(Instant::now() < deadline);
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn close_while_connecting() {
+    struct Handler(Arc<AtomicBool>, Arc<AtomicUsize>);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            while !self.0.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+
+        fn on_destroy(&self, _: &mut FakeConnection) {
+            self.1.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let release = Arc::new(AtomicBool::new(false));
+    let destroyed = Arc::new(AtomicUsize::new(0));
+    let pool = Pool::builder()
+        .max_size(1)
+        .build_unchecked(Handler(release.clone(), destroyed.clone()));
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while pool.state().connecting == 0 {
+        assert!(Instant::now() < deadline);
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    assert!(!pool.close(Duration::from_millis(50)));
+    release.store(true, Ordering::SeqCst);
+    while destroyed.load(Ordering::SeqCst) == 0 {
+        assert!(Instant::now() < deadline);
+        thread::sleep(Duration::from_millis(10));
+    }
+    let state = pool.state();
+    assert_eq!(state.connections, 0);
+    assert_eq!(state.pending_connections, 0);
+}
+
+#[test]
+fn connect_context() {
+    struct Handler(Mutex<Vec<(u32, ConnectReason, bool)>>);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            unreachable!()
+        }
+
+        fn connect_with_context(&self, context: &ConnectContext) -> Result<FakeConnection, Error> {
+            let mut contexts = self.0.lock();
+            contexts.push((
+                context.attempt(),
+                context.reason(),
+                context.deadline().is_some(),
+            ));
+            if contexts.len() == 1 {
+                Err(Error)
+            } else {
+                Ok(FakeConnection(true))
+            }
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .backoff(Box::new(FixedBackoff(Duration::from_millis(10))))
+        .connect_timeout(Some(Duration::from_secs(1)))
+        .build(Handler(Mutex::new(vec![])))
+        .unwrap();
+
+    let mut conn = pool.get().unwrap();
+    PooledConnection::invalidate(&mut conn);
+    drop(conn);
+    pool.get().unwrap();
+    pool.dedicated_connection().unwrap();
+
+    assert_eq!(
+        *pool.manager().0.lock(),
+        vec![
+            (0, ConnectReason::Initialization, true),
+            (1, ConnectReason::Initialization, true),
+            (0, ConnectReason::Replacement, true),
+            (0, ConnectReason::Dedicated, true),
+        ]
+    );
+}
+
+#[test]
+fn connection_info() {
+    let pool = Pool::builder().max_size(1).build(OkManager).unwrap();
+
+    let conn = pool.get().unwrap();
+    let info = PooledConnection::info(&conn);
+    assert_eq!(info.checkouts(), 1);
+    assert!(info.checked_out_at() >= info.created_at());
+    drop(conn);
+
+    let conn = pool.get().unwrap();
+    let info2 = PooledConnection::info(&conn);
+    assert_eq!(info2.id(), info.id());
+    assert_eq!(info2.created_at(), info.created_at());
+    assert_eq!(info2.checkouts(), 2);
+    assert_eq!(PooledConnection::id(&conn), info.id());
+    assert_eq!(PooledConnection::checkout_count(&conn), 2);
+    let age = PooledConnection::age(&conn);
+    assert!(age <= info.created_at().elapsed());
+}
+
+#[test]
+fn connection_statistics() {
+    let pool = Pool::builder().max_size(2).build(OkManager).unwrap();
+
+    let conn = pool.get().unwrap();
+    let id = PooledConnection::id(&conn);
+    thread::sleep(Duration::from_millis(20));
+    drop(conn);
+    let conn = pool.get().unwrap();
+    assert_eq!(PooledConnection::id(&conn), id);
+
+    let mut stats = pool.connection_statistics();
+    stats.sort_by_key(|stats| !stats.checked_out);
+    assert_eq!(stats.len(), 2);
+    assert_eq!(stats[0].id, id);
+    assert_eq!(stats[0].checkouts, 2);
+    assert!(stats[0].busy >= Duration::from_millis(20));
+    assert!(stats[0].last_validated_at.is_some());
+    assert!(!stats[1].checked_out);
+    assert_eq!(stats[1].checkouts, 0);
+    assert_eq!(stats[1].last_validated_at, None);
+
+    drop(conn);
+    let stats = pool.connection_statistics();
+    assert!(stats.iter().all(|stats| !stats.checked_out));
+}
+
+#[test]
+fn state_waiters() {
+    let pool = Pool::builder().max_size(1).build(OkManager).unwrap();
+    let events = pool.subscribe();
+
+    let conn = pool.get().unwrap();
+    let pool2 = pool.clone();
+    let t = thread::spawn(move || drop(pool2.get().unwrap()));
+    thread::sleep(Duration::from_millis(50));
+
+    let state = pool.state();
+    assert_eq!(state.waiters, 1);
+    assert!(state.longest_wait.unwrap() >= Duration::from_millis(50));
+
+    drop(conn);
+    t.join().unwrap();
+    let state = pool.state();
+    assert_eq!(state.waiters, 0);
+    assert_eq!(state.longest_wait, None);
+
+    let waiters = events
+        .try_iter()
+        .filter_map(|event| match event {
+            Event::Checkout(event) => Some(event.waiters()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(waiters, [0, 0]);
+}
+
+#[test]
+fn get_async() {
+    use std::future::{self, Future};
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut f = Box::pin(f);
+        loop {
+            match f.as_mut().poll(&mut cx) {
+                Poll::Ready(r) => return r,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    let pool = Pool::builder().max_size(1).build(OkManager).unwrap();
+    let conn = block_on(pool.get_async()).unwrap();
+
+    let t = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        drop(conn);
+    });
+    let conn = block_on(pool.get_async()).unwrap();
+    t.join().unwrap();
+    drop(conn);
+
+    pool.close(Duration::from_secs(1));
+    assert!(block_on(pool.get_async()).is_err());
+
+    // connections are tested on the pool's threads, not the one polling
+    struct Handler(Mutex<Option<thread::ThreadId>>);
+
+    impl AsyncManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> ManagerFuture<'_, FakeConnection, Error> {
+            let mut yielded = false;
+            Box::pin(future::poll_fn(move |cx| {
+                if yielded {
+                    return Poll::Ready(Ok(FakeConnection(true)));
+                }
+                yielded = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }))
+        }
+
+        fn is_valid<'a>(&'a self, _: &'a mut FakeConnection) -> ManagerFuture<'a, (), Error> {
+            *self.0.lock() = Some(thread::current().id());
+            Box::pin(future::ready(Ok(())))
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .fair_queueing(true)
+        .build(AsyncManager::new(Handler(Mutex::new(None))))
+        .unwrap();
+    let conn = block_on(pool.get_async()).unwrap();
+    let tested_on = pool.manager().manager().0.lock().unwrap();
+    assert_ne!(tested_on, thread::current().id());
+
+    // a waiting future takes its turn after a caller which was already waiting
+    let pool2 = pool.clone();
+    let (tx, rx) = mpsc::channel();
+    let t = thread::spawn(move || {
+        let conn = pool2.get().unwrap();
+        tx.send(()).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        drop(conn);
+    });
+    thread::sleep(Duration::from_millis(50));
+    let mut future = Box::pin(pool.get_async());
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    assert!(future
+        .as_mut()
+        .poll(&mut Context::from_waker(&waker))
+        .is_pending());
+    drop(conn);
+    assert!(future
+        .as_mut()
+        .poll(&mut Context::from_waker(&waker))
+        .is_pending());
+    rx.recv().unwrap();
+    block_on(future).unwrap();
+    t.join().unwrap();
+
+    // pending futures count as waiters and are limited by max_waiters
+    let pool = Pool::builder()
+        .max_size(1)
+        .max_waiters(Some(1))
+        .build(OkManager)
+        .unwrap();
+    let conn = pool.get().unwrap();
+    let mut first = Box::pin(pool.get_async());
+    assert!(first
+        .as_mut()
+        .poll(&mut Context::from_waker(&waker))
+        .is_pending());
+    assert_eq!(pool.state().waiters, 1);
+    let err = block_on(pool.get_async()).err().unwrap();
+    assert!(err.to_string().contains("too many callers"));
+    drop(first);
+    assert_eq!(pool.state().waiters, 0);
+    drop(conn);
+
+    // stale connections are closed on the pool's threads, not the one polling
+    struct Disconnecting(Arc<Mutex<Option<thread::ThreadId>>>);
+
+    impl ManageConnection for Disconnecting {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+
+        fn disconnect(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            *self.0.lock() = Some(thread::current().id());
+            Ok(())
+        }
+    }
+
+    let closed_on = Arc::new(Mutex::new(None));
+    let pool = Pool::builder()
+        .max_size(1)
+        .build(Disconnecting(closed_on.clone()))
+        .unwrap();
+    pool.swap_manager(Disconnecting(closed_on.clone()));
+    block_on(pool.get_async()).unwrap();
+    let closed_on = closed_on.lock().unwrap();
+    assert_ne!(closed_on, thread::current().id());
+}
+
+#[test]
+fn boxed_error() {
+    struct Handler;
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Box<dyn error::Error + Send + Sync>;
+
+        fn connect(&self) -> Result<FakeConnection, Self::Error> {
+            Err("blammo".into())
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let err = Pool::builder()
+        .connection_timeout(Duration::from_millis(100))
+        .build(Handler)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("blammo"));
+}
+
+#[test]
+fn is_valid_with_deadline() {
+    struct Handler(Mutex<Vec<Option<Instant>>>);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            unreachable!()
+        }
+
+        fn is_valid_with_deadline(
+            &self,
+            _: &mut FakeConnection,
+            deadline: Option<Instant>,
+        ) -> Result<(), Error> {
+            self.0.lock().push(deadline);
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .build(Handler(Mutex::new(vec![])))
+        .unwrap();
+
+    let start = Instant::now();
+    pool.get_timeout(Duration::from_secs(5)).unwrap();
+    pool.try_get().unwrap();
+
+    let deadlines = pool.manager().0.lock().clone();
+    assert_eq!(deadlines.len(), 2);
+    let deadline = deadlines[0].unwrap();
+    assert!(deadline >= start + Duration::from_secs(5));
+    assert!(deadline <= Instant::now() + Duration::from_secs(5));
+    assert_eq!(deadlines[1], None);
+}
+
+#[test]
+fn test_multi_host_manager() {
+    struct Handler(AtomicUsize);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let manager = MultiHostManager::new(vec![
+        Handler(AtomicUsize::new(0)),
+        Handler(AtomicUsize::new(0)),
+    ]);
+    let pool = Pool::builder().max_size(4).build(manager).unwrap();
+
+    let conns = (0..4).map(|_| pool.get().unwrap()).collect::<Vec<_>>();
+    assert!(conns.iter().all(|conn| conn.0));
+    assert_eq!(conns.iter().filter(|conn| conn.host() == 0).count(), 2);
+    for manager in pool.manager().managers() {
+        assert_eq!(manager.0.load(Ordering::SeqCst), 2);
+    }
+}
+
+#[test]
+fn test_failover_manager() {
+    struct Handler(AtomicBool);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            if self.0.load(Ordering::SeqCst) {
+                Ok(FakeConnection(true))
+            } else {
+                Err(Error)
+            }
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let manager = FailoverManager::new(vec![
+        Handler(AtomicBool::new(false)),
+        Handler(AtomicBool::new(true)),
+    ])
+    .probe_interval(Duration::from_millis(100));
+    let pool = Pool::builder().max_size(1).build(manager).unwrap();
+
+    let mut conn = pool.get().unwrap();
+    assert_eq!(conn.host(), 1);
+    pool.manager().managers()[0].0.store(true, Ordering::SeqCst);
+    PooledConnection::invalidate(&mut conn);
+    drop(conn);
+    let conn = pool.get().unwrap();
+    assert_eq!(conn.host(), 1);
+    drop(conn);
+
+    thread::sleep(Duration::from_millis(200));
+    let mut conn = pool.get().unwrap();
+    PooledConnection::invalidate(&mut conn);
+    drop(conn);
+    assert_eq!(pool.get().unwrap().host(), 0);
+    assert_eq!(pool.manager().connect_errors(0), 1);
+    assert_eq!(pool.manager().connect_errors(1), 0);
+    assert_eq!(pool.manager().validation_errors(0), 0);
+}
+
+#[test]
+fn test_credential_manager() {
+    struct Handler;
+
+    impl ManageConnection for Handler {
+        type Connection = (FakeConnection, u32);
+        type Error = Error;
+
+        fn connect(&self) -> Result<(FakeConnection, u32), Error> {
+            unreachable!()
+        }
+
+        fn is_valid(&self, _: &mut (FakeConnection, u32)) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut (FakeConnection, u32)) -> bool {
+            false
+        }
+    }
+
+    impl ConnectWithCredentials<u32> for Handler {
+        fn connect_with_credentials(
+            &self,
+            token: u32,
+            _: &ConnectContext,
+        ) -> Result<(FakeConnection, u32), Error> {
+            Ok((FakeConnection(true), token))
+        }
+    }
+
+    let token = Arc::new(AtomicUsize::new(0));
+    let token2 = token.clone();
+    let provider =
+        move || -> Result<u32, Error> { Ok(token2.fetch_add(1, Ordering::SeqCst) as u32) };
+    let pool = Pool::builder()
+        .max_size(2)
+        .build(CredentialManager::new(Handler, provider))
+        .unwrap();
+
+    let conn1 = pool.get().unwrap();
+    let conn2 = pool.get().unwrap();
+    let mut tokens = vec![conn1.1, conn2.1];
+    tokens.sort_unstable();
+    assert_eq!(tokens, [0, 1]);
+    assert_eq!(token.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_get_tagged() {
+    struct Handler(AtomicUsize);
+
+    impl ManageConnection for Handler {
+        type Connection = usize;
+        type Error = Error;
+
+        fn connect(&self) -> Result<usize, Error> {
+            Ok(self.0.fetch_add(1, Ordering::SeqCst))
+        }
+
+        fn is_valid(&self, _: &mut usize) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut usize) -> bool {
+            false
+        }
+
+        fn tags(&self, conn: &usize) -> Vec<String> {
+            let tag = if *conn & 1 == 0 { "even" } else { "odd" };
+            vec![tag.to_string()]
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(2)
+        .connection_timeout(Duration::from_millis(100))
+        .build(Handler(AtomicUsize::new(0)))
+        .unwrap();
+
+    for _ in 0..3 {
+        let conn = pool.get_tagged("odd").unwrap();
+        assert_eq!(*conn, 1);
+        assert_eq!(PooledConnection::tags(&conn), ["odd"]);
+    }
+
+    let even = pool.get_tagged("even").unwrap();
+    assert_eq!(*even, 0);
+    // the idle odd connection makes room for another even one
+    let even2 = pool.get_tagged("even").unwrap();
+    assert_eq!(*even2, 2);
+    assert!(pool.get_tagged("other").is_err());
+
+    let pool2 = pool.clone();
+    let t = thread::spawn(move || *pool2.get_tagged("even").unwrap());
+    thread::sleep(Duration::from_millis(20));
+    drop(even);
+    assert_eq!(t.join().unwrap(), 0);
+    drop(even2);
+}
+
+#[test]
+fn test_get_tagged_evicts() {
+    struct Handler(AtomicUsize);
+
+    impl ManageConnection for Handler {
+        type Connection = String;
+        type Error = Error;
+
+        fn connect(&self) -> Result<String, Error> {
+            unreachable!()
+        }
+
+        fn connect_with_context(&self, context: &ConnectContext) -> Result<String, Error> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(context.tag().unwrap_or("untagged").to_string())
+        }
+
+        fn is_valid(&self, _: &mut String) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut String) -> bool {
+            false
+        }
+
+        fn tags(&self, conn: &String) -> Vec<String> {
+            vec![conn.clone()]
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_secs(1))
+        .build(Handler(AtomicUsize::new(0)))
+        .unwrap();
+
+    let start = Instant::now();
+    assert_eq!(*pool.get_tagged("a").unwrap(), "a");
+    assert_eq!(*pool.get_tagged("b").unwrap(), "b");
+    assert_eq!(*pool.get_tagged("b").unwrap(), "b");
+    assert!(start.elapsed() < Duration::from_millis(500));
+    assert_eq!(pool.manager().0.load(Ordering::SeqCst), 3);
+    assert_eq!(pool.state().connections, 1);
+}
+
+#[test]
+fn test_read_write_manager() {
+    let manager = ReadWriteManager::new(OkManager, 3, OkManager, 1);
+    let pool = Pool::builder().max_size(4).build(manager).unwrap();
+    pool.wait_until_ready(4, Duration::from_secs(5)).unwrap();
+
+    let manager = pool.manager();
+    assert_eq!(manager.connections(ConnectionClass::Read), 3);
+    assert_eq!(manager.connections(ConnectionClass::Write), 1);
+
+    let write = pool.get_write().unwrap();
+    assert_eq!(write.class(), ConnectionClass::Write);
+    let reads = (0..3).map(|_| pool.get_read().unwrap()).collect::<Vec<_>>();
+    assert!(reads
+        .iter()
+        .all(|conn| conn.class() == ConnectionClass::Read));
+    assert!(pool.get_timeout(Duration::from_millis(10)).is_err());
+}
+
+impl From<ClassFull> for Error {
+    fn from(_: ClassFull) -> Error {
+        Error
+    }
+}
+
+#[test]
+fn test_read_write_manager_concurrent() {
+    let manager = ReadWriteManager::new(
+        LatencyManager::new(OkManager, Duration::from_millis(100)),
+        2,
+        LatencyManager::new(OkManager, Duration::from_millis(100)),
+        1,
+    );
+    let manager = Arc::new(manager);
+
+    // every fill starts before any finishes, but each class stays within its size
+    let threads = (0..4)
+        .map(|_| {
+            let manager = manager.clone();
+            thread::spawn(move || manager.connect().ok())
+        })
+        .collect::<Vec<_>>();
+    let conns = threads
+        .into_iter()
+        .filter_map(|t| t.join().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(conns.len(), 3);
+    assert_eq!(manager.connections(ConnectionClass::Read), 2);
+    assert_eq!(manager.connections(ConnectionClass::Write), 1);
+
+    drop(conns);
+    assert_eq!(manager.connections(ConnectionClass::Read), 0);
+    assert_eq!(manager.connections(ConnectionClass::Write), 0);
+}
+
+#[test]
+fn test_read_write_manager_get_write_when_full() {
+    let manager = ReadWriteManager::new(OkManager, 2, OkManager, 1);
+    let pool = Pool::builder()
+        .max_size(2)
+        .min_idle(Some(0))
+        .build(manager)
+        .unwrap();
+
+    let reads = (0..2).map(|_| pool.get_read().unwrap()).collect::<Vec<_>>();
+    drop(reads);
+    assert_eq!(pool.manager().connections(ConnectionClass::Read), 2);
+
+    // an idle read connection makes room for the write connection
+    let start = Instant::now();
+    let write = pool.get_write().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(write.class(), ConnectionClass::Write);
+    assert_eq!(pool.manager().connections(ConnectionClass::Read), 1);
+    assert_eq!(pool.manager().connections(ConnectionClass::Write), 1);
+}
+
+#[test]
+fn test_expires_at() {
+    struct Handler(AtomicUsize);
+
+    impl ManageConnection for Handler {
+        type Connection = usize;
+        type Error = Error;
+
+        fn connect(&self) -> Result<usize, Error> {
+            Ok(self.0.fetch_add(1, Ordering::SeqCst))
+        }
+
+        fn is_valid(&self, _: &mut usize) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut usize) -> bool {
+            false
+        }
+
+        fn expires_at(&self, conn: &usize) -> Option<Instant> {
+            match *conn {
+                0 => Some(Instant::now() + Duration::from_millis(100)),
+                1 => Some(Instant::now() + Duration::from_secs(1)),
+                _ => None,
+            }
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .build(Handler(AtomicUsize::new(0)))
+        .unwrap();
+
+    assert_eq!(*pool.get().unwrap(), 0);
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(*pool.get().unwrap(), 1);
+    assert_eq!(*pool.get().unwrap(), 1);
+
+    // connections are retired a little before they expire
+    thread::sleep(Duration::from_millis(950));
+    assert_eq!(*pool.get().unwrap(), 2);
+}
+
+#[test]
+fn test_discard_on_return() {
+    static CHECKED_IN: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug)]
+    struct Customizer;
+
+    impl CustomizeConnection<FakeConnection, Error> for Customizer {
+        fn discard_on_return(&self, conn: &mut FakeConnection) -> bool {
+            !conn.0
+        }
+
+        fn on_check_in(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            CHECKED_IN.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_customizer(Box::new(Customizer))
+        .build(OkManager)
+        .unwrap();
+
+    pool.get().unwrap().0 = false;
+    assert_eq!(pool.state().idle_connections, 0);
+    assert_eq!(CHECKED_IN.load(Ordering::SeqCst), 0);
+
+    assert!(pool.get().unwrap().0);
+    assert_eq!(pool.state().idle_connections, 1);
+    assert_eq!(CHECKED_IN.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_manager_middleware() {
+    let manager = CountingManager::new(LatencyManager::new(OkManager, Duration::from_millis(10)));
+    let start = Instant::now();
+    let pool = Pool::builder().max_size(2).build(manager).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(10));
+    pool.wait_until_ready(2, Duration::from_secs(5)).unwrap();
+
+    drop(pool.get().unwrap());
+    let manager = pool.manager();
+    assert_eq!(manager.connects(), 2);
+    assert_eq!(manager.connect_errors(), 0);
+    // one check for each connection by wait_until_ready, and one on checkout
+    assert_eq!(manager.validations(), 3);
+    assert_eq!(manager.validation_errors(), 0);
+    assert_eq!(manager.broken(), 0);
+}
+
+#[test]
+fn test_boxed_manager() {
+    trait Conn: Send {
+        fn name(&self) -> &'static str;
+    }
+
+    impl Conn for FakeConnection {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+    }
+
+    struct Handler;
+
+    impl ManageConnection for Handler {
+        type Connection = Box<dyn Conn>;
+        type Error = Error;
+
+        fn connect(&self) -> Result<Box<dyn Conn>, Error> {
+            Ok(Box::new(FakeConnection(true)))
+        }
+
+        fn is_valid(&self, _: &mut Box<dyn Conn>) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut Box<dyn Conn>) -> bool {
+            false
+        }
+    }
+
+    type DynManager = Box<dyn ManageConnection<Connection = Box<dyn Conn>, Error = Error>>;
+
+    let manager: DynManager = Box::new(Handler);
+    let pool: Pool<DynManager> = Pool::builder().max_size(1).build(manager).unwrap();
+    assert_eq!(pool.get().unwrap().name(), "fake");
+}
+
+#[test]
+fn test_validated_manager() {
+    let manager = ValidatedManager::new(
+        OkManager,
+        |conn: &mut FakeConnection| {
+            if conn.0 {
+                Ok(())
+            } else {
+                Err(Error)
+            }
+        },
+    );
+    let pool = Pool::builder().max_size(1).build(manager).unwrap();
+
+    pool.get().unwrap().0 = false;
+    assert!(pool.get().unwrap().0);
+}
+
+#[test]
+fn test_max_concurrent_connects() {
+    struct Handler {
+        connecting: AtomicUsize,
+        max: AtomicUsize,
+    }
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            let connecting = self.connecting.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max.fetch_max(connecting, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            self.connecting.fetch_sub(1, Ordering::SeqCst);
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(6)
+        .max_concurrent_connects(Some(2))
+        .thread_pool(Arc::new(scheduled_thread_pool::ScheduledThreadPool::new(6)))
+        .build(Handler {
+            connecting: AtomicUsize::new(0),
+            max: AtomicUsize::new(0),
+        })
+        .unwrap();
+    pool.wait_until_ready(6, Duration::from_secs(5)).unwrap();
+    assert_eq!(pool.manager().max.load(Ordering::SeqCst), 2);
+
+    struct SlowHandler;
+
+    impl ManageConnection for SlowHandler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            thread::sleep(Duration::from_millis(500));
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    // attempts waiting for a slot leave the pool's threads free for other jobs
+    let thread_pool = Arc::new(scheduled_thread_pool::ScheduledThreadPool::new(2));
+    let _pool = Pool::builder()
+        .max_size(4)
+        .max_concurrent_connects(Some(1))
+        .thread_pool(thread_pool.clone())
+        .build_unchecked(SlowHandler);
+    thread::sleep(Duration::from_millis(50));
+    let (tx, rx) = mpsc::channel();
+    thread_pool.execute(move || tx.send(()).unwrap());
+    rx.recv_timeout(Duration::from_millis(200)).unwrap();
+
+    // a synchronous caller waits for a slot no longer than its own timeout
+    let pool = Pool::builder()
+        .max_size(2)
+        .min_idle(Some(0))
+        .max_concurrent_connects(Some(1))
+        .synchronous(true)
+        .build(SlowHandler)
+        .unwrap();
+    let pool2 = pool.clone();
+    let t = thread::spawn(move || drop(pool2.get().unwrap()));
+    thread::sleep(Duration::from_millis(50));
+    let start = Instant::now();
+    let err = pool.get_timeout(Duration::from_millis(50)).err().unwrap();
+    assert!(start.elapsed() < Duration::from_millis(300));
+    assert!(err
+        .to_string()
+        .contains("waiting for another connection attempt"));
+    t.join().unwrap();
+
+    assert!(Pool::builder()
+        .max_concurrent_connects(Some(0))
+        .build(OkManager)
+        .is_err());
+}
+
+#[test]
+fn test_hedge_delay() {
+    static RELEASED: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug)]
+    struct Customizer;
+
+    impl CustomizeConnection<usize, Error> for Customizer {
+        fn on_release(&self, conn: usize) {
+            // the pool's own connection is released when the pool is dropped
+            if conn == 0 {
+                RELEASED.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    struct Handler(AtomicUsize);
+
+    impl ManageConnection for Handler {
+        type Connection = usize;
+        type Error = Error;
+
+        fn connect(&self) -> Result<usize, Error> {
+            let n = self.0.fetch_add(1, Ordering::SeqCst);
+            // the first attempt stalls
+            if n == 0 {
+                thread::sleep(Duration::from_millis(500));
+            }
+            Ok(n)
+        }
+
+        fn is_valid(&self, _: &mut usize) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut usize) -> bool {
+            false
+        }
+    }
+
+    let start = Instant::now();
+    let pool = Pool::builder()
+        .max_size(1)
+        .hedge_delay(Some(Duration::from_millis(50)))
+        .connection_customizer(Box::new(Customizer))
+        .build(Handler(AtomicUsize::new(0)))
+        .unwrap();
+    assert!(start.elapsed() < Duration::from_millis(400));
+    assert_eq!(*pool.get().unwrap(), 1);
+
+    // the stalled connection is closed once it's established
+    thread::sleep(Duration::from_millis(600));
+    assert_eq!(RELEASED.load(Ordering::SeqCst), 1);
+    assert_eq!(pool.manager().0.load(Ordering::SeqCst), 2);
+    assert_eq!(pool.state().connections, 1);
+}
+
+#[test]
+fn test_on_destroy() {
+    struct Handler(AtomicUsize);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, conn: &mut FakeConnection) -> bool {
+            !conn.0
+        }
+
+        fn on_destroy(&self, _: &mut FakeConnection) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(2)
+        .build(Handler(AtomicUsize::new(0)))
+        .unwrap();
+    pool.wait_until_ready(2, Duration::from_secs(5)).unwrap();
+    let manager = pool.manager();
+
+    pool.get().unwrap().0 = false;
+    assert_eq!(manager.0.load(Ordering::SeqCst), 1);
+
+    let conn = pool.get().unwrap();
+    PooledConnection::detach(conn);
+    assert_eq!(manager.0.load(Ordering::SeqCst), 1);
+
+    pool.wait_until_ready(2, Duration::from_secs(5)).unwrap();
+    // let the helper thread which opened the replacement release the pool
+    thread::sleep(Duration::from_millis(50));
+    drop(pool);
+    assert_eq!(manager.0.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn test_deep_check() {
+    struct Handler {
+        pings: AtomicUsize,
+        deep_checks: AtomicUsize,
+        destroyed: Arc<AtomicUsize>,
+        entered: Mutex<mpsc::Sender<()>>,
+        release: Mutex<Receiver<()>>,
+    }
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            self.pings.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn deep_check(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            self.deep_checks.fetch_add(1, Ordering::SeqCst);
+            self.entered.lock().send(()).unwrap();
+            self.release.lock().recv().unwrap();
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+
+        fn on_destroy(&self, _: &mut FakeConnection) {
+            self.destroyed.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn handler(destroyed: &Arc<AtomicUsize>) -> (Handler, Receiver<()>, mpsc::Sender<()>) {
+        let (entered_tx, entered_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel();
+        let handler = Handler {
+            pings: AtomicUsize::new(0),
+            deep_checks: AtomicUsize::new(0),
+            destroyed: destroyed.clone(),
+            entered: Mutex::new(entered_tx),
+            release: Mutex::new(release_rx),
+        };
+        (handler, entered_rx, release_tx)
+    }
+
+    let destroyed = Arc::new(AtomicUsize::new(0));
+    let (manager, entered, release) = handler(&destroyed);
+    let pool = Pool::builder().max_size(2).build(manager).unwrap();
+    pool.wait_until_ready(2, Duration::from_secs(1)).unwrap();
+
+    let shared = Arc::downgrade(&pool.0);
+    let t = thread::spawn(move || crate::validate_idle_connections(&shared));
+
+    // the other connection stays available while one is being checked
+    entered.recv().unwrap();
+    assert_eq!(pool.state().idle_connections, 1);
+    drop(pool.try_get().unwrap());
+    release.send(()).unwrap();
+
+    // a connection from a swapped out manager isn't returned to the pool
+    entered.recv().unwrap();
+    let manager = pool.swap_manager(handler(&destroyed).0);
+    release.send(()).unwrap();
+    t.join().unwrap();
+
+    assert_eq!(manager.deep_checks.load(Ordering::SeqCst), 2);
+    // wait_until_ready checked both connections before the checkout
+    assert_eq!(manager.pings.load(Ordering::SeqCst), 3);
+    assert_eq!(destroyed.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_retry_after() {
+    struct Handler(AtomicUsize);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            if self.0.fetch_add(1, Ordering::SeqCst) == 0 {
+                Err(Error)
+            } else {
+                Ok(FakeConnection(true))
+            }
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+
+        fn retry_after(&self, _: &Error) -> Option<Duration> {
+            Some(Duration::from_millis(10))
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .min_idle(Some(0))
+        .backoff(Box::new(FixedBackoff(Duration::from_secs(30))))
+        .build(Handler(AtomicUsize::new(0)))
+        .unwrap();
+
+    pool.get_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(pool.manager().0.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_background_disconnect() {
+    struct Handler(Mutex<Vec<Option<String>>>);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, conn: &mut FakeConnection) -> bool {
+            !conn.0
+        }
+
+        fn disconnect(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            let name = thread::current().name().map(|s| s.to_string());
+            self.0.lock().push(name);
+            Err(Error)
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .build(Handler(Mutex::new(vec![])))
+        .unwrap();
+    pool.get().unwrap().0 = false;
+    let threads = pool.manager().0.lock().clone();
+    assert_eq!(threads, [thread::current().name().map(|s| s.to_string())]);
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .background_disconnect(true)
+        .build(Handler(Mutex::new(vec![])))
+        .unwrap();
+    pool.get().unwrap().0 = false;
+    thread::sleep(Duration::from_millis(100));
+    let threads = pool.manager().0.lock().clone();
+    assert_eq!(threads.len(), 1);
+    assert!(threads[0].as_ref().unwrap().starts_with("r2d2-worker-"));
+}
+
+#[test]
+fn test_pinned_pool() {
+    use std::rc::Rc;
+
+    let pool = PinnedPool::new(2, || -> Result<Rc<thread::ThreadId>, Error> {
+        Ok(Rc::new(thread::current().id()))
+    });
+    assert_eq!(pool.size(), 2);
+
+    let conn1 = pool.get().unwrap();
+    let conn2 = pool.get().unwrap();
+    assert!(pool.get_timeout(Duration::from_millis(10)).is_err());
+
+    // each connection stays on the thread which opened it
+    let id1 = conn1.run(|conn| **conn == thread::current().id()).unwrap();
+    assert!(id1);
+    let name = conn2
+        .run(|_| thread::current().name().map(|s| s.to_string()))
+        .unwrap();
+    assert!(name.unwrap().starts_with("r2d2-pinned-"));
+
+    let pool2 = pool.clone();
+    let t = thread::spawn(move || pool2.get().unwrap().run(|_| 5).unwrap());
+    drop(conn1);
+    assert_eq!(t.join().unwrap(), 5);
+
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| conn2.run(|_| panic!("boom"))));
+    assert!(result.is_err());
+    assert!(conn2.run(|_| ()).is_ok());
+}
+
+#[test]
+fn test_validation_failure_event() {
+    struct Handler;
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, conn: &mut FakeConnection) -> Result<(), Error> {
+            if conn.0 {
+                Ok(())
+            } else {
+                Err(Error)
+            }
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let pool = Pool::builder().max_size(1).build(Handler).unwrap();
+    let events = pool.subscribe();
+
+    let mut conn = pool.get().unwrap();
+    let id = PooledConnection::info(&conn).id();
+    conn.0 = false;
+    drop(conn);
+    pool.get().unwrap();
+
+    let event = events
+        .try_iter()
+        .find_map(|event| match event {
+            Event::ValidationFailure(event) => Some(event),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(event.connection_id(), id);
+    assert_eq!(event.error(), "blammo");
+}
+
+#[test]
+fn test_leak_detection() {
+    let pool = Pool::builder()
+        .max_size(2)
+        .maintenance_rate(Duration::from_millis(10))
+        .leak_detection_threshold(Some(Duration::from_millis(50)))
+        .build(OkManager)
+        .unwrap();
+    let events = pool.subscribe();
+
+    let leaked = pool.get().unwrap();
+    drop(pool.get().unwrap());
+    thread::sleep(Duration::from_millis(150));
+
+    let leaks = events
+        .try_iter()
+        .filter_map(|event| match event {
+            Event::Leak(event) => Some(event),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(leaks.len(), 1);
+    assert_eq!(
+        leaks[0].connection_id(),
+        PooledConnection::info(&leaked).id()
+    );
+    assert!(leaks[0].duration() >= Duration::from_millis(50));
+}
+
+#[test]
+fn test_capture_backtraces() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_millis(50))
+        .capture_backtraces(true)
+        .build(OkManager)
+        .unwrap();
+    let events = pool.subscribe();
+
+    let conn = pool.get().unwrap();
+    pool.get().unwrap_err();
+
+    let checked_out = pool.checked_out();
+    assert_eq!(checked_out.len(), 1);
+    assert_eq!(checked_out[0].connection_id(), PooledConnection::id(&conn));
+    assert!(checked_out[0].backtrace().is_some());
+
+    let event = events
+        .try_iter()
+        .find_map(|event| match event {
+            Event::Timeout(event) => Some(event),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(event.checked_out().len(), 1);
+    assert!(event.checked_out()[0].backtrace().is_some());
+
+    drop(conn);
+    assert!(pool.checked_out().is_empty());
+}
+
+#[test]
+fn test_slow_checkout() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .slow_checkout_threshold(Some(Duration::from_millis(50)))
+        .build(OkManager)
+        .unwrap();
+    let events = pool.subscribe();
+
+    let conn = pool.get().unwrap();
+    let id = PooledConnection::info(&conn).id();
+    let t = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        drop(conn);
+    });
+    let conn = pool.get().unwrap();
+    t.join().unwrap();
+
+    let slow = events
+        .try_iter()
+        .filter_map(|event| match event {
+            Event::SlowCheckout(event) => Some(event),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(slow.len(), 1);
+    assert_eq!(slow[0].connection_id(), id);
+    assert!(slow[0].duration() >= Duration::from_millis(50));
+    assert_eq!(slow[0].state().in_use_connections, 1);
+    drop(conn);
+}
+
+#[test]
+fn test_exhaustion_events() {
+    let pool = Pool::builder().max_size(1).build(OkManager).unwrap();
+    let events = pool.subscribe();
+
+    let conn = pool.get().unwrap();
+    let t = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        drop(conn);
+    });
+    let conn = pool.get().unwrap();
+    t.join().unwrap();
+    drop(conn);
+
+    let events = events
+        .try_iter()
+        .filter(|event| matches!(event, Event::Exhausted(_) | Event::Recovered(_)))
+        .collect::<Vec<_>>();
+    assert_eq!(events.len(), 2);
+    match &events[0] {
+        Event::Exhausted(event) => assert_eq!(event.state().in_use_connections, 1),
+        event => panic!("unexpected event {:?}", event),
+    }
+    match &events[1] {
+        Event::Recovered(event) => assert!(event.duration() >= Duration::from_millis(100)),
+        event => panic!("unexpected event {:?}", event),
+    }
+}
+
+#[test]
+fn test_statistics() {
+    let pool = Pool::builder()
+        .max_size(2)
+        .max_uses(Some(2))
+        .connection_timeout(Duration::from_millis(100))
+        .build(OkManager)
+        .unwrap();
+
+    let mut conn1 = pool.get().unwrap();
+    let conn2 = pool.get().unwrap();
+    pool.get().unwrap_err();
+    PooledConnection::invalidate(&mut conn1);
+    drop(conn1);
+    drop(conn2);
+
+    let stats = pool.statistics();
+    assert_eq!(stats.checkouts, 2);
+    assert_eq!(stats.checkout_timeouts, 1);
+    assert_eq!(stats.connections_closed, 1);
+    assert_eq!(stats.closed_broken, 1);
+    assert_eq!(stats.closed_expired, 0);
+
+    // the remaining original connection reaches max_uses on this checkout
+    let mut conns = vec![pool.get().unwrap(), pool.get().unwrap()];
+    conns.clear();
+
+    let stats = pool.statistics();
+    assert_eq!(stats.checkouts, 4);
+    assert_eq!(stats.closed_expired, 1);
+    assert_eq!(stats.connections_closed, 2);
+    assert!(stats.connections_created >= 3);
+    assert_eq!(stats.connect_failures, 0);
+    assert_eq!(stats.validation_failures, 0);
+}
+
+#[test]
+fn test_watermarks() {
+    let pool = Pool::builder()
+        .max_size(2)
+        .connection_timeout(Duration::from_millis(100))
+        .build(OkManager)
+        .unwrap();
+
+    let conn1 = pool.get().unwrap();
+    let conn2 = pool.get().unwrap();
+    pool.get().unwrap_err();
+    drop(conn1);
+
+    let stats = pool.statistics();
+    assert_eq!(stats.peak_in_use, 2);
+    assert_eq!(stats.peak_waiters, 1);
+    assert!(stats.peak_wait >= Duration::from_millis(100));
+
+    pool.reset_watermarks();
+    let stats = pool.statistics();
+    assert_eq!(stats.peak_in_use, 1);
+    assert_eq!(stats.peak_waiters, 0);
+    assert_eq!(stats.peak_wait, Duration::from_secs(0));
+    assert_eq!(stats.checkout_timeouts, 1);
+    drop(conn2);
+}
+
+#[test]
+fn test_connect_failure_statistics() {
+    let manager = NthConnectFailManager { n: Mutex::new(1) };
+    let pool = Pool::builder()
+        .max_size(2)
+        .min_idle(Some(1))
+        .connection_timeout(Duration::from_millis(100))
+        .build(manager)
+        .unwrap();
+
+    let _conn = pool.get().unwrap();
+    pool.get().unwrap_err();
+
+    let stats = pool.statistics();
+    assert!(stats.connect_failures >= 1);
+    assert_eq!(stats.checkout_timeouts, 1);
+    assert_eq!(stats.validation_failures, 0);
+}
+
+#[test]
+fn state_connecting() {
+    struct Handler(Arc<AtomicBool>);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            while !self.0.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let release = Arc::new(AtomicBool::new(false));
+    let pool = Pool::builder()
+        .max_size(2)
+        .build_unchecked(Handler(release.clone()));
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while pool.state().connecting < 2 {
+        assert!(Instant::now() < deadline, "{:?}", pool.state());
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(pool.state().connections, 0);
+
+    release.store(true, Ordering::SeqCst);
+    while pool.state().connections < 2 {
+        assert!(Instant::now() < deadline, "{:?}", pool.state());
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(pool.state().connecting, 0);
+}
+
+#[test]
+fn test_health() {
+    struct Handler(AtomicBool, AtomicBool);
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            if self.0.load(Ordering::SeqCst) {
+                Ok(FakeConnection(true))
+            } else {
+                Err(Error)
+            }
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            if self.0.load(Ordering::SeqCst) && !self.1.swap(false, Ordering::SeqCst) {
+                Ok(())
+            } else {
+                Err(Error)
+            }
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_millis(100))
+        .backoff(Box::new(FixedBackoff(Duration::from_millis(10))))
+        .build(Handler(AtomicBool::new(true), AtomicBool::new(false)))
+        .unwrap();
+    let events = pool.subscribe();
+    assert_eq!(pool.health(), Health::Healthy);
+
+    // an occasional failure doesn't make the pool unhealthy
+    pool.manager().1.store(true, Ordering::SeqCst);
+    drop(pool.get().unwrap());
+    assert_eq!(pool.health(), Health::Healthy);
+
+    pool.manager().0.store(false, Ordering::SeqCst);
+    pool.get().unwrap_err();
+    assert_eq!(pool.health(), Health::Unavailable);
+
+    // it takes several successes to recover
+    pool.manager().0.store(true, Ordering::SeqCst);
+    drop(pool.get().unwrap());
+    assert_eq!(pool.health(), Health::Degraded);
+    for _ in 0..8 {
+        drop(pool.get().unwrap());
+    }
+    assert_eq!(pool.health(), Health::Healthy);
+
+    let changes = events
+        .try_iter()
+        .filter_map(|event| match event {
+            Event::HealthChange(event) => Some((event.from(), event.to())),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        changes,
+        vec![
+            (Health::Healthy, Health::Unavailable),
+            (Health::Unavailable, Health::Degraded),
+            (Health::Degraded, Health::Healthy),
+        ]
+    );
+}
+
+#[test]
+fn test_last_error() {
+    let manager = NthConnectFailManager { n: Mutex::new(1) };
+    let pool = Pool::builder()
+        .max_size(2)
+        .min_idle(Some(1))
+        .connection_timeout(Duration::from_millis(100))
+        .build(manager)
+        .unwrap();
+    assert!(pool.last_error().is_none());
+
+    let conn = pool.get().unwrap();
+    pool.get().unwrap_err();
+    let error = pool.last_error().unwrap();
+    assert_eq!(error.message(), "blammo");
+    assert!(error.occurred_at() <= Instant::now());
+
+    // the error outlives the timeout which reported it
+    drop(conn);
+    drop(pool.get().unwrap());
+    assert!(pool.last_error().is_some());
+}
+
+#[test]
+fn test_connect_and_validation_durations() {
+    struct Handler;
+
+    impl ManageConnection for Handler {
+        type Connection = FakeConnection;
+        type Error = Error;
+
+        fn connect(&self) -> Result<FakeConnection, Error> {
+            thread::sleep(Duration::from_millis(20));
+            Ok(FakeConnection(true))
+        }
+
+        fn is_valid(&self, _: &mut FakeConnection) -> Result<(), Error> {
+            thread::sleep(Duration::from_millis(10));
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut FakeConnection) -> bool {
+            false
+        }
+    }
+
+    #[derive(Debug)]
+    struct TestEventHandler(Arc<Mutex<Vec<Event>>>);
+
+    impl HandleEvent for TestEventHandler {
+        fn handle_acquire(&self, event: AcquireEvent) {
+            self.0.lock().push(Event::Acquire(event));
+        }
+
+        fn handle_checkout(&self, event: CheckoutEvent) {
+            self.0.lock().push(Event::Checkout(event));
+        }
+    }
+
+    let events = Arc::new(Mutex::new(vec![]));
+    let pool = Pool::builder()
+        .max_size(1)
+        .event_handler(Box::new(TestEventHandler(events.clone())))
+        .build(Handler)
+        .unwrap();
+    drop(pool.get().unwrap());
+
+    match &events.lock()[..] {
+        [Event::Acquire(acquire), Event::Checkout(checkout)] => {
+            assert!(acquire.connect_duration() >= Duration::from_millis(20));
+            assert!(checkout.validation_duration().unwrap() >= Duration::from_millis(10));
+        }
+        events => panic!("unexpected events: {:?}", events),
+    }
+
+    let stats = pool.statistics();
+    assert_eq!(stats.connect_duration.count(), 1);
+    assert!(stats.connect_duration.max() >= Duration::from_millis(20));
+    assert_eq!(stats.validation_duration.count(), 1);
+    assert!(stats.validation_duration.max() >= Duration::from_millis(10));
+}
+
+#[test]
+fn test_stats_sink() {
+    #[derive(Debug)]
+    struct Sink(Arc<Mutex<Vec<StatsSnapshot>>>);
+
+    impl StatsSink for Sink {
+        fn report(&self, snapshot: &StatsSnapshot) {
+            self.0.lock().push(snapshot.clone());
+        }
+    }
+
+    let snapshots = Arc::new(Mutex::new(vec![]));
+    let pool = Pool::builder()
+        .max_size(2)
+        .stats_sink(Box::new(Sink(snapshots.clone())))
+        .stats_interval(Duration::from_millis(20))
+        .build(OkManager)
+        .unwrap();
+    let _conn = pool.get().unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    let snapshots = snapshots.lock();
+    assert!(snapshots.len() >= 2);
+    let last = snapshots.last().unwrap();
+    assert_eq!(last.state.in_use_connections, 1);
+    assert_eq!(last.statistics.checkouts, 1);
+    assert_eq!(last.connections.len(), 2);
+    assert!(snapshots[0].taken_at < last.taken_at);
+}
+
+#[test]
+fn test_statsd_reporter() {
+    let server = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    server
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+
+    let pool = Pool::builder().max_size(2).build(OkManager).unwrap();
+    let _conn = pool.get().unwrap();
+    let reporter = StatsdReporter::new(server.local_addr().unwrap())
+        .unwrap()
+        .prefix("app.db")
+        .tag("env", "test")
+        .tag("region", "local")
+        .interval(Duration::from_millis(50));
+    let zero = StatsdReporter::new(server.local_addr().unwrap())
+        .unwrap()
+        .interval(Duration::from_secs(0));
+    assert!(pool.report_statsd(zero).is_err());
+    pool.report_statsd(reporter).unwrap();
+
+    let mut buf = [0; 1500];
+    let n = server.recv(&mut buf).unwrap();
+    let packet = std::str::from_utf8(&buf[..n]).unwrap();
+    let lines = packet.lines().collect::<Vec<_>>();
+    assert!(lines.contains(&"app.db.connections:2|g|#env:test,region:local"));
+    assert!(lines.contains(&"app.db.in_use_connections:1|g|#env:test,region:local"));
+    assert!(lines.contains(&"app.db.connections_created:2|c|#env:test,region:local"));
+    assert!(lines.contains(&"app.db.checkouts:1|c|#env:test,region:local"));
+
+    // counters are sent as deltas
+    let n = server.recv(&mut buf).unwrap();
+    let packet = std::str::from_utf8(&buf[..n]).unwrap();
+    assert!(packet
+        .lines()
+        .any(|line| line == "app.db.checkouts:0|c|#env:test,region:local"));
+}
+
+#[test]
+fn test_statsd_reporter_packets() {
+    let server = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    server
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+
+    let pool = Pool::builder().max_size(2).build(OkManager).unwrap();
+    let prefix = "app".repeat(40);
+    let reporter = StatsdReporter::new(server.local_addr().unwrap())
+        .unwrap()
+        .prefix(&prefix);
+    reporter.report(&pool.stats_snapshot());
+
+    // a long prefix spreads the metrics over several bounded packets
+    let mut buf = [0; 2048];
+    let mut packets = 0;
+    let mut lines = vec![];
+    while !lines
+        .iter()
+        .any(|line: &String| line.contains(".checkouts:"))
+    {
+        let n = server.recv(&mut buf).unwrap();
+        assert!(n <= 1432);
+        packets += 1;
+        let packet = std::str::from_utf8(&buf[..n]).unwrap();
+        lines.extend(packet.lines().map(|line| line.to_owned()));
+    }
+    assert!(packets > 1);
+    assert!(lines.iter().all(|line| line.starts_with(&prefix)));
+    assert!(lines.contains(&format!("{}.connections:2|g", prefix)));
+}
+
+#[test]
+fn test_statsd_reporter_shared() {
+    let server = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    server
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+
+    let pool1 = Pool::builder().max_size(1).build(OkManager).unwrap();
+    let pool2 = Pool::builder().max_size(1).build(OkManager).unwrap();
+    for _ in 0..3 {
+        drop(pool1.get().unwrap());
+    }
+    let reporter = StatsdReporter::new(server.local_addr().unwrap()).unwrap();
+
+    // one reporter keeps separate counts for several unnamed pools
+    let mut buf = [0; 1500];
+    let mut report = |pool: &Pool<OkManager>, expected: &str| {
+        reporter.report(&pool.stats_snapshot());
+        let n = server.recv(&mut buf).unwrap();
+        let packet = std::str::from_utf8(&buf[..n]).unwrap();
+        assert!(packet.lines().any(|line| line == expected), "{}", packet);
+    };
+    report(&pool1, "r2d2.checkouts:3|c");
+    report(&pool2, "r2d2.checkouts:0|c");
+    drop(pool1.get().unwrap());
+    report(&pool1, "r2d2.checkouts:1|c");
+    drop(pool2.get().unwrap());
+    report(&pool2, "r2d2.checkouts:1|c");
+}
+
+#[test]
+fn test_checkout_wait_histogram() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_millis(200))
+        .build(OkManager)
+        .unwrap();
+    assert_eq!(pool.statistics().checkout_wait.count(), 0);
+
+    let conn = pool.get().unwrap();
+    let (tx, rx) = mpsc::channel();
+    let pool2 = pool.clone();
+    let t = thread::spawn(move || {
+        tx.send(()).unwrap();
+        drop(pool2.get().unwrap());
+    });
+    rx.recv().unwrap();
+    thread::sleep(Duration::from_millis(50));
+    drop(conn);
+    t.join().unwrap();
+    drop(pool.get().unwrap());
+
+    let wait = pool.statistics().checkout_wait;
+    assert_eq!(wait.count(), 3);
+    assert!(wait.max() >= Duration::from_millis(50));
+    assert!(wait.quantile(1.) >= Duration::from_millis(50));
+    assert!(wait.quantile(0.) < Duration::from_millis(50));
+    assert_eq!(wait.buckets().map(|(_, n)| n).sum::<u64>(), 3);
+}
+
+#[test]
+#[cfg(feature = "log")]
+fn test_logging_manager() {
+    let manager = LoggingManager::new(CountingManager::new(OkManager));
+    let pool = Pool::builder().max_size(1).build(manager).unwrap();
+    drop(pool.get().unwrap());
+    assert_eq!(pool.manager().manager().connects(), 1);
+    assert_eq!(pool.manager().manager().validations(), 1);
+}